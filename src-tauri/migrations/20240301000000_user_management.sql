@@ -0,0 +1,5 @@
+-- =====================================
+-- USER MANAGEMENT
+-- =====================================
+-- Disabled accounts keep their row (and history) but can no longer log in
+ALTER TABLE users ADD COLUMN status TEXT NOT NULL DEFAULT 'Aktif';
@@ -0,0 +1,24 @@
+-- =====================================
+-- LOGIN PROTECTION
+-- =====================================
+-- Failed attempts are tracked per username, including names that do not exist
+CREATE TABLE login_attempts (
+    username TEXT PRIMARY KEY,
+    failed_count INTEGER NOT NULL DEFAULT 0,
+    last_failed_at DATETIME,
+    locked_until DATETIME
+);
+
+CREATE TABLE login_events (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    username TEXT NOT NULL,
+    user_id INTEGER,
+    success INTEGER NOT NULL,
+    reason TEXT,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX idx_login_events_created_at ON login_events (created_at);
+
+INSERT INTO settings (key, value) VALUES ('login_max_attempts', '5');
+INSERT INTO settings (key, value) VALUES ('login_lockout_minutes', '15');
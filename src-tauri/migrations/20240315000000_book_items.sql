@@ -0,0 +1,60 @@
+-- =====================================
+-- BOOK ITEMS TABLE (one row per physical copy)
+-- =====================================
+-- status: 'Tersedia', 'Dipinjam', 'Hilang', 'Ditarik'
+CREATE TABLE book_items (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    book_id INTEGER NOT NULL,
+    barcode TEXT UNIQUE NOT NULL,
+    acquisition_date DATE,
+    price INTEGER,
+    condition TEXT NOT NULL DEFAULT 'Bagus',
+    status TEXT NOT NULL DEFAULT 'Tersedia',
+    notes TEXT,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (book_id) REFERENCES books(id)
+);
+
+CREATE INDEX idx_book_items_book ON book_items (book_id, status);
+
+ALTER TABLE loans ADD COLUMN item_id INTEGER REFERENCES book_items(id);
+
+-- One item per existing copy, barcoded as <book barcode>-NN
+WITH RECURSIVE copies(book_id, n, total) AS (
+    SELECT id, 1, total_copy FROM books WHERE total_copy > 0
+    UNION ALL
+    SELECT book_id, n + 1, total FROM copies WHERE n < total
+)
+INSERT INTO book_items (book_id, barcode, acquisition_date, status)
+SELECT c.book_id,
+       COALESCE(b.barcode, 'B-' || b.id) || '-' || printf('%02d', c.n),
+       date(b.created_at),
+       'Tersedia'
+FROM copies c
+JOIN books b ON b.id = c.book_id
+ORDER BY c.book_id, c.n;
+
+-- Hand the open loans of each book to its first copies, in loan order
+UPDATE loans SET item_id = (
+    SELECT bi.id
+    FROM (
+        SELECT id, book_id, ROW_NUMBER() OVER (PARTITION BY book_id ORDER BY id) AS rn
+        FROM book_items
+    ) bi
+    JOIN (
+        SELECT id, book_id, ROW_NUMBER() OVER (PARTITION BY book_id ORDER BY id) AS rn
+        FROM loans WHERE status = 'borrowed'
+    ) ol ON ol.book_id = bi.book_id AND ol.rn = bi.rn
+    WHERE ol.id = loans.id
+)
+WHERE status = 'borrowed';
+
+UPDATE book_items SET status = 'Dipinjam'
+WHERE id IN (SELECT item_id FROM loans WHERE status = 'borrowed' AND item_id IS NOT NULL);
+
+CREATE INDEX idx_loans_item ON loans (item_id);
+
+-- From now on available_copy is derived from the items
+UPDATE books SET available_copy = (
+    SELECT COUNT(*) FROM book_items WHERE book_id = books.id AND status = 'Tersedia'
+);
@@ -0,0 +1,35 @@
+-- =====================================
+-- LOAN POLICIES
+-- =====================================
+-- member_type: 'student', 'teacher', 'staff'
+ALTER TABLE members ADD COLUMN member_type TEXT NOT NULL DEFAULT 'student';
+
+-- collection_type: 'reference', 'fiction' (general lending), 'textbook'
+ALTER TABLE books ADD COLUMN collection_type TEXT NOT NULL DEFAULT 'fiction';
+
+CREATE TABLE loan_policies (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    member_type TEXT NOT NULL,
+    collection_type TEXT NOT NULL,
+    loan_days INTEGER NOT NULL,
+    max_loans INTEGER NOT NULL,
+    max_renewals INTEGER NOT NULL DEFAULT 0,
+    fine_per_day INTEGER NOT NULL DEFAULT 0,
+    UNIQUE (member_type, collection_type)
+);
+
+-- Fine rates start from the existing global setting
+INSERT INTO loan_policies (member_type, collection_type, loan_days, max_loans, max_renewals, fine_per_day)
+SELECT p.member_type, p.collection_type, p.loan_days, p.max_loans, p.max_renewals,
+       COALESCE((SELECT CAST(value AS INTEGER) FROM settings WHERE key = 'fine_late_per_day'), 1000)
+FROM (
+    SELECT 'student' AS member_type, 'fiction' AS collection_type, 7 AS loan_days, 3 AS max_loans, 1 AS max_renewals
+    UNION ALL SELECT 'student', 'textbook', 7, 3, 1
+    UNION ALL SELECT 'student', 'reference', 1, 3, 0
+    UNION ALL SELECT 'teacher', 'fiction', 14, 10, 2
+    UNION ALL SELECT 'teacher', 'textbook', 14, 10, 2
+    UNION ALL SELECT 'teacher', 'reference', 3, 10, 1
+    UNION ALL SELECT 'staff', 'fiction', 14, 5, 1
+    UNION ALL SELECT 'staff', 'textbook', 14, 5, 1
+    UNION ALL SELECT 'staff', 'reference', 3, 5, 0
+) p;
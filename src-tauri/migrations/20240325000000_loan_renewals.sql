@@ -0,0 +1,20 @@
+-- =====================================
+-- LOAN RENEWALS
+-- =====================================
+ALTER TABLE loans ADD COLUMN renewal_count INTEGER NOT NULL DEFAULT 0;
+
+CREATE TABLE loan_renewals (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    loan_id INTEGER NOT NULL,
+    previous_due_date DATETIME NOT NULL,
+    new_due_date DATETIME NOT NULL,
+    renewed_by INTEGER,
+    renewed_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (loan_id) REFERENCES loans(id),
+    FOREIGN KEY (renewed_by) REFERENCES users(id)
+);
+
+CREATE INDEX idx_loan_renewals_loan ON loan_renewals (loan_id);
+
+-- Loans overdue by more than this many days can no longer be renewed
+INSERT INTO settings (key, value) VALUES ('renewal_overdue_limit_days', '0');
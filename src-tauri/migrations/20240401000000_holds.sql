@@ -0,0 +1,24 @@
+-- =====================================
+-- HOLDS (reservation queue per title)
+-- =====================================
+-- status: 'waiting', 'ready', 'fulfilled', 'cancelled', 'expired'
+-- A ready hold owns one copy (item_id), which is kept aside with status 'Dipesan'
+CREATE TABLE holds (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    book_id INTEGER NOT NULL,
+    member_id INTEGER NOT NULL,
+    item_id INTEGER,
+    status TEXT NOT NULL DEFAULT 'waiting',
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    ready_at DATETIME,
+    expires_at DATETIME,
+    closed_at DATETIME,
+    FOREIGN KEY (book_id) REFERENCES books(id),
+    FOREIGN KEY (member_id) REFERENCES members(id),
+    FOREIGN KEY (item_id) REFERENCES book_items(id)
+);
+
+CREATE INDEX idx_holds_book_status ON holds (book_id, status);
+CREATE INDEX idx_holds_member ON holds (member_id);
+
+INSERT INTO settings (key, value) VALUES ('hold_pickup_days', '3');
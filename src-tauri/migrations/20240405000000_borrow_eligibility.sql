@@ -0,0 +1,24 @@
+-- =====================================
+-- BORROWING ELIGIBILITY
+-- =====================================
+-- NULL means the membership does not expire
+ALTER TABLE members ADD COLUMN valid_until DATE;
+
+-- One-time permission to borrow despite blocking reasons, valid on the day it is granted
+CREATE TABLE borrow_overrides (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    member_id INTEGER NOT NULL,
+    reason TEXT NOT NULL,
+    granted_by INTEGER,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    used_at DATETIME,
+    loan_id INTEGER,
+    FOREIGN KEY (member_id) REFERENCES members(id),
+    FOREIGN KEY (granted_by) REFERENCES users(id),
+    FOREIGN KEY (loan_id) REFERENCES loans(id)
+);
+
+CREATE INDEX idx_borrow_overrides_member ON borrow_overrides (member_id);
+
+-- Members whose unpaid fines add up to more than this may not borrow
+INSERT INTO settings (key, value) VALUES ('max_unpaid_fine', '0');
@@ -0,0 +1,18 @@
+-- =====================================
+-- FINE ACCRUAL
+-- =====================================
+-- Late fine built up so far by a loan that is still out, refreshed by the daily run
+ALTER TABLE loans ADD COLUMN accrued_fine INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE loans ADD COLUMN fine_accrued_at DATETIME;
+
+-- Days the library is closed are never charged
+CREATE TABLE closure_days (
+    date DATE PRIMARY KEY,
+    description TEXT
+);
+
+-- fine_grace_days: late days that are not charged
+-- fine_max_per_loan: cap on the late fine of a single loan (0 = no cap)
+INSERT INTO settings (key, value) VALUES ('fine_grace_days', '0');
+INSERT INTO settings (key, value) VALUES ('fine_max_per_loan', '0');
+INSERT INTO settings (key, value) VALUES ('fine_accrual_last_run', '');
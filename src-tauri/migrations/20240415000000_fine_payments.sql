@@ -0,0 +1,44 @@
+-- =====================================
+-- FINE PAYMENTS LEDGER
+-- =====================================
+-- entry_type: 'payment' (money received) or 'waiver' (amount written off)
+-- method: 'cash' or 'transfer' for payments, NULL for waivers
+-- A reversal is a new row with the negated amount that points at the original
+-- through reversal_of, so the ledger is never edited after the fact.
+CREATE TABLE fine_payments (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    fine_id INTEGER NOT NULL,
+    entry_type TEXT NOT NULL,
+    amount INTEGER NOT NULL,
+    method TEXT,
+    reason TEXT,
+    reversal_of INTEGER,
+    received_by INTEGER,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (fine_id) REFERENCES fines(id),
+    FOREIGN KEY (reversal_of) REFERENCES fine_payments(id),
+    FOREIGN KEY (received_by) REFERENCES users(id)
+);
+
+CREATE INDEX idx_fine_payments_fine ON fine_payments (fine_id);
+CREATE UNIQUE INDEX idx_fine_payments_reversal ON fine_payments (reversal_of);
+
+-- Fines settled before the ledger existed were paid in full, in cash
+INSERT INTO fine_payments (fine_id, entry_type, amount, method, created_at)
+SELECT id, 'payment', amount, 'cash', COALESCE(paid_at, created_at)
+FROM fines
+WHERE status = 'Paid' AND amount > 0;
+
+-- Outstanding balance of every fine, derived from the ledger
+CREATE VIEW fine_balances AS
+SELECT
+    f.id AS fine_id,
+    l.member_id,
+    f.amount,
+    COALESCE(SUM(CASE WHEN p.entry_type = 'payment' THEN p.amount END), 0) AS paid_amount,
+    COALESCE(SUM(CASE WHEN p.entry_type = 'waiver' THEN p.amount END), 0) AS waived_amount,
+    f.amount - COALESCE(SUM(p.amount), 0) AS remaining_amount
+FROM fines f
+JOIN loans l ON f.loan_id = l.id
+LEFT JOIN fine_payments p ON p.fine_id = f.id
+GROUP BY f.id;
@@ -0,0 +1,13 @@
+-- =====================================
+-- LOST BOOKS
+-- =====================================
+-- Replacement price of a title; a copy's own price in book_items takes precedence
+ALTER TABLE books ADD COLUMN price INTEGER;
+
+-- loans.status gains 'lost'. A lost loan that turns up again is closed as
+-- 'returned' with found_at set.
+ALTER TABLE loans ADD COLUMN lost_at DATETIME;
+ALTER TABLE loans ADD COLUMN found_at DATETIME;
+
+-- Charged on top of the replacement price of a lost book
+INSERT INTO settings (key, value) VALUES ('fine_lost_processing_fee', '10000');
@@ -0,0 +1,10 @@
+-- =====================================
+-- RECEIPTS
+-- =====================================
+-- Printed at the top of receipts; library_logo is the path of an image file
+INSERT INTO settings (key, value) VALUES ('library_name', 'Perpustakaan Sekolah');
+INSERT INTO settings (key, value) VALUES ('library_address', '');
+INSERT INTO settings (key, value) VALUES ('library_logo', '');
+
+-- Thermal paper roll width in mm: 58 or 80
+INSERT INTO settings (key, value) VALUES ('receipt_paper_width', '58');
@@ -0,0 +1,6 @@
+-- =====================================
+-- LABELS
+-- =====================================
+-- Shelf call number printed on spine labels, e.g. "813 AND h"; when empty the
+-- label falls back to the author and title initials
+ALTER TABLE books ADD COLUMN call_number TEXT;
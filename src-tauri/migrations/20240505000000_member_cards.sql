@@ -0,0 +1,8 @@
+-- =====================================
+-- MEMBER CARDS
+-- =====================================
+-- Path of the member's photo file, printed on the membership card
+ALTER TABLE members ADD COLUMN photo TEXT;
+
+-- Title printed above the library name on membership cards
+INSERT INTO settings (key, value) VALUES ('card_header', 'KARTU ANGGOTA PERPUSTAKAAN');
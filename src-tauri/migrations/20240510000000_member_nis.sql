@@ -0,0 +1,7 @@
+-- =====================================
+-- MEMBER ROSTER
+-- =====================================
+-- Student number (NIS) from the school office roster, used to match members
+-- when a new roster is imported
+ALTER TABLE members ADD COLUMN nis TEXT;
+CREATE UNIQUE INDEX idx_members_nis ON members(nis) WHERE nis IS NOT NULL;
@@ -0,0 +1,39 @@
+-- =====================================
+-- ACADEMIC YEAR ROLLOVER
+-- =====================================
+-- Next grade for each grade (the first word of kelas), as JSON; null means
+-- the grade graduates
+INSERT INTO settings (key, value) VALUES ('promotion_map', '{"X": "XI", "XI": "XII", "XII": null}');
+
+CREATE TABLE academic_rollovers (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    academic_year TEXT NOT NULL,
+    promotion_map TEXT NOT NULL,
+    performed_by INTEGER NOT NULL,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    undone_at DATETIME,
+    undone_by INTEGER,
+    FOREIGN KEY (performed_by) REFERENCES users(id),
+    FOREIGN KEY (undone_by) REFERENCES users(id)
+);
+
+-- One row per member moved by a rollover, holding what is needed to undo it.
+-- outcome: 'promoted', 'graduated', or 'blocked' for a graduate who still had
+-- loans or fines and stays active until cleared (cleared_at)
+CREATE TABLE member_promotions (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    rollover_id INTEGER NOT NULL,
+    member_id INTEGER NOT NULL,
+    old_kelas TEXT,
+    new_kelas TEXT,
+    old_status TEXT,
+    outcome TEXT NOT NULL,
+    open_loans INTEGER NOT NULL DEFAULT 0,
+    unpaid_amount INTEGER NOT NULL DEFAULT 0,
+    cleared_at DATETIME,
+    FOREIGN KEY (rollover_id) REFERENCES academic_rollovers(id),
+    FOREIGN KEY (member_id) REFERENCES members(id)
+);
+
+CREATE INDEX idx_member_promotions_rollover ON member_promotions (rollover_id);
+CREATE INDEX idx_member_promotions_member ON member_promotions (member_id);
@@ -0,0 +1,39 @@
+-- =====================================
+-- CATALOGUE SEARCH
+-- =====================================
+-- Subject keywords entered by the librarian, searched together with the title,
+-- author, publisher and category
+ALTER TABLE books ADD COLUMN keywords TEXT;
+
+-- Full-text index over the book columns. remove_diacritics folds "é" to "e"
+-- and case is folded by the tokenizer, so "Pramoedya" matches "pramoedya".
+CREATE VIRTUAL TABLE books_fts USING fts5(
+    title,
+    author,
+    publisher,
+    category,
+    keywords,
+    content = 'books',
+    content_rowid = 'id',
+    tokenize = 'unicode61 remove_diacritics 2',
+    prefix = '2 3'
+);
+
+CREATE TRIGGER books_fts_insert AFTER INSERT ON books BEGIN
+    INSERT INTO books_fts (rowid, title, author, publisher, category, keywords)
+    VALUES (new.id, new.title, new.author, new.publisher, new.category, new.keywords);
+END;
+
+CREATE TRIGGER books_fts_delete AFTER DELETE ON books BEGIN
+    INSERT INTO books_fts (books_fts, rowid, title, author, publisher, category, keywords)
+    VALUES ('delete', old.id, old.title, old.author, old.publisher, old.category, old.keywords);
+END;
+
+CREATE TRIGGER books_fts_update AFTER UPDATE OF title, author, publisher, category, keywords ON books BEGIN
+    INSERT INTO books_fts (books_fts, rowid, title, author, publisher, category, keywords)
+    VALUES ('delete', old.id, old.title, old.author, old.publisher, old.category, old.keywords);
+    INSERT INTO books_fts (rowid, title, author, publisher, category, keywords)
+    VALUES (new.id, new.title, new.author, new.publisher, new.category, new.keywords);
+END;
+
+INSERT INTO books_fts (books_fts) VALUES ('rebuild');
@@ -0,0 +1,23 @@
+-- =====================================
+-- SELF-SERVICE STATION
+-- =====================================
+-- Most books a member may take in one self-checkout visit, and the ESC/POS
+-- printer device the station prints loan receipts on
+INSERT INTO settings (key, value) VALUES ('self_checkout_max_books', '3');
+INSERT INTO settings (key, value) VALUES ('self_checkout_printer', '');
+
+-- Books dropped at the station. The loan is closed when the book is scanned;
+-- the copy stays 'Diperiksa' until staff record the condition it came back in.
+CREATE TABLE self_returns (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    loan_id INTEGER NOT NULL UNIQUE,
+    item_id INTEGER,
+    returned_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    inspected_at DATETIME,
+    inspected_by INTEGER,
+    FOREIGN KEY (loan_id) REFERENCES loans(id),
+    FOREIGN KEY (item_id) REFERENCES book_items(id),
+    FOREIGN KEY (inspected_by) REFERENCES users(id)
+);
+
+CREATE INDEX idx_self_returns_pending ON self_returns (inspected_at);
@@ -0,0 +1,22 @@
+-- =====================================
+-- VISITOR GUESTBOOK (BUKU TAMU)
+-- =====================================
+-- One row per visit. Members check in with their card; guests and parents are
+-- written in by name. kelas and jenis_kelamin are copied at check-in so the
+-- statistics keep the class a student was in when they visited.
+CREATE TABLE visits (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    member_id INTEGER,
+    visitor_name TEXT NOT NULL,
+    purpose TEXT,
+    kelas TEXT,
+    jenis_kelamin TEXT,
+    checked_in_at DATETIME NOT NULL,
+    checked_out_at DATETIME,
+    recorded_by INTEGER,
+    FOREIGN KEY (member_id) REFERENCES members(id),
+    FOREIGN KEY (recorded_by) REFERENCES users(id)
+);
+
+CREATE INDEX idx_visits_checked_in_at ON visits (checked_in_at);
+CREATE INDEX idx_visits_member ON visits (member_id);
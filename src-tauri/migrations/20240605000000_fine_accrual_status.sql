@@ -0,0 +1,3 @@
+-- Why the last background fine accrual failed, with the time it failed; empty
+-- after a run that succeeded
+INSERT INTO settings (key, value) VALUES ('fine_accrual_last_error', '');
@@ -0,0 +1,86 @@
+use crate::auth::{Role, Session, SessionStore};
+use crate::models::{AuditLogEntry, AuditLogFilter};
+use serde::Serialize;
+use serde_json::Value;
+use sqlx::{QueryBuilder, Sqlite, SqliteConnection, SqlitePool};
+use tauri::State;
+
+pub fn snapshot<T: Serialize>(value: &T) -> Option<Value> {
+    serde_json::to_value(value).ok()
+}
+
+// Write one audit entry; callers pass their transaction so the entry is
+// committed (or rolled back) together with the change it describes
+pub async fn record(
+    conn: &mut SqliteConnection,
+    session: &Session,
+    action: &str,
+    entity: &str,
+    entity_id: Option<i64>,
+    before: Option<Value>,
+    after: Option<Value>,
+) -> Result<(), String> {
+    sqlx::query(
+        "INSERT INTO audit_log (user_id, username, action, entity, entity_id, before_data, after_data) VALUES (?, ?, ?, ?, ?, ?, ?)",
+    )
+    .bind(session.account_id())
+    .bind(&session.username)
+    .bind(action)
+    .bind(entity)
+    .bind(entity_id)
+    .bind(before.map(|v| v.to_string()))
+    .bind(after.map(|v| v.to_string()))
+    .execute(conn)
+    .await
+    .map_err(|e| format!("Gagal mencatat log audit: {}", e))?;
+
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn get_audit_log(
+    pool: State<'_, SqlitePool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+    filter: Option<AuditLogFilter>,
+) -> Result<Vec<AuditLogEntry>, String> {
+    sessions.authorize(&token, Role::Admin)?;
+
+    let filter = filter.unwrap_or_default();
+    let mut query: QueryBuilder<Sqlite> = QueryBuilder::new(
+        "SELECT id, user_id, username, action, entity, entity_id, before_data, after_data, created_at FROM audit_log WHERE 1 = 1",
+    );
+
+    // Dates are inclusive local calendar days (YYYY-MM-DD)
+    if let Some(date_from) = filter.date_from {
+        query
+            .push(" AND date(created_at, 'localtime') >= date(")
+            .push_bind(date_from)
+            .push(")");
+    }
+    if let Some(date_to) = filter.date_to {
+        query
+            .push(" AND date(created_at, 'localtime') <= date(")
+            .push_bind(date_to)
+            .push(")");
+    }
+    if let Some(user_id) = filter.user_id {
+        query.push(" AND user_id = ").push_bind(user_id);
+    }
+    if let Some(entity) = filter.entity {
+        query.push(" AND entity = ").push_bind(entity);
+    }
+    if let Some(entity_id) = filter.entity_id {
+        query.push(" AND entity_id = ").push_bind(entity_id);
+    }
+
+    query
+        .push(" ORDER BY created_at DESC, id DESC LIMIT ")
+        .push_bind(filter.limit.unwrap_or(500));
+
+    query
+        .build_query_as::<AuditLogEntry>()
+        .fetch_all(&*pool)
+        .await
+        .map_err(|e| e.to_string())
+}
@@ -0,0 +1,260 @@
+use crate::models::User;
+use crate::settings_commands::get_setting_i64;
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use chrono::{DateTime, Duration, Utc};
+use sqlx::SqliteConnection;
+use std::collections::HashMap;
+use std::sync::{Mutex, MutexGuard};
+
+// Sessions expire after this many hours without any command being called
+const SESSION_IDLE_HOURS: i64 = 12;
+
+// Added to every failed login to slow down password guessing
+pub const FAILED_LOGIN_DELAY_MS: u64 = 500;
+
+// Hash a password with Argon2id and a random salt, stored as a PHC string
+pub fn hash_password(password: &str) -> Result<String, String> {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|e| format!("Gagal mengenkripsi kata sandi: {}", e))
+}
+
+// Rows written before hashing was introduced still hold the plain password
+pub fn is_legacy_password(stored: &str) -> bool {
+    !stored.starts_with("$argon2")
+}
+
+// Checked when the username doesn't exist, so that a login attempt takes the
+// same time whether or not the account is real
+pub const DUMMY_PASSWORD_HASH: &str =
+    "$argon2id$v=19$m=19456,t=2,p=1$qAMRqL/noAjCB5OaiDMTPw$EZoumGMl3Y+KzZIEMoNoHMaI0IcfBv9dz3JcOOr2a7A";
+
+pub fn verify_password(password: &str, stored: &str) -> bool {
+    if is_legacy_password(stored) {
+        return password == stored;
+    }
+
+    match PasswordHash::new(stored) {
+        Ok(parsed) => Argon2::default()
+            .verify_password(password.as_bytes(), &parsed)
+            .is_ok(),
+        Err(_) => false,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Role {
+    Viewer,
+    Staff,
+    Admin,
+}
+
+impl Role {
+    pub fn from_db(role: &str) -> Option<Role> {
+        match role {
+            "viewer" => Some(Role::Viewer),
+            "staff" => Some(Role::Staff),
+            "admin" => Some(Role::Admin),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Session {
+    pub user_id: i64,
+    pub username: String,
+    pub role: Role,
+    pub expires_at: DateTime<Utc>,
+}
+
+// Account ids start at 1, so 0 can stand for "no account"
+const NO_ACCOUNT: i64 = 0;
+
+impl Session {
+    // Actions a member takes at the self-service station. They are not made by
+    // a user account and are never stored in the SessionStore.
+    pub fn station(username: String) -> Session {
+        Session {
+            user_id: NO_ACCOUNT,
+            username,
+            role: Role::Staff,
+            expires_at: Utc::now(),
+        }
+    }
+
+    // The user account behind the session; None for a station session
+    pub fn account_id(&self) -> Option<i64> {
+        (self.user_id != NO_ACCOUNT).then_some(self.user_id)
+    }
+}
+
+// Sessions live only in memory, so restarting the app logs everyone out
+#[derive(Default)]
+pub struct SessionStore {
+    sessions: Mutex<HashMap<String, Session>>,
+}
+
+impl SessionStore {
+    pub fn create(&self, user: &User) -> Result<String, String> {
+        let role = Role::from_db(&user.role)
+            .ok_or_else(|| format!("Peran pengguna '{}' tidak dikenali", user.role))?;
+
+        let mut bytes = [0u8; 32];
+        OsRng.fill_bytes(&mut bytes);
+        let token: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+
+        let session = Session {
+            user_id: user.id,
+            username: user.username.clone(),
+            role,
+            expires_at: Utc::now() + Duration::hours(SESSION_IDLE_HOURS),
+        };
+
+        self.lock().insert(token.clone(), session);
+        Ok(token)
+    }
+
+    pub fn revoke(&self, token: &str) {
+        self.lock().remove(token);
+    }
+
+    pub fn revoke_user(&self, user_id: i64) {
+        self.lock().retain(|_, s| s.user_id != user_id);
+    }
+
+    // Validate the token and check that its role is at least `required`
+    pub fn authorize(&self, token: &str, required: Role) -> Result<Session, String> {
+        let mut sessions = self.lock();
+        let now = Utc::now();
+        sessions.retain(|_, s| s.expires_at > now);
+
+        let session = sessions
+            .get_mut(token)
+            .ok_or("Sesi tidak valid atau telah berakhir, silakan login kembali")?;
+
+        if session.role < required {
+            return Err("Anda tidak memiliki akses untuk tindakan ini".to_string());
+        }
+
+        session.expires_at = now + Duration::hours(SESSION_IDLE_HOURS);
+        Ok(session.clone())
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<String, Session>> {
+        self.sessions.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+pub async fn record_login_event(
+    conn: &mut SqliteConnection,
+    username: &str,
+    user_id: Option<i64>,
+    success: bool,
+    reason: Option<&str>,
+) -> Result<(), String> {
+    sqlx::query(
+        "INSERT INTO login_events (username, user_id, success, reason) VALUES (?, ?, ?, ?)",
+    )
+    .bind(username)
+    .bind(user_id)
+    .bind(success)
+    .bind(reason)
+    .execute(conn)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    Ok(())
+}
+
+// Reject the attempt early while the username is still locked out
+pub async fn check_lockout(conn: &mut SqliteConnection, username: &str) -> Result<(), String> {
+    let locked_until: Option<DateTime<Utc>> =
+        sqlx::query_scalar("SELECT locked_until FROM login_attempts WHERE username = ?")
+            .bind(username)
+            .fetch_optional(&mut *conn)
+            .await
+            .map_err(|e| e.to_string())?
+            .flatten();
+
+    let now = Utc::now();
+    match locked_until {
+        Some(until) if until > now => {
+            record_login_event(conn, username, None, false, Some("locked")).await?;
+            let minutes = (until - now).num_minutes() + 1;
+            Err(format!(
+                "Akun dikunci sementara karena terlalu banyak percobaan gagal. Coba lagi dalam {} menit",
+                minutes
+            ))
+        }
+        _ => Ok(()),
+    }
+}
+
+// Count a failed attempt; returns the lockout length in minutes when this
+// attempt pushed the username over `login_max_attempts`
+pub async fn register_failed_login(
+    conn: &mut SqliteConnection,
+    username: &str,
+    user_id: Option<i64>,
+    reason: &str,
+) -> Result<Option<i64>, String> {
+    let max_attempts = get_setting_i64(&mut *conn, "login_max_attempts", 5).await;
+    let lockout_minutes = get_setting_i64(&mut *conn, "login_lockout_minutes", 15).await;
+    let now = Utc::now();
+
+    sqlx::query(
+        r#"
+        INSERT INTO login_attempts (username, failed_count, last_failed_at)
+        VALUES (?, 1, ?)
+        ON CONFLICT(username) DO UPDATE SET
+            failed_count = failed_count + 1,
+            last_failed_at = excluded.last_failed_at
+        "#,
+    )
+    .bind(username)
+    .bind(now)
+    .execute(&mut *conn)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    record_login_event(conn, username, user_id, false, Some(reason)).await?;
+
+    let failed_count: i64 =
+        sqlx::query_scalar("SELECT failed_count FROM login_attempts WHERE username = ?")
+            .bind(username)
+            .fetch_one(&mut *conn)
+            .await
+            .map_err(|e| e.to_string())?;
+
+    // A limit of 0 disables lockout entirely
+    if max_attempts <= 0 || failed_count < max_attempts {
+        return Ok(None);
+    }
+
+    sqlx::query("UPDATE login_attempts SET failed_count = 0, locked_until = ? WHERE username = ?")
+        .bind(now + Duration::minutes(lockout_minutes))
+        .bind(username)
+        .execute(conn)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(Some(lockout_minutes))
+}
+
+pub async fn clear_failed_logins(
+    conn: &mut SqliteConnection,
+    username: &str,
+) -> Result<(), String> {
+    sqlx::query("DELETE FROM login_attempts WHERE username = ?")
+        .bind(username)
+        .execute(conn)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(())
+}
@@ -0,0 +1,219 @@
+// Barcode encoders. Each returns the symbol as a run of modules (true = bar),
+// without quiet zones.
+
+// Bar/space widths of Code 128 symbols 0..=105; the stop pattern is separate
+const CODE128_PATTERNS: [&str; 106] = [
+    "212222", "222122", "222221", "121223", "121322", "131222", "122213", "122312", "132212",
+    "221213", "221312", "231212", "112232", "122132", "122231", "113222", "123122", "123221",
+    "223211", "221132", "221231", "213212", "223112", "312131", "311222", "321122", "321221",
+    "312212", "322112", "322211", "212123", "212321", "232121", "111323", "131123", "131321",
+    "112313", "132113", "132311", "211313", "231113", "231311", "112133", "112331", "132131",
+    "113123", "113321", "133121", "313121", "211331", "231131", "213113", "213311", "213131",
+    "311123", "311321", "331121", "312113", "312311", "332111", "314111", "221411", "431111",
+    "111224", "111422", "121124", "121421", "141122", "141221", "112214", "112412", "122114",
+    "122411", "142112", "142211", "241211", "221114", "413111", "241112", "134111", "111242",
+    "121142", "121241", "114212", "124112", "124211", "411212", "421112", "421211", "212141",
+    "214121", "412121", "111143", "111341", "131141", "114113", "114311", "411113", "411311",
+    "113141", "114131", "311141", "411131", "211412", "211214", "211232",
+];
+const CODE128_STOP: &str = "2331112";
+const CODE128_START_B: usize = 104;
+
+// EAN-13 left-hand odd parity ("L") digit patterns
+const EAN_L: [&str; 10] = [
+    "0001101", "0011001", "0010011", "0111101", "0100011", "0110001", "0101111", "0111011",
+    "0110111", "0001011",
+];
+// Which of the six left digits use even parity ("G"), keyed by the first digit
+const EAN_PARITY: [&str; 10] = [
+    "LLLLLL", "LLGLGG", "LLGGLG", "LLGGGL", "LGLLGG", "LGGLLG", "LGGGLL", "LGLGLG", "LGLGGL",
+    "LGGLGL",
+];
+
+fn push_widths(modules: &mut Vec<bool>, widths: &str) {
+    for (i, w) in widths.bytes().enumerate() {
+        let bar = i % 2 == 0;
+        modules.extend(std::iter::repeat_n(bar, (w - b'0') as usize));
+    }
+}
+
+fn push_bits(modules: &mut Vec<bool>, bits: &str) {
+    modules.extend(bits.bytes().map(|b| b == b'1'));
+}
+
+// Code 128 using code set B, which covers printable ASCII
+pub fn code128(data: &str) -> Result<Vec<bool>, String> {
+    if data.is_empty() {
+        return Err("Isi barcode tidak boleh kosong".to_string());
+    }
+
+    let mut values = vec![CODE128_START_B];
+    for c in data.chars() {
+        if !(' '..='~').contains(&c) {
+            return Err(format!(
+                "Karakter '{}' tidak dapat dibuat barcode Code 128",
+                c
+            ));
+        }
+        values.push(c as usize - 32);
+    }
+    let checksum = values
+        .iter()
+        .enumerate()
+        .map(|(i, v)| v * i.max(1))
+        .sum::<usize>()
+        % 103;
+    values.push(checksum);
+
+    let mut modules = Vec::new();
+    for value in values {
+        push_widths(&mut modules, CODE128_PATTERNS[value]);
+    }
+    push_widths(&mut modules, CODE128_STOP);
+    Ok(modules)
+}
+
+fn ean_check_digit(digits: &[u8]) -> u8 {
+    let sum: u32 = digits
+        .iter()
+        .enumerate()
+        .map(|(i, d)| *d as u32 * if i % 2 == 0 { 1 } else { 3 })
+        .sum();
+    ((10 - sum % 10) % 10) as u8
+}
+
+// EAN-13 from 12 digits (the check digit is added) or 13 digits (it is verified)
+pub fn ean13(data: &str) -> Result<Vec<bool>, String> {
+    let digits: Vec<u8> = data.bytes().map(|b| b.wrapping_sub(b'0')).collect();
+    if !(digits.len() == 12 || digits.len() == 13) || digits.iter().any(|d| *d > 9) {
+        return Err(format!("'{}' bukan kode EAN-13 yang valid", data));
+    }
+    let check = ean_check_digit(&digits[..12]);
+    if digits.len() == 13 && digits[12] != check {
+        return Err(format!("Digit pemeriksa EAN-13 '{}' salah", data));
+    }
+
+    let parity = EAN_PARITY[digits[0] as usize].as_bytes();
+    let mut modules = Vec::new();
+    push_bits(&mut modules, "101");
+    for (i, d) in digits[1..7].iter().enumerate() {
+        let l = EAN_L[*d as usize];
+        if parity[i] == b'L' {
+            push_bits(&mut modules, l);
+        } else {
+            // G patterns are the R patterns reversed, and R is L inverted
+            modules.extend(l.bytes().rev().map(|b| b == b'0'));
+        }
+    }
+    push_bits(&mut modules, "01010");
+    for d in digits[7..12].iter().chain(std::iter::once(&check)) {
+        modules.extend(EAN_L[*d as usize].bytes().map(|b| b == b'0'));
+    }
+    push_bits(&mut modules, "101");
+    Ok(modules)
+}
+
+// An ISBN in either form as the 13 digits printed in its EAN-13 barcode.
+// Hyphens and spaces are ignored; None when the check digit does not match.
+pub fn isbn13(isbn: &str) -> Option<String> {
+    let cleaned: String = isbn
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != '-')
+        .collect::<String>()
+        .to_uppercase();
+
+    match cleaned.len() {
+        13 if cleaned.bytes().all(|b| b.is_ascii_digit()) => {
+            let digits: Vec<u8> = cleaned.bytes().map(|b| b - b'0').collect();
+            (ean_check_digit(&digits[..12]) == digits[12]).then_some(cleaned)
+        }
+        10 => {
+            let (body, check) = cleaned.split_at(9);
+            if !body.bytes().all(|b| b.is_ascii_digit()) {
+                return None;
+            }
+            let sum: u32 = body
+                .bytes()
+                .enumerate()
+                .map(|(i, b)| (b - b'0') as u32 * (10 - i as u32))
+                .sum();
+            let expected = (11 - sum % 11) % 11;
+            let valid = match check {
+                "X" => expected == 10,
+                _ => check.parse::<u32>().ok() == Some(expected),
+            };
+            if !valid {
+                return None;
+            }
+
+            let mut digits: Vec<u8> = b"978".iter().map(|b| b - b'0').collect();
+            digits.extend(body.bytes().map(|b| b - b'0'));
+            digits.push(ean_check_digit(&digits));
+            Some(digits.iter().map(|d| (b'0' + d) as char).collect())
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bits(modules: &[bool]) -> String {
+        modules.iter().map(|m| if *m { '1' } else { '0' }).collect()
+    }
+
+    #[test]
+    fn code128_copy_code() {
+        let bits = bits(&code128("B-2024-0001").unwrap());
+        // Start B, 11 characters, checksum and the 13-module stop
+        assert_eq!(bits.len(), 13 * 11 + 13);
+        assert!(bits.starts_with("11010010000"));
+        assert_eq!(&bits[11..22], "10001011000");
+        // (104 + 34*1 + 13*2 + ... + 17*11) % 103 = 69
+        assert_eq!(&bits[132..143], "10110010000");
+        assert!(bits.ends_with("1100011101011"));
+    }
+
+    #[test]
+    fn code128_rejects_non_ascii() {
+        assert!(code128("").is_err());
+        assert!(code128("Buku é").is_err());
+    }
+
+    #[test]
+    fn ean13_modules() {
+        let expected = [
+            "101", "0111011", "0001001", "0100111", "0111101", "0100111", "0101111", "01010",
+            "1011100", "1110010", "1010000", "1100110", "1001110", "1000100", "101",
+        ]
+        .concat();
+        assert_eq!(bits(&ean13("9780306406157").unwrap()), expected);
+        assert_eq!(bits(&ean13("978030640615").unwrap()), expected);
+    }
+
+    #[test]
+    fn ean13_rejects_bad_check_digit() {
+        assert!(ean13("9780306406158").is_err());
+        assert!(ean13("97803064061").is_err());
+        assert!(ean13("97803064061A7").is_err());
+    }
+
+    #[test]
+    fn isbn_to_ean() {
+        assert_eq!(isbn13("0-306-40615-2").as_deref(), Some("9780306406157"));
+        assert_eq!(isbn13("080442957x").as_deref(), Some("9780804429573"));
+        assert_eq!(
+            isbn13("978-0-306-40615-7").as_deref(),
+            Some("9780306406157")
+        );
+    }
+
+    #[test]
+    fn isbn_rejects_bad_check_digit() {
+        assert_eq!(isbn13("0-306-40615-3"), None);
+        assert_eq!(isbn13("0804429571"), None);
+        assert_eq!(isbn13("9780306406158"), None);
+        assert_eq!(isbn13("12345"), None);
+    }
+}
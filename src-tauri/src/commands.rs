@@ -1,17 +1,96 @@
-use crate::models::{Book, FineWithDetails, Loan, LoanWithDetails, Member, NewMember, User};
+use crate::audit;
+use crate::auth::{self, Role, Session, SessionStore};
+use crate::eligibility_commands;
+use crate::fine_commands;
+use crate::hold_commands;
+use crate::item_commands;
+use crate::models::{
+    AuthSession, Book, BookFilter, Fine, FineFilter, FineWithDetails, ListQuery, Loan, LoanFilter,
+    LoanRenewal, LoanWithDetails, Member, MemberFilter, NewMember, Page, User,
+};
+use crate::paging::{self, ListSql};
+use crate::policy_commands;
+use crate::search_commands;
+use crate::settings_commands;
 use chrono::{Duration, Utc};
-use sqlx::SqlitePool;
+use sqlx::{QueryBuilder, Sqlite, SqliteConnection, SqlitePool};
 use tauri::State;
 
+const BOOK_LIST: ListSql = ListSql {
+    select: "SELECT b.*",
+    from: "FROM books b WHERE b.deleted_at IS NULL",
+    sort_columns: &[
+        ("title", "b.title"),
+        ("author", "b.author"),
+        ("category", "b.category"),
+        ("published_year", "b.published_year"),
+        ("rack_location", "b.rack_location"),
+        ("available_copy", "b.available_copy"),
+        ("created_at", "b.created_at"),
+    ],
+    default_order: "b.title ASC, b.id ASC",
+    tiebreak: "b.id",
+};
+
 #[tauri::command]
-pub async fn get_books(pool: State<'_, SqlitePool>) -> Result<Vec<Book>, String> {
-    sqlx::query_as::<_, Book>("SELECT * FROM books WHERE deleted_at IS NULL ORDER BY title ASC")
-        .fetch_all(&*pool)
-        .await
-        .map_err(|e| e.to_string())
+pub async fn get_books(
+    pool: State<'_, SqlitePool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+    query: Option<ListQuery<BookFilter>>,
+) -> Result<Page<Book>, String> {
+    sessions.authorize(&token, Role::Viewer)?;
+
+    let query = query.unwrap_or_default();
+    let filter = &query.filter;
+    let search = filter
+        .search
+        .as_deref()
+        .and_then(search_commands::match_expression);
+    paging::fetch_page(&pool, &BOOK_LIST, &query, |builder| {
+        if let Some(search) = &search {
+            builder
+                .push(" AND b.id IN (SELECT rowid FROM books_fts WHERE books_fts MATCH ")
+                .push_bind(search.clone())
+                .push(")");
+        }
+        if let Some(category) = &filter.category {
+            builder
+                .push(" AND b.category = ")
+                .push_bind(category.clone());
+        }
+        if let Some(rack) = &filter.rack {
+            builder
+                .push(" AND b.rack_location = ")
+                .push_bind(rack.clone());
+        }
+        if let Some(collection_type) = &filter.collection_type {
+            builder
+                .push(" AND b.collection_type = ")
+                .push_bind(collection_type.clone());
+        }
+        if let Some(status) = &filter.status {
+            builder.push(" AND b.status = ").push_bind(status.clone());
+        }
+        if filter.available_only {
+            builder.push(" AND b.available_copy > 0");
+        }
+    })
+    .await
 }
 #[tauri::command]
-pub async fn add_book(pool: State<'_, SqlitePool>, book: Book) -> Result<i64, String> {
+pub async fn add_book(
+    pool: State<'_, SqlitePool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+    book: Book,
+) -> Result<i64, String> {
+    let session = sessions.authorize(&token, Role::Staff)?;
+
+    policy_commands::validate_collection_type(
+        book.collection_type.as_deref().unwrap_or("fiction"),
+    )?;
+
     let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
 
     // Check if ISBN already exists
@@ -29,7 +108,28 @@ pub async fn add_book(pool: State<'_, SqlitePool>, book: Book) -> Result<i64, St
         ));
     }
 
-    let res = sqlx::query("INSERT INTO books (title, author, isbn, category, publisher, published_year, rack_location, total_copy, available_copy, cover, status) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)")
+    let created = insert_book(&mut tx, book).await?;
+    let id = created.id.unwrap_or_default();
+    audit::record(
+        &mut tx,
+        &session,
+        "create",
+        "book",
+        Some(id),
+        None,
+        audit::snapshot(&created),
+    )
+    .await?;
+
+    tx.commit().await.map_err(|e| e.to_string())?;
+
+    Ok(id)
+}
+
+// Insert a book with its generated barcode and one shelf copy per
+// `total_copy`; shared by add_book and the spreadsheet import
+pub(crate) async fn insert_book(conn: &mut SqliteConnection, book: Book) -> Result<Book, String> {
+    let res = sqlx::query("INSERT INTO books (title, author, isbn, category, publisher, published_year, rack_location, total_copy, available_copy, cover, status, collection_type, price, call_number, keywords) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)")
         .bind(book.title)
         .bind(book.author)
         .bind(book.isbn)
@@ -37,33 +137,62 @@ pub async fn add_book(pool: State<'_, SqlitePool>, book: Book) -> Result<i64, St
         .bind(book.publisher)
         .bind(book.published_year)
         .bind(book.rack_location)
-        .bind(book.total_copy)
-        .bind(book.total_copy) // initial available copy is total copy
+        .bind(0)
+        .bind(0) // counters are filled in from the items below
         .bind(book.cover)
         .bind(book.status.unwrap_or_else(|| "Tersedia".to_string()))
-        .execute(&mut *tx)
+        .bind(book.collection_type.unwrap_or_else(|| "fiction".to_string()))
+        .bind(book.price)
+        .bind(book.call_number)
+        .bind(book.keywords)
+        .execute(&mut *conn)
         .await
         .map_err(|e| e.to_string())?;
 
     let id = res.last_insert_rowid();
     let year = Utc::now().format("%Y").to_string();
-    let barcode = format!("B-{}-{}", year, format!("{:04}", id));
+    let barcode = format!("B-{}-{:04}", year, id);
 
     sqlx::query("UPDATE books SET barcode = ? WHERE id = ?")
         .bind(barcode)
         .bind(id)
-        .execute(&mut *tx)
+        .execute(&mut *conn)
         .await
         .map_err(|e| e.to_string())?;
 
-    tx.commit().await.map_err(|e| e.to_string())?;
+    // One item (with its own barcode) per physical copy
+    item_commands::create_items(&mut *conn, id, book.total_copy).await?;
+    item_commands::refresh_book_stock(&mut *conn, id).await?;
 
-    Ok(id)
+    sqlx::query_as::<_, Book>("SELECT * FROM books WHERE id = ?")
+        .bind(id)
+        .fetch_one(&mut *conn)
+        .await
+        .map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-pub async fn update_book(pool: State<'_, SqlitePool>, book: Book) -> Result<(), String> {
-    sqlx::query("UPDATE books SET title = ?, author = ?, isbn = ?, category = ?, publisher = ?, published_year = ?, rack_location = ?, total_copy = ?, available_copy = ?, cover = ?, status = ? WHERE id = ?")
+pub async fn update_book(
+    pool: State<'_, SqlitePool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+    book: Book,
+) -> Result<(), String> {
+    let session = sessions.authorize(&token, Role::Staff)?;
+
+    if let Some(collection_type) = &book.collection_type {
+        policy_commands::validate_collection_type(collection_type)?;
+    }
+
+    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+
+    let before = sqlx::query_as::<_, Book>("SELECT * FROM books WHERE id = ?")
+        .bind(book.id)
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(|_| "Buku tidak ditemukan".to_string())?;
+
+    sqlx::query("UPDATE books SET title = ?, author = ?, isbn = ?, category = ?, publisher = ?, published_year = ?, rack_location = ?, cover = ?, status = ?, collection_type = COALESCE(?, collection_type), price = COALESCE(?, price), call_number = COALESCE(?, call_number), keywords = COALESCE(?, keywords) WHERE id = ?")
         .bind(book.title)
         .bind(book.author)
         .bind(book.isbn)
@@ -71,24 +200,92 @@ pub async fn update_book(pool: State<'_, SqlitePool>, book: Book) -> Result<(),
         .bind(book.publisher)
         .bind(book.published_year)
         .bind(book.rack_location)
-        .bind(book.total_copy)
-        .bind(book.available_copy)
         .bind(book.cover)
         .bind(book.status)
+        .bind(book.collection_type)
+        .bind(book.price)
+        .bind(book.call_number)
+        .bind(book.keywords)
         .bind(book.id)
-        .execute(&*pool)
+        .execute(&mut *tx)
         .await
         .map_err(|e| e.to_string())?;
 
+    // available_copy is derived from the items; total_copy adds or withdraws copies
+    let book_id = before.id.unwrap_or_default();
+    let on_loan: i64 =
+        sqlx::query_scalar("SELECT COUNT(*) FROM loans WHERE book_id = ? AND status = 'borrowed'")
+            .bind(book_id)
+            .fetch_one(&mut *tx)
+            .await
+            .map_err(|e| e.to_string())?;
+
+    if book.total_copy < on_loan {
+        return Err(format!(
+            "Jumlah eksemplar tidak boleh kurang dari {} eksemplar yang sedang dipinjam",
+            on_loan
+        ));
+    }
+
+    let owned: i64 = sqlx::query_scalar(
+        "SELECT COUNT(*) FROM book_items WHERE book_id = ? AND status NOT IN ('Hilang', 'Ditarik')",
+    )
+    .bind(book_id)
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    let change = book.total_copy - owned;
+    if change > 0 {
+        item_commands::create_items(&mut tx, book_id, change).await?;
+        hold_commands::fill_waiting_holds(&mut tx, book_id).await?;
+    } else if change < 0 {
+        let withdrawn = item_commands::withdraw_items(&mut tx, book_id, -change).await?;
+        if withdrawn < -change {
+            return Err(format!(
+                "Hanya {} eksemplar yang ada di rak, jumlah eksemplar tidak dapat dikurangi sebanyak {}",
+                withdrawn, -change
+            ));
+        }
+    }
+    item_commands::refresh_book_stock(&mut tx, book_id).await?;
+
+    let after = sqlx::query_as::<_, Book>("SELECT * FROM books WHERE id = ?")
+        .bind(before.id)
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+    audit::record(
+        &mut tx,
+        &session,
+        "update",
+        "book",
+        before.id,
+        audit::snapshot(&before),
+        audit::snapshot(&after),
+    )
+    .await?;
+
+    tx.commit().await.map_err(|e| e.to_string())?;
+
     Ok(())
 }
 
 #[tauri::command]
-pub async fn delete_book(pool: State<'_, SqlitePool>, id: i64) -> Result<(), String> {
+pub async fn delete_book(
+    pool: State<'_, SqlitePool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+    id: i64,
+) -> Result<(), String> {
+    let session = sessions.authorize(&token, Role::Admin)?;
+
+    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+
     let active_loans: i64 =
         sqlx::query_scalar("SELECT COUNT(*) FROM loans WHERE book_id = ? AND status = 'borrowed'")
             .bind(id)
-            .fetch_one(&*pool)
+            .fetch_one(&mut *tx)
             .await
             .map_err(|e| e.to_string())?;
 
@@ -96,29 +293,92 @@ pub async fn delete_book(pool: State<'_, SqlitePool>, id: i64) -> Result<(), Str
         return Err("Buku masih sedang dipinjam".to_string());
     }
 
+    let before = sqlx::query_as::<_, Book>("SELECT * FROM books WHERE id = ?")
+        .bind(id)
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(|_| "Buku tidak ditemukan".to_string())?;
+
     sqlx::query("UPDATE books SET deleted_at = CURRENT_TIMESTAMP WHERE id = ?")
         .bind(id)
-        .execute(&*pool)
+        .execute(&mut *tx)
         .await
         .map_err(|e| e.to_string())?;
 
+    audit::record(
+        &mut tx,
+        &session,
+        "delete",
+        "book",
+        Some(id),
+        audit::snapshot(&before),
+        None,
+    )
+    .await?;
+
+    tx.commit().await.map_err(|e| e.to_string())?;
+
     Ok(())
 }
 
 // Members Commands
+const MEMBER_LIST: ListSql = ListSql {
+    select: "SELECT m.*",
+    from: "FROM members m WHERE 1 = 1",
+    sort_columns: &[
+        ("name", "m.name"),
+        ("member_code", "m.member_code"),
+        ("nis", "m.nis"),
+        ("kelas", "m.kelas"),
+        ("status", "m.status"),
+        ("joined_at", "m.joined_at"),
+        ("valid_until", "m.valid_until"),
+    ],
+    default_order: "m.name ASC, m.id ASC",
+    tiebreak: "m.id",
+};
+
 #[tauri::command]
-pub async fn get_members(pool: State<'_, SqlitePool>) -> Result<Vec<Member>, String> {
-    sqlx::query_as::<_, Member>("SELECT * FROM members ORDER BY name ASC")
-        .fetch_all(&*pool)
-        .await
-        .map_err(|e| e.to_string())
+pub async fn get_members(
+    pool: State<'_, SqlitePool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+    query: Option<ListQuery<MemberFilter>>,
+) -> Result<Page<Member>, String> {
+    sessions.authorize(&token, Role::Viewer)?;
+
+    let query = query.unwrap_or_default();
+    let filter = &query.filter;
+    paging::fetch_page(&pool, &MEMBER_LIST, &query, |builder| {
+        if let Some(search) = &filter.search {
+            paging::push_like(builder, &["m.name", "m.member_code", "m.nis"], search);
+        }
+        if let Some(kelas) = &filter.kelas {
+            builder.push(" AND m.kelas = ").push_bind(kelas.clone());
+        }
+        if let Some(status) = &filter.status {
+            builder
+                .push(" AND COALESCE(m.status, 'Aktif') = ")
+                .push_bind(status.clone());
+        }
+        if let Some(member_type) = &filter.member_type {
+            builder
+                .push(" AND COALESCE(m.member_type, 'student') = ")
+                .push_bind(member_type.clone());
+        }
+    })
+    .await
 }
 
 #[tauri::command]
 pub async fn get_book_loan_count_year(
     pool: State<'_, SqlitePool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
     book_id: i64,
 ) -> Result<i64, String> {
+    sessions.authorize(&token, Role::Viewer)?;
+
     let count: i64 = sqlx::query_scalar(
         "SELECT COUNT(*) FROM loans WHERE book_id = ? AND loan_date >= date('now', '-1 year')",
     )
@@ -131,10 +391,54 @@ pub async fn get_book_loan_count_year(
 }
 
 #[tauri::command]
-pub async fn add_member(pool: State<'_, SqlitePool>, member: NewMember) -> Result<i64, String> {
+pub async fn add_member(
+    pool: State<'_, SqlitePool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+    member: NewMember,
+) -> Result<i64, String> {
+    let session = sessions.authorize(&token, Role::Staff)?;
+
+    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+
+    let created = insert_member(&mut tx, member).await?;
+    let id = created.id.unwrap_or_default();
+    audit::record(
+        &mut tx,
+        &session,
+        "create",
+        "member",
+        Some(id),
+        None,
+        audit::snapshot(&created),
+    )
+    .await?;
+
+    tx.commit().await.map_err(|e| e.to_string())?;
+
+    Ok(id)
+}
+
+fn member_write_error(e: sqlx::Error) -> String {
+    if e.to_string().contains("members.nis") {
+        "NIS sudah dipakai anggota lain".to_string()
+    } else {
+        e.to_string()
+    }
+}
+
+// Insert a member, generating a member code when none is given; shared by
+// add_member and the roster import
+pub(crate) async fn insert_member(
+    conn: &mut SqliteConnection,
+    member: NewMember,
+) -> Result<Member, String> {
+    let member_type = member.member_type.unwrap_or_else(|| "student".to_string());
+    policy_commands::validate_member_type(&member_type)?;
+
     let code = match member.member_code {
         Some(c) if !c.trim().is_empty() => c,
-        _ => internal_generate_member_code(&pool)
+        _ => internal_generate_member_code(&mut *conn)
             .await
             .map_err(|e| e.to_string())?,
     };
@@ -142,8 +446,8 @@ pub async fn add_member(pool: State<'_, SqlitePool>, member: NewMember) -> Resul
     let res = sqlx::query(
         r#"
         INSERT INTO members
-        (member_code, name, email, kelas, phone, jenis_kelamin, status)
-        VALUES (?, ?, ?, ?, ?, ?, ?)
+        (member_code, name, email, kelas, phone, jenis_kelamin, status, member_type, valid_until, nis)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
         "#,
     )
     .bind(code)
@@ -153,34 +457,57 @@ pub async fn add_member(pool: State<'_, SqlitePool>, member: NewMember) -> Resul
     .bind(member.phone)
     .bind(member.jenis_kelamin)
     .bind(member.status.unwrap_or_else(|| "Aktif".to_string()))
-    .execute(&*pool)
+    .bind(member_type)
+    .bind(member.valid_until)
+    .bind(member.nis.filter(|n| !n.trim().is_empty()))
+    .execute(&mut *conn)
     .await
-    .map_err(|e| e.to_string())?;
+    .map_err(member_write_error)?;
 
-    Ok(res.last_insert_rowid())
+    sqlx::query_as::<_, Member>("SELECT * FROM members WHERE id = ?")
+        .bind(res.last_insert_rowid())
+        .fetch_one(&mut *conn)
+        .await
+        .map_err(|e| e.to_string())
 }
 
 // Loan Logic
 #[tauri::command]
 pub async fn borrow_book(
     pool: State<'_, SqlitePool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
     book_id: i64,
     member_id: i64,
-    days: i64,
+    days: Option<i64>,
+    item_id: Option<i64>,
 ) -> Result<i64, String> {
+    let session = sessions.authorize(&token, Role::Staff)?;
+
     let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+    let loan_id = borrow(&mut tx, &session, book_id, member_id, days, item_id).await?;
+    tx.commit().await.map_err(|e| e.to_string())?;
+
+    Ok(loan_id)
+}
 
-    // Check book availability
-    let book = sqlx::query_as::<_, Book>("SELECT * FROM books WHERE id = ? AND deleted_at IS NULL")
+// Lend a copy with every eligibility and policy check of the desk; shared by
+// the desk and the self-service station
+pub(crate) async fn borrow(
+    tx: &mut SqliteConnection,
+    session: &Session,
+    book_id: i64,
+    member_id: i64,
+    days: Option<i64>,
+    item_id: Option<i64>,
+) -> Result<i64, String> {
+    // Availability is decided when a copy is claimed below, not from the cached counter
+    sqlx::query_scalar::<_, i64>("SELECT id FROM books WHERE id = ? AND deleted_at IS NULL")
         .bind(book_id)
         .fetch_one(&mut *tx)
         .await
         .map_err(|_| "Buku tidak ditemukan".to_string())?;
 
-    if book.available_copy <= 0 {
-        return Err("Stok buku habis".to_string());
-    }
-
     // Check member status
     let member_status: String = sqlx::query_scalar("SELECT status FROM members WHERE id = ?")
         .bind(member_id)
@@ -192,12 +519,52 @@ pub async fn borrow_book(
         return Err("Anggota berstatus Nonaktif tidak dapat meminjam buku".to_string());
     }
 
+    // Overdue books, unpaid fines, the loan limit and an expired membership all
+    // block the loan unless an admin granted a one-time override today
+    let blocks = eligibility_commands::blocking_reasons(&mut *tx, member_id, Some(book_id)).await?;
+    let override_id = if blocks.is_empty() {
+        None
+    } else {
+        match eligibility_commands::pending_override(&mut *tx, member_id).await? {
+            Some(id) => Some(id),
+            None => {
+                let messages: Vec<String> = blocks.into_iter().map(|b| b.message).collect();
+                return Err(format!("Peminjaman ditolak: {}", messages.join("; ")));
+            }
+        }
+    };
+
+    // Loan period comes from the member's policy for this kind of book
+    let policy = policy_commands::policy_for(&mut *tx, member_id, book_id).await?;
+
+    // A shorter period may be chosen at the desk, never a longer one
+    let days = match days {
+        Some(d) if d < 1 => return Err("Lama peminjaman minimal 1 hari".to_string()),
+        Some(d) if d > policy.loan_days => {
+            return Err(format!(
+                "Lama peminjaman maksimal {} hari untuk anggota ini",
+                policy.loan_days
+            ))
+        }
+        Some(d) => d,
+        None => policy.loan_days,
+    };
+
     // Create loan record
     let loan_date = Utc::now();
     let due_date = loan_date + Duration::days(days);
 
-    let res = sqlx::query("INSERT INTO loans (book_id, member_id, loan_date, due_date, status) VALUES (?, ?, ?, ?, 'borrowed')")
+    // A copy kept aside for this member comes first, then the scanned copy or any
+    // copy still on the shelf. Copies kept aside for other members are never lent.
+    let item_id =
+        match hold_commands::take_ready_hold(&mut *tx, member_id, book_id, item_id).await? {
+            Some(held) => held,
+            None => item_commands::claim_item(&mut *tx, book_id, item_id).await?,
+        };
+
+    let res = sqlx::query("INSERT INTO loans (book_id, item_id, member_id, loan_date, due_date, status) VALUES (?, ?, ?, ?, ?, 'borrowed')")
         .bind(book_id)
+        .bind(item_id)
         .bind(member_id)
         .bind(loan_date)
         .bind(due_date)
@@ -205,137 +572,571 @@ pub async fn borrow_book(
         .await
         .map_err(|e| e.to_string())?;
 
-    // Update book status and stock
-    sqlx::query("UPDATE books SET available_copy = available_copy - 1, status = CASE WHEN available_copy - 1 = 0 THEN 'Dipinjam' ELSE status END WHERE id = ?")
-        .bind(book_id)
-        .execute(&mut *tx)
+    item_commands::refresh_book_stock(&mut *tx, book_id).await?;
+
+    let loan_id = res.last_insert_rowid();
+    if let Some(override_id) = override_id {
+        eligibility_commands::use_override(&mut *tx, override_id, loan_id).await?;
+    }
+
+    let loan = sqlx::query_as::<_, Loan>("SELECT * FROM loans WHERE id = ?")
+        .bind(loan_id)
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+    audit::record(
+        &mut *tx,
+        session,
+        "borrow",
+        "loan",
+        Some(loan_id),
+        None,
+        audit::snapshot(&loan),
+    )
+    .await?;
+
+    Ok(loan_id)
+}
+
+#[tauri::command]
+pub async fn return_book(
+    pool: State<'_, SqlitePool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+    loan_id: i64,
+    book_condition: String,
+    damage_category: Option<String>,
+) -> Result<(), String> {
+    let session = sessions.authorize(&token, Role::Staff)?;
+
+    let mut tx = pool
+        .begin()
+        .await
+        .map_err(|e| format!("Failed to start transaction: {}", e))?;
+
+    close_loan(
+        &mut tx,
+        &session,
+        loan_id,
+        Some((&book_condition, damage_category.as_deref())),
+    )
+    .await?;
+
+    tx.commit()
+        .await
+        .map_err(|e| format!("Failed to commit transaction: {}", e))?;
+
+    Ok(())
+}
+
+// Close an open loan and charge the late fine up to now. At the desk the
+// condition the book came back in is known and the copy goes back on the
+// shelf; a self-service return passes None and the copy waits for a staff
+// inspection instead of being recorded as "Bagus".
+pub(crate) async fn close_loan(
+    tx: &mut SqliteConnection,
+    session: &Session,
+    loan_id: i64,
+    condition: Option<(&str, Option<&str>)>,
+) -> Result<Loan, String> {
+    let loan = sqlx::query_as::<_, Loan>("SELECT * FROM loans WHERE id = ?")
+        .bind(loan_id)
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(|e| format!("Loan not found or database error: {}", e))?;
+
+    if loan.status == "returned" {
+        return Err("Book already returned".to_string());
+    }
+
+    let return_date = Utc::now();
+
+    // The fine accrued so far becomes a real fine now that the book is back
+    let rules = fine_commands::FineRules::load(&mut *tx).await?;
+    let late = rules
+        .late_fine(
+            &mut *tx,
+            loan.member_id,
+            loan.book_id,
+            loan.due_date,
+            return_date,
+        )
+        .await;
+
+    if late.amount > 0 {
+        sqlx::query("INSERT INTO fines (loan_id, amount, fine_type, status) VALUES (?, ?, 'Late', 'Unpaid')")
+            .bind(loan_id)
+            .bind(late.amount)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| format!("Failed to create late fine record: {}", e))?;
+    }
+
+    if let Some((book_condition, damage_category)) = condition {
+        charge_damage_fine(&mut *tx, &loan, book_condition, damage_category).await?;
+    }
+
+    // Only an open loan can be closed, so a double return never puts a copy back twice
+    let closed = sqlx::query("UPDATE loans SET return_date = ?, status = 'returned', book_condition = ?, damage_category = ?, accrued_fine = ?, fine_accrued_at = ? WHERE id = ? AND status = 'borrowed'")
+        .bind(return_date)
+        .bind(condition.map(|(book_condition, _)| book_condition))
+        .bind(condition.and_then(|(_, damage_category)| damage_category))
+        .bind(late.amount)
+        .bind(return_date)
+        .bind(loan_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| format!("Loan couldnt update: {}", e))?;
+
+    if closed.rows_affected() == 0 {
+        return Err("Book already returned".to_string());
+    }
+
+    match condition {
+        Some((book_condition, damage_category)) => {
+            shelve_copy(&mut *tx, &loan, book_condition, damage_category).await?
+        }
+        None => {
+            if let Some(item_id) = loan.item_id {
+                item_commands::hold_for_inspection(&mut *tx, item_id).await?;
+            }
+        }
+    }
+    item_commands::refresh_book_stock(&mut *tx, loan.book_id).await?;
+
+    let returned = sqlx::query_as::<_, Loan>("SELECT * FROM loans WHERE id = ?")
+        .bind(loan_id)
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+    audit::record(
+        &mut *tx,
+        session,
+        "return",
+        "loan",
+        Some(loan_id),
+        audit::snapshot(&loan),
+        audit::snapshot(&returned),
+    )
+    .await?;
+
+    Ok(returned)
+}
+
+// Damage fines come from the fine_damage_* settings
+pub(crate) async fn charge_damage_fine(
+    conn: &mut SqliteConnection,
+    loan: &Loan,
+    book_condition: &str,
+    damage_category: Option<&str>,
+) -> Result<(), String> {
+    if book_condition != "Rusak" {
+        return Ok(());
+    }
+    let category = match damage_category {
+        Some(category) => category,
+        None => return Ok(()),
+    };
+
+    let setting_key = match category {
+        "Ringan" => "fine_damage_light",
+        "Sedang" => "fine_damage_medium",
+        "Berat" => "fine_damage_heavy",
+        _ => return Ok(()),
+    };
+
+    let fine_amount_str: String = sqlx::query_scalar("SELECT value FROM settings WHERE key = ?")
+        .bind(setting_key)
+        .fetch_one(&mut *conn)
+        .await
+        .unwrap_or_else(|_| "0".to_string());
+
+    let mut fine_amount = fine_amount_str.parse::<i64>().unwrap_or(0);
+    // Heavy damage without a set amount costs as much as replacing the copy
+    if category == "Berat" && fine_amount == 0 {
+        fine_amount = fine_commands::replacement_price(&mut *conn, loan.book_id, loan.item_id)
+            .await?
+            .unwrap_or(0);
+    }
+    let fine_type = format!("Damage {}", category);
+
+    if fine_amount >= 0 {
+        sqlx::query(
+            "INSERT INTO fines (loan_id, amount, fine_type, status) VALUES (?, ?, ?, 'Unpaid')",
+        )
+        .bind(loan.id)
+        .bind(fine_amount)
+        .bind(fine_type)
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| format!("Failed to create damage fine record: {}", e))?;
+    }
+
+    Ok(())
+}
+
+// Put the copy back on the shelf, remembering the condition it came back in,
+// and set it aside for the next member waiting for the title
+pub(crate) async fn shelve_copy(
+    conn: &mut SqliteConnection,
+    loan: &Loan,
+    book_condition: &str,
+    damage_category: Option<&str>,
+) -> Result<(), String> {
+    if let Some(item_id) = loan.item_id {
+        let item_condition = match (book_condition, damage_category) {
+            ("Rusak", Some(category)) => format!("Rusak {}", category),
+            _ => book_condition.to_string(),
+        };
+
+        item_commands::release_item(&mut *conn, item_id, &item_condition).await?;
+    }
+    hold_commands::fill_waiting_holds(&mut *conn, loan.book_id).await?;
+
+    Ok(())
+}
+
+// Close a loan whose book will not come back: the copy leaves the stock and the
+// member is charged the replacement price plus the processing fee
+#[tauri::command]
+pub async fn declare_loan_lost(
+    pool: State<'_, SqlitePool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+    loan_id: i64,
+) -> Result<i64, String> {
+    let session = sessions.authorize(&token, Role::Staff)?;
+
+    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+
+    let loan = sqlx::query_as::<_, Loan>("SELECT * FROM loans WHERE id = ?")
+        .bind(loan_id)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| "Peminjaman tidak ditemukan".to_string())?;
+
+    if loan.status != "borrowed" {
+        return Err("Hanya peminjaman aktif yang dapat dinyatakan hilang".to_string());
+    }
+
+    let price = fine_commands::replacement_price(&mut tx, loan.book_id, loan.item_id)
+        .await?
+        .ok_or_else(|| {
+            "Harga buku belum diisi, lengkapi harga eksemplar atau buku terlebih dahulu".to_string()
+        })?;
+    let processing_fee =
+        settings_commands::get_setting_i64(&mut *tx, "fine_lost_processing_fee", 0).await;
+
+    let now = Utc::now();
+
+    // Lateness up to today is charged as it would be on a normal return
+    let rules = fine_commands::FineRules::load(&mut tx).await?;
+    let late = rules
+        .late_fine(&mut tx, loan.member_id, loan.book_id, loan.due_date, now)
+        .await;
+
+    if late.amount > 0 {
+        sqlx::query("INSERT INTO fines (loan_id, amount, fine_type, status) VALUES (?, ?, 'Late', 'Unpaid')")
+            .bind(loan_id)
+            .bind(late.amount)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| e.to_string())?;
+    }
+
+    let res = sqlx::query(
+        "INSERT INTO fines (loan_id, amount, fine_type, status) VALUES (?, ?, ?, 'Unpaid')",
+    )
+    .bind(loan_id)
+    .bind(price + processing_fee)
+    .bind(fine_commands::FINE_LOST)
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| e.to_string())?;
+    let fine_id = res.last_insert_rowid();
+
+    let closed = sqlx::query("UPDATE loans SET status = 'lost', lost_at = ?, accrued_fine = ?, fine_accrued_at = ? WHERE id = ? AND status = 'borrowed'")
+        .bind(now)
+        .bind(late.amount)
+        .bind(now)
+        .bind(loan_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    if closed.rows_affected() == 0 {
+        return Err("Hanya peminjaman aktif yang dapat dinyatakan hilang".to_string());
+    }
+
+    if let Some(item_id) = loan.item_id {
+        item_commands::mark_item_lost(&mut tx, item_id).await?;
+    }
+    item_commands::refresh_book_stock(&mut tx, loan.book_id).await?;
+
+    let lost = sqlx::query_as::<_, Loan>("SELECT * FROM loans WHERE id = ?")
+        .bind(loan_id)
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+    audit::record(
+        &mut tx,
+        &session,
+        "lost",
+        "loan",
+        Some(loan_id),
+        audit::snapshot(&loan),
+        audit::snapshot(&lost),
+    )
+    .await?;
+
+    tx.commit().await.map_err(|e| e.to_string())?;
+
+    Ok(fine_id)
+}
+
+// The lost book turned up: the replacement fine is cancelled (paid money is
+// refunded), the copy goes back into stock and the loan is closed as returned.
+// Returns the amount to refund to the member.
+#[tauri::command]
+pub async fn mark_loan_found(
+    pool: State<'_, SqlitePool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+    loan_id: i64,
+    book_condition: Option<String>,
+) -> Result<i64, String> {
+    let session = sessions.authorize(&token, Role::Staff)?;
+
+    let book_condition = book_condition.unwrap_or_else(|| "Bagus".to_string());
+
+    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+
+    let loan = sqlx::query_as::<_, Loan>("SELECT * FROM loans WHERE id = ?")
+        .bind(loan_id)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| "Peminjaman tidak ditemukan".to_string())?;
+
+    if loan.status != "lost" {
+        return Err("Peminjaman ini tidak berstatus hilang".to_string());
+    }
+
+    let lost_fines: Vec<i64> = sqlx::query_scalar(
+        "SELECT id FROM fines WHERE loan_id = ? AND fine_type = ? AND status != 'Cancelled'",
+    )
+    .bind(loan_id)
+    .bind(fine_commands::FINE_LOST)
+    .fetch_all(&mut *tx)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    let mut refunded = 0;
+    for fine_id in lost_fines {
+        refunded +=
+            fine_commands::cancel_fine(&mut tx, fine_id, "Buku ditemukan", session.user_id).await?;
+    }
+
+    let now = Utc::now();
+    sqlx::query("UPDATE loans SET status = 'returned', return_date = ?, found_at = ?, book_condition = ? WHERE id = ?")
+        .bind(now)
+        .bind(now)
+        .bind(&book_condition)
+        .bind(loan_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    // The copy may have been written off in the meantime; then it comes back as a new one
+    let restored = match loan.item_id {
+        Some(item_id) => {
+            item_commands::restore_lost_item(&mut tx, item_id, &book_condition).await?
+        }
+        None => false,
+    };
+    if !restored {
+        item_commands::create_items(&mut tx, loan.book_id, 1).await?;
+    }
+    hold_commands::fill_waiting_holds(&mut tx, loan.book_id).await?;
+    item_commands::refresh_book_stock(&mut tx, loan.book_id).await?;
+
+    let found = sqlx::query_as::<_, Loan>("SELECT * FROM loans WHERE id = ?")
+        .bind(loan_id)
+        .fetch_one(&mut *tx)
         .await
         .map_err(|e| e.to_string())?;
+    let mut after = audit::snapshot(&found);
+    if let Some(serde_json::Value::Object(map)) = after.as_mut() {
+        map.insert("refunded".to_string(), serde_json::json!(refunded));
+    }
+    audit::record(
+        &mut tx,
+        &session,
+        "found",
+        "loan",
+        Some(loan_id),
+        audit::snapshot(&loan),
+        after,
+    )
+    .await?;
 
     tx.commit().await.map_err(|e| e.to_string())?;
 
-    Ok(res.last_insert_rowid())
+    Ok(refunded)
 }
 
 #[tauri::command]
-pub async fn return_book(
+pub async fn renew_loan(
     pool: State<'_, SqlitePool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
     loan_id: i64,
-    book_condition: String,
-    damage_category: Option<String>,
-) -> Result<(), String> {
-    let mut tx = pool
-        .begin()
-        .await
-        .map_err(|e| format!("Failed to start transaction: {}", e))?;
+) -> Result<Loan, String> {
+    let session = sessions.authorize(&token, Role::Staff)?;
+
+    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
 
     let loan = sqlx::query_as::<_, Loan>("SELECT * FROM loans WHERE id = ?")
         .bind(loan_id)
-        .fetch_one(&mut *tx)
+        .fetch_optional(&mut *tx)
         .await
-        .map_err(|e| format!("Loan not found or database error: {}", e))?;
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| "Data peminjaman tidak ditemukan".to_string())?;
 
-    if loan.status == "returned" {
-        return Err("Book already returned".to_string());
+    if loan.status != "borrowed" {
+        return Err("Hanya peminjaman aktif yang dapat diperpanjang".to_string());
     }
 
-    let return_date = Utc::now();
+    let policy = policy_commands::policy_for(&mut tx, loan.member_id, loan.book_id).await?;
+    if loan.renewal_count >= policy.max_renewals {
+        return Err(if policy.max_renewals == 0 {
+            "Buku ini tidak dapat diperpanjang untuk anggota ini".to_string()
+        } else {
+            format!(
+                "Peminjaman sudah diperpanjang {} kali (batas {} kali)",
+                loan.renewal_count, policy.max_renewals
+            )
+        });
+    }
 
-    // Check for late fine
-    if return_date > loan.due_date {
-        let diff = return_date - loan.due_date;
-        let days_late = diff.num_days();
-
-        if days_late > 0 {
-            // Get fine rate from settings
-            let fine_rate_str: String =
-                sqlx::query_scalar("SELECT value FROM settings WHERE key = 'fine_late_per_day'")
-                    .fetch_one(&mut *tx)
-                    .await
-                    .unwrap_or_else(|_| "1000".to_string());
-
-            let fine_rate = fine_rate_str.parse::<i64>().unwrap_or(1000);
-            let total_fine = days_late * fine_rate;
-
-            if total_fine > 0 {
-                sqlx::query("INSERT INTO fines (loan_id, amount, fine_type, status) VALUES (?, ?, 'Late', 'Unpaid')")
-                    .bind(loan_id)
-                    .bind(total_fine)
-                    .execute(&mut *tx)
-                    .await
-                    .map_err(|e| format!("Failed to create late fine record: {}", e))?;
-            }
-        }
+    let now = Utc::now();
+    let overdue_limit =
+        settings_commands::get_setting_i64(&mut *tx, "renewal_overdue_limit_days", 0).await;
+    let days_late = (now - loan.due_date).num_days();
+    if now - loan.due_date > Duration::days(overdue_limit) {
+        return Err(format!(
+            "Peminjaman terlambat {} hari dan tidak dapat diperpanjang, kembalikan buku terlebih dahulu",
+            days_late.max(1)
+        ));
     }
 
-    // Check for damage fine
-    if book_condition == "Rusak" {
-        if let Some(category) = &damage_category {
-            let setting_key = match category.as_str() {
-                "Ringan" => "fine_damage_light",
-                "Sedang" => "fine_damage_medium",
-                "Berat" => "fine_damage_heavy",
-                _ => "",
-            };
-
-            if !setting_key.is_empty() {
-                let fine_amount_str: String =
-                    sqlx::query_scalar("SELECT value FROM settings WHERE key = ?")
-                        .bind(setting_key)
-                        .fetch_one(&mut *tx)
-                        .await
-                        .unwrap_or_else(|_| "0".to_string());
-
-                let fine_amount = fine_amount_str.parse::<i64>().unwrap_or(0);
-                let fine_type = format!("Damage {}", category);
-
-                if fine_amount >= 0 {
-                    sqlx::query("INSERT INTO fines (loan_id, amount, fine_type, status) VALUES (?, ?, ?, 'Unpaid')")
-                        .bind(loan_id)
-                        .bind(fine_amount)
-                        .bind(fine_type)
-                        .execute(&mut *tx)
-                        .await
-                        .map_err(|e| format!("Failed to create damage fine record: {}", e))?;
-                }
-            }
-        }
+    let unpaid_fines: i64 = sqlx::query_scalar(
+        "SELECT COUNT(*) FROM fines f JOIN loans l ON f.loan_id = l.id WHERE l.member_id = ? AND f.status = 'Unpaid'",
+    )
+    .bind(loan.member_id)
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    if unpaid_fines > 0 {
+        return Err("Anggota masih memiliki denda yang belum dibayar".to_string());
     }
 
-    sqlx::query("UPDATE loans SET return_date = ?, status = 'returned', book_condition = ?, damage_category = ? WHERE id = ?")
-        .bind(return_date)
-        .bind(&book_condition)
-        .bind(&damage_category)
-        .bind(loan_id)
-        .execute(&mut *tx)
-        .await
-        .map_err(|e| format!("Loan couldnt update: {}", e))?;
+    let waiting_holds: i64 =
+        sqlx::query_scalar("SELECT COUNT(*) FROM holds WHERE book_id = ? AND status = 'waiting'")
+            .bind(loan.book_id)
+            .fetch_one(&mut *tx)
+            .await
+            .map_err(|e| e.to_string())?;
+
+    if waiting_holds > 0 {
+        return Err(
+            "Buku ini sedang ditunggu anggota lain dan tidak dapat diperpanjang".to_string(),
+        );
+    }
+
+    // The new period starts from the current due date, or today if that has passed
+    let new_due_date = loan.due_date.max(now) + Duration::days(policy.loan_days);
 
     sqlx::query(
-        "UPDATE books SET available_copy = available_copy + 1, status = CASE WHEN available_copy + 1 > 0 THEN 'Tersedia' ELSE status END WHERE id = ?",
+        "INSERT INTO loan_renewals (loan_id, previous_due_date, new_due_date, renewed_by) VALUES (?, ?, ?, ?)",
     )
-    .bind(loan.book_id)
+    .bind(loan_id)
+    .bind(loan.due_date)
+    .bind(new_due_date)
+    .bind(session.user_id)
     .execute(&mut *tx)
     .await
-    .map_err(|e| format!("Book update failed: {}", e))?;
+    .map_err(|e| e.to_string())?;
 
-    tx.commit()
+    sqlx::query("UPDATE loans SET due_date = ?, renewal_count = renewal_count + 1 WHERE id = ?")
+        .bind(new_due_date)
+        .bind(loan_id)
+        .execute(&mut *tx)
         .await
-        .map_err(|e| format!("Failed to commit transaction: {}", e))?;
+        .map_err(|e| e.to_string())?;
 
-    Ok(())
+    let renewed = sqlx::query_as::<_, Loan>("SELECT * FROM loans WHERE id = ?")
+        .bind(loan_id)
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+    audit::record(
+        &mut tx,
+        &session,
+        "renew",
+        "loan",
+        Some(loan_id),
+        audit::snapshot(&loan),
+        audit::snapshot(&renewed),
+    )
+    .await?;
+
+    tx.commit().await.map_err(|e| e.to_string())?;
+
+    Ok(renewed)
+}
+
+#[tauri::command]
+pub async fn get_loan_renewals(
+    pool: State<'_, SqlitePool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+    loan_id: i64,
+) -> Result<Vec<LoanRenewal>, String> {
+    sessions.authorize(&token, Role::Viewer)?;
+
+    sqlx::query_as::<_, LoanRenewal>(
+        r#"
+        SELECT r.id, r.loan_id, r.previous_due_date, r.new_due_date, r.renewed_by,
+            u.name as renewed_by_name, r.renewed_at
+        FROM loan_renewals r
+        LEFT JOIN users u ON r.renewed_by = u.id
+        WHERE r.loan_id = ?
+        ORDER BY r.renewed_at ASC, r.id ASC
+        "#,
+    )
+    .bind(loan_id)
+    .fetch_all(&*pool)
+    .await
+    .map_err(|e| e.to_string())
 }
 
 #[tauri::command]
 pub async fn get_recent_returns(
     pool: State<'_, SqlitePool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
     limit: Option<i64>,
 ) -> Result<Vec<LoanDetail>, String> {
+    sessions.authorize(&token, Role::Viewer)?;
+
     let limit_val = limit.unwrap_or(10);
 
     let sql = r#"
         SELECT 
-            l.id, l.book_id, l.member_id,
+            l.id, l.book_id, l.member_id, l.item_id, bi.barcode as item_barcode, l.renewal_count,
             b.title as book_title, b.isbn as book_isbn, b.cover as book_cover,
             m.name as member_name, m.member_code, m.status as member_status, m.kelas as member_kelas,
             l.loan_date, l.due_date, l.status,
@@ -343,6 +1144,7 @@ pub async fn get_recent_returns(
         FROM loans l
         JOIN books b ON l.book_id = b.id
         JOIN members m ON l.member_id = m.id
+        LEFT JOIN book_items bi ON l.item_id = bi.id
         WHERE l.status = 'returned' AND l.return_date IS NOT NULL AND b.deleted_at IS NULL
         ORDER BY l.return_date DESC
         LIMIT ?
@@ -357,10 +1159,16 @@ pub async fn get_recent_returns(
     Ok(returns)
 }
 
-#[tauri::command]
-pub async fn get_active_loans(pool: State<'_, SqlitePool>) -> Result<Vec<LoanWithDetails>, String> {
-    sqlx::query_as::<_, LoanWithDetails>(
-        r#"
+const LOAN_SORT_COLUMNS: &[(&str, &str)] = &[
+    ("loan_date", "l.loan_date"),
+    ("due_date", "l.due_date"),
+    ("book_title", "b.title"),
+    ("member_name", "m.name"),
+    ("member_kelas", "m.kelas"),
+];
+
+const ACTIVE_LOAN_LIST: ListSql = ListSql {
+    select: r#"
         SELECT 
             l.id, 
             b.title as book_title, 
@@ -368,17 +1176,46 @@ pub async fn get_active_loans(pool: State<'_, SqlitePool>) -> Result<Vec<LoanWit
             l.loan_date, 
             l.due_date, 
             l.return_date, 
-            l.status
+            l.status"#,
+    from: r#"
         FROM loans l
         JOIN books b ON l.book_id = b.id
         JOIN members m ON l.member_id = m.id
-        WHERE l.status = 'borrowed' AND b.deleted_at IS NULL
-        ORDER BY l.loan_date DESC
-        "#,
-    )
-    .fetch_all(&*pool)
+        WHERE l.status = 'borrowed' AND b.deleted_at IS NULL"#,
+    sort_columns: LOAN_SORT_COLUMNS,
+    default_order: "l.loan_date DESC, l.id DESC",
+    tiebreak: "l.id",
+};
+
+fn push_loan_filters(builder: &mut QueryBuilder<'_, Sqlite>, filter: &LoanFilter) {
+    if let Some(search) = &filter.search {
+        paging::push_like(builder, &["b.title", "m.name", "m.member_code"], search);
+    }
+    if let Some(member_id) = filter.member_id {
+        builder.push(" AND l.member_id = ").push_bind(member_id);
+    }
+    if let Some(book_id) = filter.book_id {
+        builder.push(" AND l.book_id = ").push_bind(book_id);
+    }
+    if let Some(kelas) = &filter.kelas {
+        builder.push(" AND m.kelas = ").push_bind(kelas.clone());
+    }
+}
+
+#[tauri::command]
+pub async fn get_active_loans(
+    pool: State<'_, SqlitePool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+    query: Option<ListQuery<LoanFilter>>,
+) -> Result<Page<LoanWithDetails>, String> {
+    sessions.authorize(&token, Role::Viewer)?;
+
+    let query = query.unwrap_or_default();
+    paging::fetch_page(&pool, &ACTIVE_LOAN_LIST, &query, |builder| {
+        push_loan_filters(builder, &query.filter)
+    })
     .await
-    .map_err(|e| e.to_string())
 }
 
 #[derive(Debug, serde::Serialize, serde::Deserialize, sqlx::FromRow)]
@@ -393,7 +1230,11 @@ pub struct RecentActivity {
 #[tauri::command]
 pub async fn get_recent_activity(
     pool: tauri::State<'_, sqlx::SqlitePool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
 ) -> Result<Vec<RecentActivity>, String> {
+    sessions.authorize(&token, Role::Viewer)?;
+
     // This query combines latest loans and latest new members using UNION
     let activities = sqlx::query_as::<_, RecentActivity>(
         r#"
@@ -435,7 +1276,11 @@ pub struct DailyStats {
 #[tauri::command]
 pub async fn get_weekly_circulation(
     pool: tauri::State<'_, sqlx::SqlitePool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
 ) -> Result<Vec<DailyStats>, String> {
+    sessions.authorize(&token, Role::Viewer)?;
+
     let days = vec![
         "Minggu", "Senin", "Selasa", "Rabu", "Kamis", "Jumat", "Sabtu",
     ];
@@ -468,6 +1313,7 @@ pub struct Stats {
     pub overdue_loans: i64,
     pub monthly_new_members: i64,
     pub total_loans_count: i64,
+    pub visitors_today: i64,
 }
 
 #[derive(Debug, serde::Serialize, serde::Deserialize, sqlx::FromRow)]
@@ -491,7 +1337,13 @@ pub struct MemberStats {
 }
 
 #[tauri::command]
-pub async fn get_stats(pool: tauri::State<'_, sqlx::SqlitePool>) -> Result<Stats, String> {
+pub async fn get_stats(
+    pool: tauri::State<'_, sqlx::SqlitePool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+) -> Result<Stats, String> {
+    sessions.authorize(&token, Role::Viewer)?;
+
     let total_books: i64 =
         sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM books WHERE deleted_at IS NULL")
             .fetch_one(&*pool)
@@ -530,6 +1382,13 @@ pub async fn get_stats(pool: tauri::State<'_, sqlx::SqlitePool>) -> Result<Stats
         .await
         .map_err(|e| e.to_string())?;
 
+    let visitors_today: i64 = sqlx::query_scalar(
+        "SELECT COUNT(*) FROM visits WHERE date(checked_in_at, 'localtime') = date('now', 'localtime')",
+    )
+    .fetch_one(&*pool)
+    .await
+    .map_err(|e| e.to_string())?;
+
     Ok(Stats {
         total_books,
         total_members,
@@ -537,35 +1396,111 @@ pub async fn get_stats(pool: tauri::State<'_, sqlx::SqlitePool>) -> Result<Stats
         overdue_loans,
         monthly_new_members,
         total_loans_count,
+        visitors_today,
     })
 }
 
 #[tauri::command]
 pub async fn login(
     pool: tauri::State<'_, sqlx::SqlitePool>,
+    sessions: State<'_, SessionStore>,
     username: String,
     password: String,
+) -> Result<AuthSession, String> {
+    let mut conn = pool.acquire().await.map_err(|e| e.to_string())?;
+    let user = authenticate(&mut conn, &username, &password).await?;
+
+    let token = sessions.create(&user)?;
+    Ok(AuthSession { token, user })
+}
+
+// Check a username and password with the same lockout and login history as
+// the login screen
+pub(crate) async fn authenticate(
+    conn: &mut SqliteConnection,
+    username: &str,
+    password: &str,
 ) -> Result<User, String> {
+    auth::check_lockout(&mut *conn, username).await?;
+
+    let credentials: Option<(i64, String)> =
+        sqlx::query_as("SELECT id, password FROM users WHERE username = ?")
+            .bind(username)
+            .fetch_optional(&mut *conn)
+            .await
+            .map_err(|e| e.to_string())?;
+
+    let (user_id, stored_password) = match credentials {
+        Some(c) if auth::verify_password(password, &c.1) => c,
+        other => {
+            if other.is_none() {
+                auth::verify_password(password, auth::DUMMY_PASSWORD_HASH);
+            }
+            let known_id = other.map(|c| c.0);
+            let locked_for =
+                auth::register_failed_login(&mut *conn, username, known_id, "bad_credentials")
+                    .await?;
+            tokio::time::sleep(std::time::Duration::from_millis(
+                auth::FAILED_LOGIN_DELAY_MS,
+            ))
+            .await;
+
+            return Err(match locked_for {
+                Some(minutes) => format!(
+                    "Terlalu banyak percobaan gagal. Akun dikunci selama {} menit",
+                    minutes
+                ),
+                None => "Username atau password salah".to_string(),
+            });
+        }
+    };
+
+    // Upgrade plaintext passwords to a hash on first successful login
+    if auth::is_legacy_password(&stored_password) {
+        let hashed = auth::hash_password(password)?;
+        sqlx::query("UPDATE users SET password = ? WHERE id = ?")
+            .bind(hashed)
+            .bind(user_id)
+            .execute(&mut *conn)
+            .await
+            .map_err(|e| e.to_string())?;
+    }
+
     let user = sqlx::query_as::<sqlx::Sqlite, User>(
-        "SELECT id, username, name, role, avatar FROM users WHERE username = ? AND password = ?",
+        "SELECT id, username, name, role, avatar, status FROM users WHERE id = ?",
     )
-    .bind(username)
-    .bind(password)
-    .fetch_optional(&*pool)
+    .bind(user_id)
+    .fetch_one(&mut *conn)
     .await
     .map_err(|e| e.to_string())?;
 
-    match user {
-        Some(u) => Ok(u),
-        None => Err("Username atau password salah".to_string()),
+    if user.status == "Nonaktif" {
+        auth::record_login_event(&mut *conn, username, Some(user_id), false, Some("disabled"))
+            .await?;
+        return Err("Akun ini telah dinonaktifkan, hubungi administrator".to_string());
     }
+
+    auth::clear_failed_logins(&mut *conn, username).await?;
+    auth::record_login_event(&mut *conn, username, Some(user_id), true, None).await?;
+
+    Ok(user)
+}
+
+#[tauri::command]
+pub async fn logout(sessions: State<'_, SessionStore>, token: String) -> Result<(), String> {
+    sessions.revoke(&token);
+    Ok(())
 }
 
 #[tauri::command]
 pub async fn find_member_by_code(
     pool: State<'_, SqlitePool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
     member_code: String,
 ) -> Result<Member, String> {
+    sessions.authorize(&token, Role::Viewer)?;
+
     let member = sqlx::query_as::<_, Member>("SELECT * FROM members WHERE member_code = ?")
         .bind(member_code)
         .fetch_optional(&*pool)
@@ -579,19 +1514,54 @@ pub async fn find_member_by_code(
 }
 
 #[tauri::command]
-pub async fn find_book_by_isbn(pool: State<'_, SqlitePool>, isbn: String) -> Result<Book, String> {
-    let book =
-        sqlx::query_as::<_, Book>("SELECT * FROM books WHERE (isbn = ? OR barcode = ?) AND deleted_at IS NULL ORDER BY id ASC LIMIT 1")
-            .bind(&isbn)
-            .bind(&isbn)
-            .fetch_optional(&*pool)
-            .await
-            .map_err(|e| e.to_string())?;
+pub async fn find_book_by_isbn(
+    pool: State<'_, SqlitePool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+    isbn: String,
+) -> Result<Book, String> {
+    sessions.authorize(&token, Role::Viewer)?;
+
+    let mut conn = pool.acquire().await.map_err(|e| e.to_string())?;
+
+    // A scanned copy barcode must point at a copy that is on the shelf or kept
+    // aside for pickup (borrow_book checks who it is kept for)
+    if let Some(item) = item_commands::find_item_by_barcode(&mut conn, isbn.trim()).await? {
+        if item.status != item_commands::ITEM_AVAILABLE
+            && item.status != item_commands::ITEM_ON_HOLD
+        {
+            return Err(format!(
+                "Eksemplar {} berstatus {}",
+                item.barcode, item.status
+            ));
+        }
+    }
+
+    let book = sqlx::query_as::<_, Book>(
+        "SELECT * FROM books WHERE (isbn = ? OR barcode = ? OR id IN (SELECT book_id FROM book_items WHERE barcode = ?)) AND deleted_at IS NULL ORDER BY id ASC LIMIT 1",
+    )
+    .bind(&isbn)
+    .bind(&isbn)
+    .bind(isbn.trim())
+    .fetch_optional(&mut *conn)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    let ready_holds: i64 = match &book {
+        Some(b) => {
+            sqlx::query_scalar("SELECT COUNT(*) FROM holds WHERE book_id = ? AND status = 'ready'")
+                .bind(b.id)
+                .fetch_one(&mut *conn)
+                .await
+                .map_err(|e| e.to_string())?
+        }
+        None => 0,
+    };
 
     match book {
         Some(b) => {
-            if b.available_copy <= 0 {
-                Err("Stok buku habis".to_string())
+            if b.available_copy <= 0 && ready_holds == 0 {
+                Err("Stok buku habis, anggota dapat melakukan reservasi".to_string())
             } else if b.status.as_deref().unwrap_or("Tersedia") == "Tidak Tersedia" {
                 Err("Buku sedang tidak tersedia (Non-Aktif)".to_string())
             } else {
@@ -607,6 +1577,9 @@ pub struct LoanDetail {
     pub id: i64,
     pub book_id: i64,
     pub member_id: i64,
+    pub item_id: Option<i64>,
+    pub item_barcode: Option<String>,
+    pub renewal_count: i64,
     pub book_title: String,
     pub book_isbn: String,
     pub book_cover: Option<String>,
@@ -623,11 +1596,15 @@ pub struct LoanDetail {
 #[tauri::command]
 pub async fn find_active_loan(
     pool: State<'_, SqlitePool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
     query: String,
 ) -> Result<Vec<LoanDetail>, String> {
+    sessions.authorize(&token, Role::Viewer)?;
+
     let sql = r#"
         SELECT 
-            l.id, l.book_id, l.member_id,
+            l.id, l.book_id, l.member_id, l.item_id, bi.barcode as item_barcode, l.renewal_count,
             b.title as book_title, b.isbn as book_isbn, b.cover as book_cover,
             m.name as member_name, m.member_code, COALESCE(m.status, 'Aktif') as member_status, m.kelas as member_kelas,
             l.loan_date, l.due_date, l.status,
@@ -635,10 +1612,12 @@ pub async fn find_active_loan(
         FROM loans l
         JOIN books b ON l.book_id = b.id
         JOIN members m ON l.member_id = m.id
+        LEFT JOIN book_items bi ON l.item_id = bi.id
         WHERE l.status = 'borrowed' AND b.deleted_at IS NULL
         AND (
             b.isbn = ? OR 
             b.barcode = ? OR 
+            bi.barcode = ? OR
             m.member_code = ? OR
             m.name LIKE ?
         )
@@ -649,6 +1628,7 @@ pub async fn find_active_loan(
         .bind(&query)
         .bind(&query)
         .bind(&query)
+        .bind(&query)
         .bind(format!("%{}%", query))
         .fetch_all(&*pool)
         .await
@@ -661,33 +1641,50 @@ pub async fn find_active_loan(
     Ok(loans)
 }
 
-#[tauri::command]
-pub async fn get_overdue_loans(pool: State<'_, SqlitePool>) -> Result<Vec<LoanDetail>, String> {
-    let sql = r#"
+const OVERDUE_LOAN_LIST: ListSql = ListSql {
+    select: r#"
         SELECT 
-            l.id, l.book_id, l.member_id,
+            l.id, l.book_id, l.member_id, l.item_id, bi.barcode as item_barcode, l.renewal_count,
             b.title as book_title, b.isbn as book_isbn, b.cover as book_cover,
             m.name as member_name, m.member_code, COALESCE(m.status, 'Aktif') as member_status, m.kelas as member_kelas,
             l.loan_date, l.due_date, l.status,
-            (SELECT COUNT(*) FROM loans WHERE member_id = m.id AND status = 'borrowed') as member_active_loans
+            (SELECT COUNT(*) FROM loans WHERE member_id = m.id AND status = 'borrowed') as member_active_loans"#,
+    from: r#"
         FROM loans l
         JOIN books b ON l.book_id = b.id
         JOIN members m ON l.member_id = m.id
-        WHERE l.status = 'borrowed' AND l.due_date < CURRENT_TIMESTAMP AND b.deleted_at IS NULL
-        ORDER BY l.due_date ASC
-    "#;
+        LEFT JOIN book_items bi ON l.item_id = bi.id
+        WHERE l.status = 'borrowed' AND l.due_date < CURRENT_TIMESTAMP AND b.deleted_at IS NULL"#,
+    sort_columns: LOAN_SORT_COLUMNS,
+    default_order: "l.due_date ASC, l.id ASC",
+    tiebreak: "l.id",
+};
 
-    sqlx::query_as::<_, LoanDetail>(sql)
-        .fetch_all(&*pool)
-        .await
-        .map_err(|e| e.to_string())
+#[tauri::command]
+pub async fn get_overdue_loans(
+    pool: State<'_, SqlitePool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+    query: Option<ListQuery<LoanFilter>>,
+) -> Result<Page<LoanDetail>, String> {
+    sessions.authorize(&token, Role::Viewer)?;
+
+    let query = query.unwrap_or_default();
+    paging::fetch_page(&pool, &OVERDUE_LOAN_LIST, &query, |builder| {
+        push_loan_filters(builder, &query.filter)
+    })
+    .await
 }
 
 #[tauri::command]
 pub async fn get_member_loans(
     pool: State<'_, SqlitePool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
     member_id: i64,
 ) -> Result<Vec<MemberLoanInfo>, String> {
+    sessions.authorize(&token, Role::Viewer)?;
+
     let loans = sqlx::query_as::<_, MemberLoanInfo>(
         r#"
         SELECT 
@@ -730,8 +1727,12 @@ pub struct MemberBorrowingHistoryItem {
 #[tauri::command]
 pub async fn get_member_borrowing_history(
     pool: State<'_, SqlitePool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
     member_id: i64,
 ) -> Result<Vec<MemberBorrowingHistoryItem>, String> {
+    sessions.authorize(&token, Role::Viewer)?;
+
     let loans = sqlx::query_as::<_, MemberBorrowingHistoryItem>(
         r#"
         SELECT 
@@ -762,8 +1763,12 @@ pub async fn get_member_borrowing_history(
 #[tauri::command]
 pub async fn get_member_stats(
     pool: State<'_, SqlitePool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
     member_id: i64,
 ) -> Result<MemberStats, String> {
+    sessions.authorize(&token, Role::Viewer)?;
+
     let total_loans_30_days: i64 = sqlx::query_scalar(
         r#"
         SELECT COUNT(*) 
@@ -823,8 +1828,12 @@ pub async fn get_member_stats(
 #[tauri::command]
 pub async fn get_member_active_loan_count(
     pool: State<'_, SqlitePool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
     member_id: i64,
 ) -> Result<i64, String> {
+    sessions.authorize(&token, Role::Viewer)?;
+
     let count: i64 = sqlx::query_scalar(
         "SELECT COUNT(*) FROM loans WHERE member_id = ? AND status = 'borrowed'",
     )
@@ -838,7 +1847,11 @@ pub async fn get_member_active_loan_count(
 #[tauri::command]
 pub async fn get_monthly_new_members(
     pool: tauri::State<'_, sqlx::SqlitePool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
 ) -> Result<i64, String> {
+    sessions.authorize(&token, Role::Viewer)?;
+
     let count: i64 = sqlx::query_scalar(
         r#"
         SELECT COUNT(*) 
@@ -855,18 +1868,67 @@ pub async fn get_monthly_new_members(
 #[tauri::command]
 pub async fn delete_member(
     pool: tauri::State<'_, sqlx::SqlitePool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
     member_id: i64,
 ) -> Result<(), String> {
+    let session = sessions.authorize(&token, Role::Admin)?;
+
+    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+
+    let before = sqlx::query_as::<_, Member>("SELECT * FROM members WHERE id = ?")
+        .bind(member_id)
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(|_| "Anggota tidak ditemukan".to_string())?;
+
     sqlx::query("UPDATE members SET status = 'Nonaktif' WHERE id = ?")
         .bind(member_id)
-        .execute(&*pool)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let after = sqlx::query_as::<_, Member>("SELECT * FROM members WHERE id = ?")
+        .bind(member_id)
+        .fetch_one(&mut *tx)
         .await
         .map_err(|e| e.to_string())?;
+    audit::record(
+        &mut tx,
+        &session,
+        "deactivate",
+        "member",
+        Some(member_id),
+        audit::snapshot(&before),
+        audit::snapshot(&after),
+    )
+    .await?;
+
+    tx.commit().await.map_err(|e| e.to_string())?;
 
     Ok(())
 }
 #[tauri::command]
-pub async fn update_member(pool: State<'_, SqlitePool>, member: Member) -> Result<(), String> {
+pub async fn update_member(
+    pool: State<'_, SqlitePool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+    member: Member,
+) -> Result<(), String> {
+    let session = sessions.authorize(&token, Role::Staff)?;
+
+    if let Some(member_type) = &member.member_type {
+        policy_commands::validate_member_type(member_type)?;
+    }
+
+    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+
+    let before = sqlx::query_as::<_, Member>("SELECT * FROM members WHERE id = ?")
+        .bind(member.id)
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(|_| "Anggota tidak ditemukan".to_string())?;
+
     sqlx::query(
         r#"
         UPDATE members SET
@@ -876,7 +1938,10 @@ pub async fn update_member(pool: State<'_, SqlitePool>, member: Member) -> Resul
             phone = ?,
             kelas = ?,
             jenis_kelamin = ?,
-            status = ?
+            status = ?,
+            member_type = COALESCE(?, member_type),
+            valid_until = ?,
+            nis = COALESCE(?, nis)
         WHERE id = ?
         "#,
     )
@@ -887,22 +1952,50 @@ pub async fn update_member(pool: State<'_, SqlitePool>, member: Member) -> Resul
     .bind(member.kelas)
     .bind(member.jenis_kelamin)
     .bind(member.status)
+    .bind(member.member_type)
+    .bind(member.valid_until)
+    .bind(member.nis.filter(|n| !n.trim().is_empty()))
     .bind(member.id)
-    .execute(&*pool)
+    .execute(&mut *tx)
     .await
-    .map_err(|e| e.to_string())?;
+    .map_err(member_write_error)?;
+
+    let after = sqlx::query_as::<_, Member>("SELECT * FROM members WHERE id = ?")
+        .bind(before.id)
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+    audit::record(
+        &mut tx,
+        &session,
+        "update",
+        "member",
+        before.id,
+        audit::snapshot(&before),
+        audit::snapshot(&after),
+    )
+    .await?;
+
+    tx.commit().await.map_err(|e| e.to_string())?;
 
     Ok(())
 }
 
 #[tauri::command]
-pub async fn generate_member_code(pool: State<'_, SqlitePool>) -> Result<String, String> {
-    internal_generate_member_code(&pool)
+pub async fn generate_member_code(
+    pool: State<'_, SqlitePool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+) -> Result<String, String> {
+    sessions.authorize(&token, Role::Viewer)?;
+
+    let mut conn = pool.acquire().await.map_err(|e| e.to_string())?;
+    internal_generate_member_code(&mut conn)
         .await
         .map_err(|e| e.to_string())
 }
 
-async fn internal_generate_member_code(pool: &SqlitePool) -> Result<String, sqlx::Error> {
+async fn internal_generate_member_code(conn: &mut SqliteConnection) -> Result<String, sqlx::Error> {
     let current_year = chrono::Utc::now().format("%Y").to_string();
     let prefix = format!("MBR-{}-", current_year);
 
@@ -912,7 +2005,7 @@ async fn internal_generate_member_code(pool: &SqlitePool) -> Result<String, sqlx
         "SELECT member_code FROM members WHERE member_code LIKE ? ORDER BY length(member_code) DESC, member_code DESC"
     )
     .bind(format!("{}%", prefix))
-    .fetch_all(pool)
+    .fetch_all(&mut *conn)
     .await?;
 
     let mut max_num: i64 = 0;
@@ -934,12 +2027,16 @@ async fn internal_generate_member_code(pool: &SqlitePool) -> Result<String, sqlx
 #[tauri::command]
 pub async fn get_book_borrowers(
     pool: State<'_, SqlitePool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
     book_id: i64,
 ) -> Result<Vec<LoanDetail>, String> {
+    sessions.authorize(&token, Role::Viewer)?;
+
     let loans = sqlx::query_as::<_, LoanDetail>(
         r#"
         SELECT 
-            l.id, l.book_id, l.member_id,
+            l.id, l.book_id, l.member_id, l.item_id, bi.barcode as item_barcode, l.renewal_count,
             b.title as book_title, b.isbn as book_isbn, b.cover as book_cover,
             m.name as member_name, m.member_code, m.status as member_status, m.kelas as member_kelas,
             l.loan_date, l.due_date, l.status,
@@ -947,6 +2044,7 @@ pub async fn get_book_borrowers(
         FROM loans l
         JOIN books b ON l.book_id = b.id
         JOIN members m ON l.member_id = m.id
+        LEFT JOIN book_items bi ON l.item_id = bi.id
         WHERE l.book_id = ? AND l.status = 'borrowed'
         ORDER BY l.loan_date DESC
         "#
@@ -959,72 +2057,195 @@ pub async fn get_book_borrowers(
     Ok(loans)
 }
 
-#[tauri::command]
-pub async fn get_fines(pool: State<'_, SqlitePool>) -> Result<Vec<FineWithDetails>, String> {
-    let sql = r#"
+const FINE_LIST: ListSql = ListSql {
+    select: r#"
         SELECT 
             f.id, f.loan_id, l.member_id,
             m.name as member_name, m.member_code, m.kelas as member_kelas,
             b.title as book_title,
             l.loan_date, l.due_date, l.return_date,
-            f.amount, f.fine_type, f.status, f.paid_at
+            f.amount, fb.paid_amount, fb.waived_amount, fb.remaining_amount,
+            f.fine_type, f.status, f.paid_at"#,
+    from: r#"
         FROM fines f
+        JOIN fine_balances fb ON fb.fine_id = f.id
         JOIN loans l ON f.loan_id = l.id
         JOIN members m ON l.member_id = m.id
         JOIN books b ON l.book_id = b.id
-        ORDER BY f.status ASC, f.created_at DESC
-    "#;
+        WHERE 1 = 1"#,
+    sort_columns: &[
+        ("created_at", "f.created_at"),
+        ("status", "f.status"),
+        ("amount", "f.amount"),
+        ("remaining_amount", "fb.remaining_amount"),
+        ("member_name", "m.name"),
+        ("due_date", "l.due_date"),
+    ],
+    default_order: "f.status ASC, f.created_at DESC, f.id DESC",
+    tiebreak: "f.id",
+};
 
-    sqlx::query_as::<_, FineWithDetails>(sql)
-        .fetch_all(&*pool)
-        .await
-        .map_err(|e| e.to_string())
+#[tauri::command]
+pub async fn get_fines(
+    pool: State<'_, SqlitePool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+    query: Option<ListQuery<FineFilter>>,
+) -> Result<Page<FineWithDetails>, String> {
+    sessions.authorize(&token, Role::Viewer)?;
+
+    let query = query.unwrap_or_default();
+    let filter = &query.filter;
+    paging::fetch_page(&pool, &FINE_LIST, &query, |builder| {
+        if let Some(search) = &filter.search {
+            paging::push_like(builder, &["m.name", "m.member_code", "b.title"], search);
+        }
+        if let Some(member_id) = filter.member_id {
+            builder.push(" AND l.member_id = ").push_bind(member_id);
+        }
+        if let Some(status) = &filter.status {
+            builder.push(" AND f.status = ").push_bind(status.clone());
+        }
+        if let Some(fine_type) = &filter.fine_type {
+            builder
+                .push(" AND f.fine_type = ")
+                .push_bind(fine_type.clone());
+        }
+        if let Some(kelas) = &filter.kelas {
+            builder.push(" AND m.kelas = ").push_bind(kelas.clone());
+        }
+    })
+    .await
 }
 
+// Takes the whole remaining balance unless a partial amount is given; cash by default
 #[tauri::command]
-pub async fn pay_fine(pool: State<'_, SqlitePool>, fine_id: i64) -> Result<(), String> {
-    sqlx::query("UPDATE fines SET status = 'Paid', paid_at = CURRENT_TIMESTAMP WHERE id = ?")
-        .bind(fine_id)
-        .execute(&*pool)
-        .await
-        .map_err(|e| e.to_string())?;
+pub async fn pay_fine(
+    pool: State<'_, SqlitePool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+    fine_id: i64,
+    amount: Option<i64>,
+    method: Option<String>,
+) -> Result<i64, String> {
+    let session = sessions.authorize(&token, Role::Staff)?;
 
-    Ok(())
+    let method = method.unwrap_or_else(|| fine_commands::METHOD_CASH.to_string());
+    if !fine_commands::PAYMENT_METHODS.contains(&method.as_str()) {
+        return Err("Metode pembayaran tidak valid (gunakan cash atau transfer)".to_string());
+    }
+
+    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+
+    let remaining = fine_commands::remaining_balance(&mut tx, fine_id).await?;
+    if remaining <= 0 {
+        return Err("Denda sudah lunas".to_string());
+    }
+    let amount = amount.unwrap_or(remaining);
+    if amount <= 0 {
+        return Err("Jumlah pembayaran harus lebih dari 0".to_string());
+    }
+    if amount > remaining {
+        return Err(format!(
+            "Jumlah pembayaran melebihi sisa denda Rp {}",
+            remaining
+        ));
+    }
+
+    let payment = fine_commands::add_ledger_entry(
+        &mut tx,
+        fine_commands::LedgerEntry {
+            fine_id,
+            entry_type: fine_commands::ENTRY_PAYMENT,
+            amount,
+            method: Some(&method),
+            reason: None,
+            reversal_of: None,
+            received_by: session.user_id,
+        },
+    )
+    .await?;
+
+    audit::record(
+        &mut tx,
+        &session,
+        "pay",
+        "fine_payment",
+        Some(payment.id),
+        None,
+        audit::snapshot(&payment),
+    )
+    .await?;
+
+    tx.commit().await.map_err(|e| e.to_string())?;
+
+    Ok(payment.id)
 }
 
 #[tauri::command]
 pub async fn create_fine(
     pool: State<'_, SqlitePool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
     loan_id: i64,
     amount: i64,
     fine_type: String,
 ) -> Result<i64, String> {
+    let session = sessions.authorize(&token, Role::Staff)?;
+
+    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+
     let res = sqlx::query(
         "INSERT INTO fines (loan_id, amount, fine_type, status) VALUES (?, ?, ?, 'Unpaid')",
     )
     .bind(loan_id)
     .bind(amount)
     .bind(fine_type)
-    .execute(&*pool)
+    .execute(&mut *tx)
     .await
     .map_err(|e| e.to_string())?;
 
-    Ok(res.last_insert_rowid())
+    let fine_id = res.last_insert_rowid();
+    let created = sqlx::query_as::<_, Fine>("SELECT * FROM fines WHERE id = ?")
+        .bind(fine_id)
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+    audit::record(
+        &mut tx,
+        &session,
+        "create",
+        "fine",
+        Some(fine_id),
+        None,
+        audit::snapshot(&created),
+    )
+    .await?;
+
+    tx.commit().await.map_err(|e| e.to_string())?;
+
+    Ok(fine_id)
 }
 
 #[tauri::command]
 pub async fn get_fines_by_member(
     pool: State<'_, SqlitePool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
     member_id: i64,
 ) -> Result<Vec<FineWithDetails>, String> {
+    sessions.authorize(&token, Role::Viewer)?;
+
     let sql = r#"
         SELECT 
             f.id, f.loan_id, l.member_id,
             m.name as member_name, m.member_code, m.kelas as member_kelas,
             b.title as book_title,
             l.loan_date, l.due_date, l.return_date,
-            f.amount, f.fine_type, f.status, f.paid_at
+            f.amount, fb.paid_amount, fb.waived_amount, fb.remaining_amount,
+            f.fine_type, f.status, f.paid_at
         FROM fines f
+        JOIN fine_balances fb ON fb.fine_id = f.id
         JOIN loans l ON f.loan_id = l.id
         JOIN members m ON l.member_id = m.id
         JOIN books b ON l.book_id = b.id
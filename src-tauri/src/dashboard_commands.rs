@@ -1,3 +1,4 @@
+use crate::auth::{Role, SessionStore};
 use crate::dashboard_models::{BookStat, CategoryStat, MemberActivity};
 use chrono::{DateTime, Utc};
 use sqlx::{Row, SqlitePool};
@@ -6,7 +7,11 @@ use tauri::State;
 #[tauri::command]
 pub async fn get_popular_categories(
     pool: State<'_, SqlitePool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
 ) -> Result<Vec<CategoryStat>, String> {
+    sessions.authorize(&token, Role::Viewer)?;
+
     let rows = sqlx::query(
         r#"
         SELECT 
@@ -36,7 +41,13 @@ pub async fn get_popular_categories(
 }
 
 #[tauri::command]
-pub async fn get_most_borrowed_books(pool: State<'_, SqlitePool>) -> Result<Vec<BookStat>, String> {
+pub async fn get_most_borrowed_books(
+    pool: State<'_, SqlitePool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+) -> Result<Vec<BookStat>, String> {
+    sessions.authorize(&token, Role::Viewer)?;
+
     let rows = sqlx::query(
         r#"
         SELECT 
@@ -72,7 +83,11 @@ pub async fn get_most_borrowed_books(pool: State<'_, SqlitePool>) -> Result<Vec<
 #[tauri::command]
 pub async fn get_member_activity_stats(
     pool: State<'_, SqlitePool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
 ) -> Result<Vec<MemberActivity>, String> {
+    sessions.authorize(&token, Role::Viewer)?;
+
     let rows = sqlx::query(
         r#"
         SELECT 
@@ -0,0 +1,226 @@
+use crate::audit;
+use crate::auth::{Role, SessionStore};
+use crate::models::{BorrowEligibility, BorrowOverride, EligibilityBlock};
+use crate::policy_commands;
+use crate::settings_commands;
+use chrono::{Local, Utc};
+use sqlx::{SqliteConnection, SqlitePool};
+use tauri::State;
+
+fn block(code: &str, message: String) -> EligibilityBlock {
+    EligibilityBlock {
+        code: code.to_string(),
+        message,
+    }
+}
+
+// Everything that stops this member from borrowing right now. With a book the
+// loan limit comes from the policy for that book, otherwise from the most
+// generous policy for the member's type.
+pub async fn blocking_reasons(
+    conn: &mut SqliteConnection,
+    member_id: i64,
+    book_id: Option<i64>,
+) -> Result<Vec<EligibilityBlock>, String> {
+    let mut reasons = Vec::new();
+    let now = Utc::now();
+
+    let (member_type, valid_until): (String, Option<chrono::NaiveDate>) =
+        sqlx::query_as("SELECT member_type, valid_until FROM members WHERE id = ?")
+            .bind(member_id)
+            .fetch_optional(&mut *conn)
+            .await
+            .map_err(|e| e.to_string())?
+            .ok_or_else(|| "Member tidak ditemukan".to_string())?;
+
+    let overdue: i64 = sqlx::query_scalar(
+        "SELECT COUNT(*) FROM loans WHERE member_id = ? AND status = 'borrowed' AND due_date < ?",
+    )
+    .bind(member_id)
+    .bind(now)
+    .fetch_one(&mut *conn)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    if overdue > 0 {
+        reasons.push(block(
+            "overdue",
+            format!("Memiliki {} buku yang terlambat dikembalikan", overdue),
+        ));
+    }
+
+    let unpaid: i64 = sqlx::query_scalar(
+        "SELECT COALESCE(SUM(remaining_amount), 0) FROM fine_balances WHERE member_id = ?",
+    )
+    .bind(member_id)
+    .fetch_one(&mut *conn)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    let max_unpaid = settings_commands::get_setting_i64(&mut *conn, "max_unpaid_fine", 0).await;
+    if unpaid > max_unpaid {
+        reasons.push(block(
+            "unpaid_fines",
+            format!(
+                "Denda belum dibayar Rp {} melebihi batas Rp {}",
+                unpaid, max_unpaid
+            ),
+        ));
+    }
+
+    let max_loans: Option<i64> = match book_id {
+        Some(book_id) => Some(
+            policy_commands::policy_for(&mut *conn, member_id, book_id)
+                .await?
+                .max_loans,
+        ),
+        None => {
+            sqlx::query_scalar("SELECT MAX(max_loans) FROM loan_policies WHERE member_type = ?")
+                .bind(&member_type)
+                .fetch_one(&mut *conn)
+                .await
+                .map_err(|e| e.to_string())?
+        }
+    };
+
+    let active: i64 = sqlx::query_scalar(
+        "SELECT COUNT(*) FROM loans WHERE member_id = ? AND status = 'borrowed'",
+    )
+    .bind(member_id)
+    .fetch_one(&mut *conn)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    if let Some(max_loans) = max_loans {
+        if active >= max_loans {
+            reasons.push(block(
+                "loan_limit",
+                format!("Sudah meminjam {} buku (batas {} buku)", active, max_loans),
+            ));
+        }
+    }
+
+    if let Some(valid_until) = valid_until {
+        if valid_until < Local::now().date_naive() {
+            reasons.push(block(
+                "membership_expired",
+                format!("Masa keanggotaan berakhir pada {}", valid_until),
+            ));
+        }
+    }
+
+    Ok(reasons)
+}
+
+// An unused override granted today, if any
+pub async fn pending_override(
+    conn: &mut SqliteConnection,
+    member_id: i64,
+) -> Result<Option<i64>, String> {
+    sqlx::query_scalar(
+        "SELECT id FROM borrow_overrides WHERE member_id = ? AND used_at IS NULL AND date(created_at, 'localtime') = date('now', 'localtime') ORDER BY id ASC LIMIT 1",
+    )
+    .bind(member_id)
+    .fetch_optional(conn)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+pub async fn use_override(
+    conn: &mut SqliteConnection,
+    override_id: i64,
+    loan_id: i64,
+) -> Result<(), String> {
+    sqlx::query("UPDATE borrow_overrides SET used_at = ?, loan_id = ? WHERE id = ?")
+        .bind(Utc::now())
+        .bind(loan_id)
+        .bind(override_id)
+        .execute(conn)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn check_borrow_eligibility(
+    pool: State<'_, SqlitePool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+    member_id: i64,
+    book_id: Option<i64>,
+) -> Result<BorrowEligibility, String> {
+    sessions.authorize(&token, Role::Viewer)?;
+
+    let mut conn = pool.acquire().await.map_err(|e| e.to_string())?;
+    let reasons = blocking_reasons(&mut conn, member_id, book_id).await?;
+    let override_available = pending_override(&mut conn, member_id).await?.is_some();
+
+    Ok(BorrowEligibility {
+        member_id,
+        eligible: reasons.is_empty() || override_available,
+        reasons,
+        override_available,
+    })
+}
+
+#[tauri::command]
+pub async fn grant_borrow_override(
+    pool: State<'_, SqlitePool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+    member_id: i64,
+    reason: String,
+) -> Result<i64, String> {
+    let session = sessions.authorize(&token, Role::Admin)?;
+
+    let reason = reason.trim().to_string();
+    if reason.is_empty() {
+        return Err("Alasan pengecualian wajib diisi".to_string());
+    }
+
+    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+
+    let blocks = blocking_reasons(&mut tx, member_id, None).await?;
+
+    let res = sqlx::query(
+        "INSERT INTO borrow_overrides (member_id, reason, granted_by) VALUES (?, ?, ?)",
+    )
+    .bind(member_id)
+    .bind(&reason)
+    .bind(session.user_id)
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    let id = res.last_insert_rowid();
+    let created =
+        sqlx::query_as::<_, BorrowOverride>("SELECT * FROM borrow_overrides WHERE id = ?")
+            .bind(id)
+            .fetch_one(&mut *tx)
+            .await
+            .map_err(|e| e.to_string())?;
+
+    // Keep what was being overridden next to the override itself
+    let mut after = audit::snapshot(&created);
+    if let Some(serde_json::Value::Object(map)) = after.as_mut() {
+        map.insert(
+            "blocking_reasons".to_string(),
+            serde_json::to_value(&blocks).unwrap_or_default(),
+        );
+    }
+    audit::record(
+        &mut tx,
+        &session,
+        "grant",
+        "borrow_override",
+        Some(id),
+        None,
+        after,
+    )
+    .await?;
+
+    tx.commit().await.map_err(|e| e.to_string())?;
+
+    Ok(id)
+}
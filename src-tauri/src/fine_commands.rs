@@ -0,0 +1,732 @@
+use crate::audit;
+use crate::auth::{Role, SessionStore};
+use crate::models::{AccruedFine, ClosureDay, DailyCashTotal, FinePayment, MemberFineBalance};
+use crate::policy_commands;
+use crate::settings_commands;
+use chrono::{DateTime, Duration, Local, NaiveDate, Utc};
+use serde_json::json;
+use sqlx::{SqliteConnection, SqlitePool};
+use std::collections::HashSet;
+use tauri::State;
+
+// How often the background task checks whether today's accrual has run
+const ACCRUAL_CHECK_INTERVAL_SECS: u64 = 60 * 60;
+
+pub const FINE_LOST: &str = "Lost";
+pub const ENTRY_PAYMENT: &str = "payment";
+pub const ENTRY_WAIVER: &str = "waiver";
+pub const METHOD_CASH: &str = "cash";
+pub const PAYMENT_METHODS: [&str; 2] = [METHOD_CASH, "transfer"];
+
+const PAYMENT_SELECT: &str = r#"
+    SELECT
+        p.id, p.fine_id, p.entry_type, p.amount, p.method, p.reason, p.reversal_of,
+        p.received_by, u.name as received_by_name,
+        EXISTS (SELECT 1 FROM fine_payments r WHERE r.reversal_of = p.id) as reversed,
+        p.created_at
+    FROM fine_payments p
+    LEFT JOIN users u ON p.received_by = u.id
+"#;
+
+pub struct LateFine {
+    pub days_late: i64,
+    pub chargeable_days: i64,
+    pub amount: i64,
+    pub capped: bool,
+}
+
+// Settings and closure days shared by every late fine calculation
+pub struct FineRules {
+    grace_days: i64,
+    max_per_loan: i64,
+    default_rate: i64,
+    closures: HashSet<NaiveDate>,
+}
+
+impl FineRules {
+    pub async fn load(conn: &mut SqliteConnection) -> Result<Self, String> {
+        let grace_days = settings_commands::get_setting_i64(&mut *conn, "fine_grace_days", 0).await;
+        let max_per_loan =
+            settings_commands::get_setting_i64(&mut *conn, "fine_max_per_loan", 0).await;
+        let default_rate =
+            settings_commands::get_setting_i64(&mut *conn, "fine_late_per_day", 1000).await;
+
+        let closures: Vec<NaiveDate> = sqlx::query_scalar("SELECT date FROM closure_days")
+            .fetch_all(&mut *conn)
+            .await
+            .map_err(|e| e.to_string())?;
+
+        Ok(FineRules {
+            grace_days,
+            max_per_loan,
+            default_rate,
+            closures: closures.into_iter().collect(),
+        })
+    }
+
+    // Late fine for a loan due at `due_date` if it comes back at `until`. Every
+    // full day past the due date counts, except closure days and the grace days.
+    // Closure days are local calendar dates.
+    pub async fn late_fine(
+        &self,
+        conn: &mut SqliteConnection,
+        member_id: i64,
+        book_id: i64,
+        due_date: DateTime<Utc>,
+        until: DateTime<Utc>,
+    ) -> LateFine {
+        let days_late = (until - due_date).num_days().max(0);
+        let open_days = (1..=days_late)
+            .filter(|d| {
+                !self.closures.contains(
+                    &(due_date + Duration::days(*d))
+                        .with_timezone(&Local)
+                        .date_naive(),
+                )
+            })
+            .count() as i64;
+        let chargeable_days = (open_days - self.grace_days).max(0);
+
+        let rate = match policy_commands::policy_for(conn, member_id, book_id).await {
+            Ok(policy) => policy.fine_per_day,
+            Err(_) => self.default_rate,
+        };
+
+        let mut amount = chargeable_days * rate;
+        let capped = self.max_per_loan > 0 && amount > self.max_per_loan;
+        if capped {
+            amount = self.max_per_loan;
+        }
+
+        LateFine {
+            days_late,
+            chargeable_days,
+            amount,
+            capped,
+        }
+    }
+}
+
+pub struct LedgerEntry<'a> {
+    pub fine_id: i64,
+    pub entry_type: &'a str,
+    pub amount: i64,
+    pub method: Option<&'a str>,
+    pub reason: Option<&'a str>,
+    pub reversal_of: Option<i64>,
+    pub received_by: i64,
+}
+
+pub async fn remaining_balance(conn: &mut SqliteConnection, fine_id: i64) -> Result<i64, String> {
+    sqlx::query_scalar("SELECT remaining_amount FROM fine_balances WHERE fine_id = ?")
+        .bind(fine_id)
+        .fetch_optional(conn)
+        .await
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| "Denda tidak ditemukan".to_string())
+}
+
+async fn find_payment(conn: &mut SqliteConnection, payment_id: i64) -> Result<FinePayment, String> {
+    sqlx::query_as::<_, FinePayment>(&format!("{} WHERE p.id = ?", PAYMENT_SELECT))
+        .bind(payment_id)
+        .fetch_optional(conn)
+        .await
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| "Pembayaran denda tidak ditemukan".to_string())
+}
+
+// Write one ledger row and bring the fine's status in line with its new balance
+pub async fn add_ledger_entry(
+    conn: &mut SqliteConnection,
+    entry: LedgerEntry<'_>,
+) -> Result<FinePayment, String> {
+    let res = sqlx::query(
+        "INSERT INTO fine_payments (fine_id, entry_type, amount, method, reason, reversal_of, received_by, created_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+    )
+    .bind(entry.fine_id)
+    .bind(entry.entry_type)
+    .bind(entry.amount)
+    .bind(entry.method)
+    .bind(entry.reason)
+    .bind(entry.reversal_of)
+    .bind(entry.received_by)
+    .bind(Utc::now())
+    .execute(&mut *conn)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    let remaining = remaining_balance(conn, entry.fine_id).await?;
+    if remaining <= 0 {
+        sqlx::query(
+            "UPDATE fines SET status = 'Paid', paid_at = COALESCE(paid_at, ?) WHERE id = ?",
+        )
+        .bind(Utc::now())
+        .bind(entry.fine_id)
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| e.to_string())?;
+    } else {
+        sqlx::query("UPDATE fines SET status = 'Unpaid', paid_at = NULL WHERE id = ?")
+            .bind(entry.fine_id)
+            .execute(&mut *conn)
+            .await
+            .map_err(|e| e.to_string())?;
+    }
+
+    find_payment(conn, res.last_insert_rowid()).await
+}
+
+// Undo a fine that should never have been charged: payments are reversed (so
+// the money shows up as a refund in the day's cash total) and the rest is
+// waived. Returns the amount to hand back to the member.
+pub async fn cancel_fine(
+    conn: &mut SqliteConnection,
+    fine_id: i64,
+    reason: &str,
+    user_id: i64,
+) -> Result<i64, String> {
+    let payments = sqlx::query_as::<_, FinePayment>(&format!(
+        "{} WHERE p.fine_id = ? AND p.entry_type = 'payment' AND p.reversal_of IS NULL ORDER BY p.id ASC",
+        PAYMENT_SELECT
+    ))
+    .bind(fine_id)
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    let mut refunded = 0;
+    for payment in payments.iter().filter(|p| !p.reversed) {
+        add_ledger_entry(
+            conn,
+            LedgerEntry {
+                fine_id,
+                entry_type: ENTRY_PAYMENT,
+                amount: -payment.amount,
+                method: payment.method.as_deref(),
+                reason: Some(reason),
+                reversal_of: Some(payment.id),
+                received_by: user_id,
+            },
+        )
+        .await?;
+        refunded += payment.amount;
+    }
+
+    let remaining = remaining_balance(conn, fine_id).await?;
+    if remaining > 0 {
+        add_ledger_entry(
+            conn,
+            LedgerEntry {
+                fine_id,
+                entry_type: ENTRY_WAIVER,
+                amount: remaining,
+                method: None,
+                reason: Some(reason),
+                reversal_of: None,
+                received_by: user_id,
+            },
+        )
+        .await?;
+    }
+
+    sqlx::query("UPDATE fines SET status = 'Cancelled' WHERE id = ?")
+        .bind(fine_id)
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(refunded)
+}
+
+// What it costs to replace a copy: its own price, else the price of the title
+pub async fn replacement_price(
+    conn: &mut SqliteConnection,
+    book_id: i64,
+    item_id: Option<i64>,
+) -> Result<Option<i64>, String> {
+    sqlx::query_scalar(
+        "SELECT COALESCE((SELECT price FROM book_items WHERE id = ?), (SELECT price FROM books WHERE id = ?))",
+    )
+    .bind(item_id)
+    .bind(book_id)
+    .fetch_one(conn)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+#[derive(sqlx::FromRow)]
+struct OpenLoan {
+    loan_id: i64,
+    book_id: i64,
+    book_title: String,
+    member_id: i64,
+    member_name: String,
+    member_code: String,
+    member_kelas: Option<String>,
+    due_date: DateTime<Utc>,
+}
+
+// Accrued late fines of the overdue loans that are still out, as of now
+pub async fn compute_accrued_fines(
+    conn: &mut SqliteConnection,
+    member_id: Option<i64>,
+) -> Result<Vec<AccruedFine>, String> {
+    let now = Utc::now();
+    let rules = FineRules::load(conn).await?;
+
+    let loans = sqlx::query_as::<_, OpenLoan>(
+        r#"
+        SELECT
+            l.id as loan_id, l.book_id, b.title as book_title,
+            l.member_id, m.name as member_name, m.member_code, m.kelas as member_kelas,
+            l.due_date
+        FROM loans l
+        JOIN books b ON l.book_id = b.id
+        JOIN members m ON l.member_id = m.id
+        WHERE l.status = 'borrowed' AND l.due_date < ?
+        AND (? IS NULL OR l.member_id = ?)
+        ORDER BY l.due_date ASC
+        "#,
+    )
+    .bind(now)
+    .bind(member_id)
+    .bind(member_id)
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    let mut fines = Vec::new();
+    for loan in loans {
+        let fine = rules
+            .late_fine(conn, loan.member_id, loan.book_id, loan.due_date, now)
+            .await;
+        fines.push(AccruedFine {
+            loan_id: loan.loan_id,
+            book_id: loan.book_id,
+            book_title: loan.book_title,
+            member_id: loan.member_id,
+            member_name: loan.member_name,
+            member_code: loan.member_code,
+            member_kelas: loan.member_kelas,
+            due_date: loan.due_date,
+            days_late: fine.days_late,
+            chargeable_days: fine.chargeable_days,
+            amount: fine.amount,
+            capped: fine.capped,
+        });
+    }
+
+    Ok(fines)
+}
+
+// Store the current accrued amount on every open loan; returns how many loans
+// carry an accrued fine
+pub async fn accrue_fines(conn: &mut SqliteConnection) -> Result<i64, String> {
+    let fines = compute_accrued_fines(conn, None).await?;
+    let now = Utc::now();
+
+    sqlx::query("UPDATE loans SET accrued_fine = 0, fine_accrued_at = ? WHERE status = 'borrowed'")
+        .bind(now)
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    for fine in &fines {
+        sqlx::query("UPDATE loans SET accrued_fine = ? WHERE id = ?")
+            .bind(fine.amount)
+            .bind(fine.loan_id)
+            .execute(&mut *conn)
+            .await
+            .map_err(|e| e.to_string())?;
+    }
+
+    sqlx::query("INSERT OR REPLACE INTO settings (key, value) VALUES ('fine_accrual_last_run', ?)")
+        .bind(Local::now().date_naive().to_string())
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| e.to_string())?;
+    sqlx::query(
+        "INSERT OR REPLACE INTO settings (key, value) VALUES ('fine_accrual_last_error', '')",
+    )
+    .execute(&mut *conn)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    Ok(fines.iter().filter(|f| f.amount > 0).count() as i64)
+}
+
+async fn accrue_once(pool: &SqlitePool) -> Result<(), String> {
+    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+    accrue_fines(&mut tx).await?;
+    tx.commit().await.map_err(|e| e.to_string())
+}
+
+// Background task started with the app: runs the accrual once per day
+pub async fn run_daily_accrual(pool: SqlitePool) {
+    loop {
+        let today = Local::now().date_naive().to_string();
+        let last_run: Option<String> =
+            sqlx::query_scalar("SELECT value FROM settings WHERE key = 'fine_accrual_last_run'")
+                .fetch_optional(&pool)
+                .await
+                .ok()
+                .flatten();
+
+        if last_run.as_deref() != Some(today.as_str()) {
+            if let Err(e) = accrue_once(&pool).await {
+                // Shown to staff in the settings until the next run succeeds.
+                // If even this write fails the next check simply tries again.
+                let _ = sqlx::query(
+                    "INSERT OR REPLACE INTO settings (key, value) VALUES ('fine_accrual_last_error', ?)",
+                )
+                .bind(format!("{}: {}", Local::now().format("%Y-%m-%d %H:%M"), e))
+                .execute(&pool)
+                .await;
+            }
+        }
+
+        tokio::time::sleep(std::time::Duration::from_secs(ACCRUAL_CHECK_INTERVAL_SECS)).await;
+    }
+}
+
+// Live amounts, so a member can be told what they owe before returning the books
+#[tauri::command]
+pub async fn get_accrued_fines(
+    pool: State<'_, SqlitePool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+    member_id: Option<i64>,
+) -> Result<Vec<AccruedFine>, String> {
+    sessions.authorize(&token, Role::Viewer)?;
+
+    let mut conn = pool.acquire().await.map_err(|e| e.to_string())?;
+    compute_accrued_fines(&mut conn, member_id).await
+}
+
+#[tauri::command]
+pub async fn run_fine_accrual(
+    pool: State<'_, SqlitePool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+) -> Result<i64, String> {
+    let session = sessions.authorize(&token, Role::Staff)?;
+
+    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+
+    let loans = accrue_fines(&mut tx).await?;
+    audit::record(
+        &mut tx,
+        &session,
+        "accrue",
+        "fine",
+        None,
+        None,
+        Some(json!({ "loans": loans })),
+    )
+    .await?;
+
+    tx.commit().await.map_err(|e| e.to_string())?;
+
+    Ok(loans)
+}
+
+#[tauri::command]
+pub async fn get_closure_days(
+    pool: State<'_, SqlitePool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+) -> Result<Vec<ClosureDay>, String> {
+    sessions.authorize(&token, Role::Viewer)?;
+
+    sqlx::query_as::<_, ClosureDay>("SELECT date, description FROM closure_days ORDER BY date ASC")
+        .fetch_all(&*pool)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn add_closure_day(
+    pool: State<'_, SqlitePool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+    day: ClosureDay,
+) -> Result<(), String> {
+    let session = sessions.authorize(&token, Role::Admin)?;
+
+    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+
+    sqlx::query("INSERT OR REPLACE INTO closure_days (date, description) VALUES (?, ?)")
+        .bind(day.date)
+        .bind(&day.description)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    audit::record(
+        &mut tx,
+        &session,
+        "create",
+        "closure_day",
+        None,
+        None,
+        audit::snapshot(&day),
+    )
+    .await?;
+
+    tx.commit().await.map_err(|e| e.to_string())?;
+
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn delete_closure_day(
+    pool: State<'_, SqlitePool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+    date: NaiveDate,
+) -> Result<(), String> {
+    let session = sessions.authorize(&token, Role::Admin)?;
+
+    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+
+    let before = sqlx::query_as::<_, ClosureDay>(
+        "SELECT date, description FROM closure_days WHERE date = ?",
+    )
+    .bind(date)
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(|e| e.to_string())?
+    .ok_or_else(|| "Hari libur tidak ditemukan".to_string())?;
+
+    sqlx::query("DELETE FROM closure_days WHERE date = ?")
+        .bind(date)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    audit::record(
+        &mut tx,
+        &session,
+        "delete",
+        "closure_day",
+        None,
+        audit::snapshot(&before),
+        None,
+    )
+    .await?;
+
+    tx.commit().await.map_err(|e| e.to_string())?;
+
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn get_fine_payments(
+    pool: State<'_, SqlitePool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+    fine_id: i64,
+) -> Result<Vec<FinePayment>, String> {
+    sessions.authorize(&token, Role::Viewer)?;
+
+    sqlx::query_as::<_, FinePayment>(&format!(
+        "{} WHERE p.fine_id = ? ORDER BY p.id ASC",
+        PAYMENT_SELECT
+    ))
+    .bind(fine_id)
+    .fetch_all(&*pool)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+// Write off part or all of the remaining balance; the reason is required
+#[tauri::command]
+pub async fn waive_fine(
+    pool: State<'_, SqlitePool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+    fine_id: i64,
+    amount: Option<i64>,
+    reason: String,
+) -> Result<i64, String> {
+    let session = sessions.authorize(&token, Role::Admin)?;
+
+    let reason = reason.trim().to_string();
+    if reason.is_empty() {
+        return Err("Alasan pembebasan denda wajib diisi".to_string());
+    }
+
+    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+
+    let remaining = remaining_balance(&mut tx, fine_id).await?;
+    if remaining <= 0 {
+        return Err("Denda sudah lunas".to_string());
+    }
+    let amount = amount.unwrap_or(remaining);
+    if amount <= 0 || amount > remaining {
+        return Err(format!(
+            "Jumlah pembebasan harus antara Rp 1 dan sisa denda Rp {}",
+            remaining
+        ));
+    }
+
+    let waiver = add_ledger_entry(
+        &mut tx,
+        LedgerEntry {
+            fine_id,
+            entry_type: ENTRY_WAIVER,
+            amount,
+            method: None,
+            reason: Some(&reason),
+            reversal_of: None,
+            received_by: session.user_id,
+        },
+    )
+    .await?;
+
+    audit::record(
+        &mut tx,
+        &session,
+        "waive",
+        "fine_payment",
+        Some(waiver.id),
+        None,
+        audit::snapshot(&waiver),
+    )
+    .await?;
+
+    tx.commit().await.map_err(|e| e.to_string())?;
+
+    Ok(waiver.id)
+}
+
+// Cancel a payment or waiver by booking its opposite; the original row stays
+#[tauri::command]
+pub async fn reverse_fine_payment(
+    pool: State<'_, SqlitePool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+    payment_id: i64,
+    reason: String,
+) -> Result<i64, String> {
+    let session = sessions.authorize(&token, Role::Admin)?;
+
+    let reason = reason.trim().to_string();
+    if reason.is_empty() {
+        return Err("Alasan pembatalan wajib diisi".to_string());
+    }
+
+    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+
+    let original = find_payment(&mut tx, payment_id).await?;
+    if original.reversal_of.is_some() {
+        return Err("Entri pembatalan tidak dapat dibatalkan lagi".to_string());
+    }
+    if original.reversed {
+        return Err("Pembayaran ini sudah dibatalkan".to_string());
+    }
+
+    let reversal = add_ledger_entry(
+        &mut tx,
+        LedgerEntry {
+            fine_id: original.fine_id,
+            entry_type: &original.entry_type,
+            amount: -original.amount,
+            method: original.method.as_deref(),
+            reason: Some(&reason),
+            reversal_of: Some(original.id),
+            received_by: session.user_id,
+        },
+    )
+    .await?;
+
+    audit::record(
+        &mut tx,
+        &session,
+        "reverse",
+        "fine_payment",
+        Some(reversal.id),
+        audit::snapshot(&original),
+        audit::snapshot(&reversal),
+    )
+    .await?;
+
+    tx.commit().await.map_err(|e| e.to_string())?;
+
+    Ok(reversal.id)
+}
+
+#[tauri::command]
+pub async fn get_member_fine_balance(
+    pool: State<'_, SqlitePool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+    member_id: i64,
+) -> Result<MemberFineBalance, String> {
+    sessions.authorize(&token, Role::Viewer)?;
+
+    sqlx::query_as::<_, MemberFineBalance>(
+        r#"
+        SELECT
+            ? as member_id,
+            COALESCE(SUM(amount), 0) as total_amount,
+            COALESCE(SUM(paid_amount), 0) as paid_amount,
+            COALESCE(SUM(waived_amount), 0) as waived_amount,
+            COALESCE(SUM(remaining_amount), 0) as remaining_amount
+        FROM fine_balances
+        WHERE member_id = ?
+        "#,
+    )
+    .bind(member_id)
+    .bind(member_id)
+    .fetch_one(&*pool)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+// Money taken on one local day (today by default), net of reversals booked that
+// day; cash_amount is what should be in the drawer
+#[tauri::command]
+pub async fn get_daily_cash_total(
+    pool: State<'_, SqlitePool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+    date: Option<NaiveDate>,
+) -> Result<DailyCashTotal, String> {
+    sessions.authorize(&token, Role::Viewer)?;
+
+    let date = date.unwrap_or_else(|| chrono::Local::now().date_naive());
+
+    let (cash_amount, transfer_amount, waived_amount, payment_count, reversal_count): (
+        i64,
+        i64,
+        i64,
+        i64,
+        i64,
+    ) = sqlx::query_as(
+        r#"
+        SELECT
+            COALESCE(SUM(CASE WHEN entry_type = 'payment' AND method = 'cash' THEN amount END), 0),
+            COALESCE(SUM(CASE WHEN entry_type = 'payment' AND method = 'transfer' THEN amount END), 0),
+            COALESCE(SUM(CASE WHEN entry_type = 'waiver' THEN amount END), 0),
+            COUNT(CASE WHEN entry_type = 'payment' AND reversal_of IS NULL THEN 1 END),
+            COUNT(CASE WHEN reversal_of IS NOT NULL THEN 1 END)
+        FROM fine_payments
+        WHERE date(created_at, 'localtime') = ?
+        "#,
+    )
+    .bind(date.to_string())
+    .fetch_one(&*pool)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    Ok(DailyCashTotal {
+        date,
+        cash_amount,
+        transfer_amount,
+        total_amount: cash_amount + transfer_amount,
+        waived_amount,
+        payment_count,
+        reversal_count,
+    })
+}
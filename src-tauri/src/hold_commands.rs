@@ -0,0 +1,354 @@
+use crate::audit;
+use crate::auth::{Role, SessionStore};
+use crate::item_commands;
+use crate::models::{Hold, HoldWithDetails};
+use crate::settings_commands;
+use chrono::{Duration, Utc};
+use serde_json::json;
+use sqlx::{QueryBuilder, Sqlite, SqliteConnection, SqlitePool};
+use tauri::State;
+
+async fn find_hold(conn: &mut SqliteConnection, hold_id: i64) -> Result<Hold, String> {
+    sqlx::query_as::<_, Hold>("SELECT * FROM holds WHERE id = ?")
+        .bind(hold_id)
+        .fetch_optional(conn)
+        .await
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| "Reservasi tidak ditemukan".to_string())
+}
+
+// Hand shelf copies of a book to the waiting holds, oldest hold first
+pub async fn fill_waiting_holds(conn: &mut SqliteConnection, book_id: i64) -> Result<(), String> {
+    let pickup_days = settings_commands::get_setting_i64(&mut *conn, "hold_pickup_days", 3).await;
+
+    loop {
+        let hold_id: Option<i64> = sqlx::query_scalar(
+            "SELECT id FROM holds WHERE book_id = ? AND status = 'waiting' ORDER BY id ASC LIMIT 1",
+        )
+        .bind(book_id)
+        .fetch_optional(&mut *conn)
+        .await
+        .map_err(|e| e.to_string())?;
+
+        let item_id: Option<i64> = sqlx::query_scalar(
+            "SELECT id FROM book_items WHERE book_id = ? AND status = 'Tersedia' ORDER BY id ASC LIMIT 1",
+        )
+        .bind(book_id)
+        .fetch_optional(&mut *conn)
+        .await
+        .map_err(|e| e.to_string())?;
+
+        let (Some(hold_id), Some(item_id)) = (hold_id, item_id) else {
+            return Ok(());
+        };
+
+        sqlx::query(
+            "UPDATE book_items SET status = 'Dipesan' WHERE id = ? AND status = 'Tersedia'",
+        )
+        .bind(item_id)
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| e.to_string())?;
+
+        let now = Utc::now();
+        sqlx::query(
+            "UPDATE holds SET status = 'ready', item_id = ?, ready_at = ?, expires_at = ? WHERE id = ?",
+        )
+        .bind(item_id)
+        .bind(now)
+        .bind(now + Duration::days(pickup_days))
+        .bind(hold_id)
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| e.to_string())?;
+    }
+}
+
+// Close a ready hold and pass its copy on to the next member in the queue
+async fn release_hold(
+    conn: &mut SqliteConnection,
+    hold: &Hold,
+    status: &str,
+) -> Result<(), String> {
+    sqlx::query("UPDATE holds SET status = ?, closed_at = ? WHERE id = ?")
+        .bind(status)
+        .bind(Utc::now())
+        .bind(hold.id)
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    if let Some(item_id) = hold.item_id {
+        sqlx::query(
+            "UPDATE book_items SET status = 'Tersedia' WHERE id = ? AND status = 'Dipesan'",
+        )
+        .bind(item_id)
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| e.to_string())?;
+    }
+
+    fill_waiting_holds(conn, hold.book_id).await?;
+    item_commands::refresh_book_stock(conn, hold.book_id).await
+}
+
+// Used by borrow_book: lend the copy kept aside for this member, if there is one
+// and it is the copy being scanned (or no particular copy was scanned)
+pub async fn take_ready_hold(
+    conn: &mut SqliteConnection,
+    member_id: i64,
+    book_id: i64,
+    requested_item: Option<i64>,
+) -> Result<Option<i64>, String> {
+    let hold = sqlx::query_as::<_, Hold>(
+        "SELECT * FROM holds WHERE member_id = ? AND book_id = ? AND status = 'ready' ORDER BY id ASC LIMIT 1",
+    )
+    .bind(member_id)
+    .bind(book_id)
+    .fetch_optional(&mut *conn)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    let Some(Hold {
+        id,
+        item_id: Some(item_id),
+        ..
+    }) = hold
+    else {
+        return Ok(None);
+    };
+    if requested_item.is_some_and(|requested| requested != item_id) {
+        return Ok(None);
+    }
+
+    let res = sqlx::query(
+        "UPDATE book_items SET status = 'Dipinjam' WHERE id = ? AND status = 'Dipesan'",
+    )
+    .bind(item_id)
+    .execute(&mut *conn)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    if res.rows_affected() == 0 {
+        return Err("Eksemplar yang dipesan tidak lagi tersedia".to_string());
+    }
+
+    sqlx::query("UPDATE holds SET status = 'fulfilled', closed_at = ? WHERE id = ?")
+        .bind(Utc::now())
+        .bind(id)
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(Some(item_id))
+}
+
+// Expire ready holds whose pickup window has passed; returns the expired holds
+pub async fn expire_ready_holds(conn: &mut SqliteConnection) -> Result<Vec<Hold>, String> {
+    let expired = sqlx::query_as::<_, Hold>(
+        "SELECT * FROM holds WHERE status = 'ready' AND expires_at < ? ORDER BY id ASC",
+    )
+    .bind(Utc::now())
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    for hold in &expired {
+        release_hold(conn, hold, "expired").await?;
+    }
+
+    Ok(expired)
+}
+
+#[tauri::command]
+pub async fn place_hold(
+    pool: State<'_, SqlitePool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+    book_id: i64,
+    member_id: i64,
+) -> Result<i64, String> {
+    let session = sessions.authorize(&token, Role::Staff)?;
+
+    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+
+    let member_status: String = sqlx::query_scalar("SELECT status FROM members WHERE id = ?")
+        .bind(member_id)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| "Member tidak ditemukan".to_string())?;
+
+    if member_status == "Nonaktif" {
+        return Err("Anggota berstatus Nonaktif tidak dapat memesan buku".to_string());
+    }
+
+    let available: i64 =
+        sqlx::query_scalar("SELECT available_copy FROM books WHERE id = ? AND deleted_at IS NULL")
+            .bind(book_id)
+            .fetch_optional(&mut *tx)
+            .await
+            .map_err(|e| e.to_string())?
+            .ok_or_else(|| "Buku tidak ditemukan".to_string())?;
+
+    if available > 0 {
+        return Err("Buku masih tersedia di rak, silakan langsung dipinjam".to_string());
+    }
+
+    let existing: Option<i64> = sqlx::query_scalar(
+        "SELECT id FROM holds WHERE book_id = ? AND member_id = ? AND status IN ('waiting', 'ready')",
+    )
+    .bind(book_id)
+    .bind(member_id)
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    if existing.is_some() {
+        return Err("Anggota sudah memiliki reservasi untuk buku ini".to_string());
+    }
+
+    let res =
+        sqlx::query("INSERT INTO holds (book_id, member_id, status) VALUES (?, ?, 'waiting')")
+            .bind(book_id)
+            .bind(member_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| e.to_string())?;
+
+    let id = res.last_insert_rowid();
+    let created = find_hold(&mut tx, id).await?;
+    audit::record(
+        &mut tx,
+        &session,
+        "create",
+        "hold",
+        Some(id),
+        None,
+        audit::snapshot(&created),
+    )
+    .await?;
+
+    tx.commit().await.map_err(|e| e.to_string())?;
+
+    Ok(id)
+}
+
+// Active holds (waiting and ready) unless a status is given
+#[tauri::command]
+pub async fn get_holds(
+    pool: State<'_, SqlitePool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+    book_id: Option<i64>,
+    member_id: Option<i64>,
+    status: Option<String>,
+) -> Result<Vec<HoldWithDetails>, String> {
+    sessions.authorize(&token, Role::Viewer)?;
+
+    let mut query: QueryBuilder<Sqlite> = QueryBuilder::new(
+        r#"
+        SELECT
+            h.id, h.book_id, b.title as book_title,
+            h.member_id, m.name as member_name, m.member_code,
+            bi.barcode as item_barcode, h.status,
+            CASE WHEN h.status = 'waiting' THEN (
+                SELECT COUNT(*) FROM holds h2
+                WHERE h2.book_id = h.book_id AND h2.status = 'waiting' AND h2.id <= h.id
+            ) END as queue_position,
+            h.created_at, h.ready_at, h.expires_at
+        FROM holds h
+        JOIN books b ON h.book_id = b.id
+        JOIN members m ON h.member_id = m.id
+        LEFT JOIN book_items bi ON h.item_id = bi.id
+        WHERE 1 = 1
+        "#,
+    );
+
+    match status {
+        Some(status) => {
+            query.push(" AND h.status = ").push_bind(status);
+        }
+        None => {
+            query.push(" AND h.status IN ('waiting', 'ready')");
+        }
+    }
+    if let Some(book_id) = book_id {
+        query.push(" AND h.book_id = ").push_bind(book_id);
+    }
+    if let Some(member_id) = member_id {
+        query.push(" AND h.member_id = ").push_bind(member_id);
+    }
+    query.push(" ORDER BY h.book_id ASC, h.id ASC");
+
+    query
+        .build_query_as::<HoldWithDetails>()
+        .fetch_all(&*pool)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn cancel_hold(
+    pool: State<'_, SqlitePool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+    hold_id: i64,
+) -> Result<(), String> {
+    let session = sessions.authorize(&token, Role::Staff)?;
+
+    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+
+    let before = find_hold(&mut tx, hold_id).await?;
+    if before.status != "waiting" && before.status != "ready" {
+        return Err("Reservasi ini sudah tidak aktif".to_string());
+    }
+
+    release_hold(&mut tx, &before, "cancelled").await?;
+
+    let after = find_hold(&mut tx, hold_id).await?;
+    audit::record(
+        &mut tx,
+        &session,
+        "cancel",
+        "hold",
+        Some(hold_id),
+        audit::snapshot(&before),
+        audit::snapshot(&after),
+    )
+    .await?;
+
+    tx.commit().await.map_err(|e| e.to_string())?;
+
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn expire_holds(
+    pool: State<'_, SqlitePool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+) -> Result<i64, String> {
+    let session = sessions.authorize(&token, Role::Staff)?;
+
+    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+
+    let expired = expire_ready_holds(&mut tx).await?;
+    if !expired.is_empty() {
+        let ids: Vec<i64> = expired.iter().map(|h| h.id).collect();
+        audit::record(
+            &mut tx,
+            &session,
+            "expire",
+            "hold",
+            None,
+            None,
+            Some(json!({ "hold_ids": ids })),
+        )
+        .await?;
+    }
+
+    tx.commit().await.map_err(|e| e.to_string())?;
+
+    Ok(expired.len() as i64)
+}
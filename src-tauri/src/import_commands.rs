@@ -0,0 +1,888 @@
+use crate::audit;
+use crate::auth::{Role, SessionStore};
+use crate::barcode;
+use crate::commands;
+use crate::models::{
+    Book, BookImportReport, FieldChange, ImportIssue, Member, NewMember, RosterChange,
+    RosterImportReport,
+};
+use crate::policy_commands;
+use calamine::{open_workbook_auto, Reader};
+use chrono::{Datelike, Utc};
+use sqlx::SqlitePool;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use tauri::State;
+
+// Book fields that can be imported, with the header names recognised for each
+const BOOK_COLUMNS: [(&str, &[&str]); 12] = [
+    ("title", &["title", "judul", "judul_buku"]),
+    ("author", &["author", "pengarang", "penulis"]),
+    ("isbn", &["isbn"]),
+    ("category", &["category", "kategori"]),
+    ("publisher", &["publisher", "penerbit"]),
+    (
+        "published_year",
+        &["published_year", "year", "tahun", "tahun_terbit"],
+    ),
+    (
+        "rack_location",
+        &["rack_location", "rack", "rak", "lokasi_rak"],
+    ),
+    (
+        "total_copy",
+        &[
+            "total_copy",
+            "copies",
+            "jumlah",
+            "eksemplar",
+            "jumlah_eksemplar",
+        ],
+    ),
+    (
+        "collection_type",
+        &["collection_type", "koleksi", "jenis_koleksi"],
+    ),
+    ("price", &["price", "harga"]),
+    (
+        "call_number",
+        &["call_number", "no_panggil", "nomor_panggil"],
+    ),
+    ("keywords", &["keywords", "kata_kunci", "subjek", "subject"]),
+];
+const REQUIRED_BOOK_COLUMNS: [&str; 3] = ["title", "author", "isbn"];
+
+// Member fields read from the school office roster
+const ROSTER_COLUMNS: [(&str, &[&str]); 6] = [
+    ("nis", &["nis", "no_induk", "nomor_induk", "student_id"]),
+    ("name", &["name", "nama", "nama_siswa", "nama_lengkap"]),
+    ("kelas", &["kelas", "class", "rombel"]),
+    (
+        "jenis_kelamin",
+        &["jenis_kelamin", "gender", "jk", "l_p", "sex"],
+    ),
+    ("email", &["email", "e_mail"]),
+    ("phone", &["phone", "telepon", "no_hp", "hp"]),
+];
+const REQUIRED_ROSTER_COLUMNS: [&str; 3] = ["nis", "name", "kelas"];
+
+// Grades in class names, longest Roman numeral first so "XIA" reads as XI A
+const GRADES: [(&str, &str); 12] = [
+    ("VIII", "8"),
+    ("XII", "12"),
+    ("VII", "7"),
+    ("III", "3"),
+    ("XI", "11"),
+    ("IX", "9"),
+    ("IV", "4"),
+    ("VI", "6"),
+    ("II", "2"),
+    ("X", "10"),
+    ("V", "5"),
+    ("I", "1"),
+];
+
+// A spreadsheet as its header row and data rows of trimmed cell text
+pub(crate) struct Table {
+    pub headers: Vec<String>,
+    pub rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    pub fn read(path: &str) -> Result<Table, String> {
+        let extension = Path::new(path)
+            .extension()
+            .map(|e| e.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        let mut lines = match extension.as_str() {
+            "csv" | "txt" => read_csv(path)?,
+            "xlsx" | "xlsm" | "xls" | "ods" => read_workbook(path)?,
+            _ => return Err("Format file tidak didukung (gunakan CSV atau XLSX)".to_string()),
+        };
+        if lines.is_empty() {
+            return Err("File tidak berisi data".to_string());
+        }
+
+        let headers = lines.remove(0);
+        Ok(Table {
+            headers,
+            rows: lines,
+        })
+    }
+}
+
+fn read_csv(path: &str) -> Result<Vec<Vec<String>>, String> {
+    let content = std::fs::read(path).map_err(|e| format!("Gagal membuka {}: {}", path, e))?;
+    let content = content.strip_prefix(b"\xef\xbb\xbf").unwrap_or(&content);
+
+    // Spreadsheets set to an Indonesian locale export with semicolons
+    let first_line = content.split(|b| *b == b'\n').next().unwrap_or_default();
+    let count = |c: u8| first_line.iter().filter(|b| **b == c).count();
+    let delimiter = if count(b';') > count(b',') {
+        b';'
+    } else {
+        b','
+    };
+
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .delimiter(delimiter)
+        .from_reader(content);
+    reader
+        .byte_records()
+        .map(|record| {
+            record
+                .map(|r| {
+                    r.iter()
+                        .map(|cell| String::from_utf8_lossy(cell).trim().to_string())
+                        .collect()
+                })
+                .map_err(|e| e.to_string())
+        })
+        .collect()
+}
+
+fn read_workbook(path: &str) -> Result<Vec<Vec<String>>, String> {
+    let mut workbook =
+        open_workbook_auto(path).map_err(|e| format!("Gagal membuka {}: {}", path, e))?;
+    let range = workbook
+        .worksheet_range_at(0)
+        .ok_or("File tidak memiliki lembar kerja")?
+        .map_err(|e| e.to_string())?;
+
+    Ok(range
+        .rows()
+        .map(|row| {
+            row.iter()
+                .map(|cell| cell.to_string().trim().to_string())
+                .collect()
+        })
+        .collect())
+}
+
+fn header_key(header: &str) -> String {
+    header
+        .trim()
+        .to_lowercase()
+        .replace([' ', '-', '.', '/'], "_")
+}
+
+// Which column holds each field: the header named in `columns` (field ->
+// header) when given, otherwise the first header matching a known name.
+// Headers that map to nothing are reported as warnings.
+pub(crate) fn map_columns(
+    table: &Table,
+    fields: &[(&str, &[&str])],
+    required: &[&str],
+    columns: &Option<HashMap<String, String>>,
+    issues: &mut Vec<ImportIssue>,
+) -> Result<HashMap<String, usize>, String> {
+    let keys: Vec<String> = table.headers.iter().map(|h| header_key(h)).collect();
+    let mut mapped = HashMap::new();
+
+    for (field, aliases) in fields {
+        let chosen = columns.as_ref().and_then(|c| c.get(*field));
+        let index = match chosen {
+            Some(header) => Some(
+                keys.iter()
+                    .position(|k| *k == header_key(header))
+                    .ok_or_else(|| format!("Kolom '{}' tidak ada di file", header))?,
+            ),
+            None => keys.iter().position(|k| aliases.contains(&k.as_str())),
+        };
+        if let Some(index) = index {
+            mapped.insert(field.to_string(), index);
+        }
+    }
+
+    if let Some(columns) = columns {
+        if let Some(field) = columns
+            .keys()
+            .find(|f| !fields.iter().any(|(name, _)| name == f))
+        {
+            return Err(format!("Kolom tujuan '{}' tidak dikenal", field));
+        }
+    }
+    if let Some(field) = required.iter().find(|f| !mapped.contains_key(**f)) {
+        return Err(format!("Kolom wajib '{}' tidak ditemukan di file", field));
+    }
+
+    for (i, header) in table.headers.iter().enumerate() {
+        if !header.is_empty() && !mapped.values().any(|index| *index == i) {
+            issues.push(ImportIssue {
+                row: 1,
+                column: Some(header.clone()),
+                severity: "warning".to_string(),
+                message: format!("Kolom '{}' tidak dikenali dan diabaikan", header),
+            });
+        }
+    }
+    Ok(mapped)
+}
+
+pub(crate) fn issue(row: usize, column: &str, severity: &str, message: String) -> ImportIssue {
+    ImportIssue {
+        row,
+        column: Some(column.to_string()),
+        severity: severity.to_string(),
+        message,
+    }
+}
+
+// Whole numbers as spreadsheets tend to show them: "2019", "Rp 45.000",
+// "45,000.00"
+fn parse_number(text: &str) -> Option<i64> {
+    let text = text.trim();
+    let text = text
+        .strip_prefix("Rp")
+        .or_else(|| text.strip_prefix("rp"))
+        .unwrap_or(text);
+    let text = text
+        .strip_suffix(",00")
+        .or_else(|| text.strip_suffix(".00"))
+        .or_else(|| text.strip_suffix(".0"))
+        .unwrap_or(text);
+    let digits: String = text
+        .chars()
+        .filter(|c| !matches!(c, '.' | ',' | ' '))
+        .collect();
+    digits.parse().ok()
+}
+
+// Comparable form of a stored ISBN, so "0-306-40615-2" matches 9780306406157
+fn isbn_key(isbn: &str) -> String {
+    barcode::isbn13(isbn).unwrap_or_else(|| {
+        isbn.chars()
+            .filter(|c| !c.is_whitespace() && *c != '-')
+            .collect::<String>()
+            .to_uppercase()
+    })
+}
+
+// Check every row of a CSV or XLSX book list and, unless `dry_run` is set or
+// a row has errors, add all the books in one transaction
+#[tauri::command]
+pub async fn import_books(
+    pool: State<'_, SqlitePool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+    path: String,
+    columns: Option<HashMap<String, String>>,
+    dry_run: bool,
+) -> Result<BookImportReport, String> {
+    let session = sessions.authorize(&token, Role::Staff)?;
+
+    let table = Table::read(path.trim())?;
+    let mut issues = Vec::new();
+    let mapped = map_columns(
+        &table,
+        &BOOK_COLUMNS,
+        &REQUIRED_BOOK_COLUMNS,
+        &columns,
+        &mut issues,
+    )?;
+
+    // Deleted books keep their ISBN, which is still unique
+    let existing: Vec<(String, String, bool)> =
+        sqlx::query_as("SELECT isbn, title, deleted_at IS NOT NULL FROM books")
+            .fetch_all(&*pool)
+            .await
+            .map_err(|e| e.to_string())?;
+    let existing: HashMap<String, (String, bool)> = existing
+        .into_iter()
+        .map(|(isbn, title, deleted)| (isbn_key(&isbn), (title, deleted)))
+        .collect();
+
+    let max_year = Utc::now().year() as i64 + 1;
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    let mut books = Vec::new();
+    let mut total_rows = 0;
+
+    for (index, cells) in table.rows.iter().enumerate() {
+        if cells.iter().all(|c| c.is_empty()) {
+            continue;
+        }
+        total_rows += 1;
+        let row = index + 2;
+        let cell = |field: &str| -> String {
+            mapped
+                .get(field)
+                .and_then(|i| cells.get(*i))
+                .cloned()
+                .unwrap_or_default()
+        };
+        let optional = |field: &str| Some(cell(field)).filter(|v| !v.is_empty());
+        let issues_before = issues.len();
+
+        let title = cell("title");
+        if title.is_empty() {
+            issues.push(issue(
+                row,
+                "title",
+                "error",
+                "Judul wajib diisi".to_string(),
+            ));
+        }
+        let author = cell("author");
+        if author.is_empty() {
+            issues.push(issue(
+                row,
+                "author",
+                "error",
+                "Pengarang wajib diisi".to_string(),
+            ));
+        }
+
+        let raw_isbn = cell("isbn");
+        let isbn = if raw_isbn.is_empty() {
+            issues.push(issue(row, "isbn", "error", "ISBN wajib diisi".to_string()));
+            None
+        } else {
+            match barcode::isbn13(&raw_isbn) {
+                Some(isbn) => {
+                    if raw_isbn
+                        .chars()
+                        .filter(|c| c.is_ascii_alphanumeric())
+                        .count()
+                        == 10
+                    {
+                        issues.push(issue(
+                            row,
+                            "isbn",
+                            "warning",
+                            format!("ISBN-10 '{}' diubah menjadi ISBN-13 {}", raw_isbn, isbn),
+                        ));
+                    }
+                    Some(isbn)
+                }
+                None => {
+                    issues.push(issue(
+                        row,
+                        "isbn",
+                        "error",
+                        format!("ISBN '{}' tidak valid", raw_isbn),
+                    ));
+                    None
+                }
+            }
+        };
+        if let Some(isbn) = &isbn {
+            if let Some((existing_title, deleted)) = existing.get(isbn) {
+                let message = if *deleted {
+                    format!(
+                        "ISBN {} sudah dipakai buku terhapus '{}'",
+                        isbn, existing_title
+                    )
+                } else {
+                    format!("ISBN {} sudah ada: '{}'", isbn, existing_title)
+                };
+                issues.push(issue(row, "isbn", "error", message));
+            } else if let Some(first) = seen.get(isbn) {
+                issues.push(issue(
+                    row,
+                    "isbn",
+                    "error",
+                    format!("ISBN {} sama dengan baris {}", isbn, first),
+                ));
+            } else {
+                seen.insert(isbn.clone(), row);
+            }
+        }
+
+        let published_year = optional("published_year").and_then(|text| {
+            match parse_number(&text).filter(|y| (1000..=max_year).contains(y)) {
+                Some(year) => Some(year),
+                None => {
+                    issues.push(issue(
+                        row,
+                        "published_year",
+                        "error",
+                        format!("Tahun terbit '{}' tidak valid", text),
+                    ));
+                    None
+                }
+            }
+        });
+
+        let total_copy = match optional("total_copy") {
+            None => 1,
+            Some(text) => match parse_number(&text).filter(|n| *n >= 1) {
+                Some(count) => count,
+                None => {
+                    issues.push(issue(
+                        row,
+                        "total_copy",
+                        "error",
+                        format!("Jumlah eksemplar '{}' harus angka minimal 1", text),
+                    ));
+                    0
+                }
+            },
+        };
+
+        let price = optional("price").and_then(|text| match parse_number(&text) {
+            Some(price) if price >= 0 => Some(price),
+            _ => {
+                issues.push(issue(
+                    row,
+                    "price",
+                    "error",
+                    format!("Harga '{}' tidak valid", text),
+                ));
+                None
+            }
+        });
+
+        let collection_type = optional("collection_type").map(|c| c.to_lowercase());
+        if let Some(collection_type) = &collection_type {
+            if let Err(e) = policy_commands::validate_collection_type(collection_type) {
+                issues.push(issue(row, "collection_type", "error", e));
+            }
+        }
+
+        if !issues[issues_before..]
+            .iter()
+            .any(|i| i.severity == "error")
+        {
+            books.push(Book {
+                id: None,
+                title,
+                author,
+                isbn: isbn.unwrap_or_default(),
+                category: optional("category"),
+                publisher: optional("publisher"),
+                published_year,
+                rack_location: optional("rack_location"),
+                barcode: None,
+                total_copy,
+                available_copy: total_copy,
+                cover: None,
+                status: None,
+                created_at: None,
+                collection_type,
+                price,
+                call_number: optional("call_number"),
+                keywords: optional("keywords"),
+            });
+        }
+    }
+
+    let valid_rows = books.len();
+    let mut report = BookImportReport {
+        dry_run,
+        total_rows,
+        valid_rows,
+        imported: 0,
+        issues,
+    };
+    if dry_run || valid_rows < total_rows || books.is_empty() {
+        return Ok(report);
+    }
+
+    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+    for book in books {
+        let created = commands::insert_book(&mut tx, book).await?;
+        audit::record(
+            &mut tx,
+            &session,
+            "import",
+            "book",
+            created.id,
+            None,
+            audit::snapshot(&created),
+        )
+        .await?;
+    }
+    tx.commit().await.map_err(|e| e.to_string())?;
+
+    report.imported = valid_rows;
+    Ok(report)
+}
+
+// Class names the way the app writes them, the grade in Roman numerals and
+// then the section: "10-a", "Kelas X.A" and "XA" all become "X A". None when
+// no grade can be read.
+fn normalize_kelas(text: &str) -> Option<String> {
+    let upper = text.trim().to_uppercase();
+    let upper = upper
+        .strip_prefix("KELAS")
+        .unwrap_or(&upper)
+        .replace(['-', '.', '_', '/'], " ");
+    let upper = upper.trim();
+
+    let digits: String = upper.chars().take_while(|c| c.is_ascii_digit()).collect();
+    let (grade, rest) = if digits.is_empty() {
+        GRADES.iter().find_map(|(roman, _)| {
+            let rest = upper.strip_prefix(roman)?;
+            // A section written against the grade is one letter, maybe numbered
+            let glued = rest.starts_with(|c: char| c.is_ascii_alphabetic());
+            let single = rest.chars().skip(1).all(|c| c.is_ascii_digit());
+            (!glued || single).then_some((*roman, rest))
+        })?
+    } else {
+        let (roman, _) = GRADES.iter().find(|(_, number)| *number == digits)?;
+        (*roman, &upper[digits.len()..])
+    };
+
+    let section = rest.split_whitespace().collect::<Vec<_>>().join(" ");
+    Some(if section.is_empty() {
+        grade.to_string()
+    } else {
+        format!("{} {}", grade, section)
+    })
+}
+
+fn normalize_gender(text: &str) -> Option<&'static str> {
+    match header_key(text).replace('_', "").as_str() {
+        "l" | "lk" | "laki" | "lakilaki" | "pria" | "m" | "male" => Some("Laki-laki"),
+        "p" | "pr" | "perempuan" | "wanita" | "f" | "female" => Some("Perempuan"),
+        _ => None,
+    }
+}
+
+fn field_change(field: &str, old_value: &Option<String>, new_value: &str) -> Option<FieldChange> {
+    (old_value.as_deref() != Some(new_value)).then(|| FieldChange {
+        field: field.to_string(),
+        old_value: old_value.clone(),
+        new_value: Some(new_value.to_string()),
+    })
+}
+
+fn is_active(member: &Member) -> bool {
+    member.status.as_deref().unwrap_or("Aktif") == "Aktif"
+}
+
+// Match a school roster export to the members by NIS: students not yet in
+// the app are created, changed names, classes and genders are updated, and
+// with `deactivate_missing` students no longer listed are deactivated. A
+// member without a NIS is linked by an exact name match. Nothing is written
+// on a dry run or when a row has errors.
+#[tauri::command]
+pub async fn import_roster(
+    pool: State<'_, SqlitePool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+    path: String,
+    columns: Option<HashMap<String, String>>,
+    deactivate_missing: bool,
+    dry_run: bool,
+) -> Result<RosterImportReport, String> {
+    let session = sessions.authorize(&token, Role::Staff)?;
+
+    let table = Table::read(path.trim())?;
+    let mut issues = Vec::new();
+    let mapped = map_columns(
+        &table,
+        &ROSTER_COLUMNS,
+        &REQUIRED_ROSTER_COLUMNS,
+        &columns,
+        &mut issues,
+    )?;
+
+    let members = sqlx::query_as::<_, Member>("SELECT * FROM members")
+        .fetch_all(&*pool)
+        .await
+        .map_err(|e| e.to_string())?;
+    let by_nis: HashMap<&str, &Member> = members
+        .iter()
+        .filter_map(|m| m.nis.as_deref().map(|nis| (nis, m)))
+        .collect();
+    let mut by_name: HashMap<String, Vec<&Member>> = HashMap::new();
+    for member in members.iter().filter(|m| m.nis.is_none()) {
+        by_name
+            .entry(member.name.trim().to_lowercase())
+            .or_default()
+            .push(member);
+    }
+
+    let mut report = RosterImportReport {
+        dry_run,
+        applied: false,
+        total_rows: 0,
+        unchanged: 0,
+        created: Vec::new(),
+        updated: Vec::new(),
+        deactivated: Vec::new(),
+        issues: Vec::new(),
+    };
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    let mut matched: HashSet<i64> = HashSet::new();
+    let mut new_members = Vec::new();
+
+    for (index, cells) in table.rows.iter().enumerate() {
+        if cells.iter().all(|c| c.is_empty()) {
+            continue;
+        }
+        report.total_rows += 1;
+        let row = index + 2;
+        let cell = |field: &str| -> String {
+            mapped
+                .get(field)
+                .and_then(|i| cells.get(*i))
+                .cloned()
+                .unwrap_or_default()
+        };
+        let issues_before = issues.len();
+
+        let nis = cell("nis");
+        if nis.is_empty() {
+            issues.push(issue(row, "nis", "error", "NIS wajib diisi".to_string()));
+        } else if let Some(first) = seen.get(&nis) {
+            issues.push(issue(
+                row,
+                "nis",
+                "error",
+                format!("NIS {} sama dengan baris {}", nis, first),
+            ));
+        } else {
+            seen.insert(nis.clone(), row);
+        }
+        let name = cell("name");
+        if name.is_empty() {
+            issues.push(issue(row, "name", "error", "Nama wajib diisi".to_string()));
+        }
+
+        let raw_kelas = cell("kelas");
+        let kelas = if raw_kelas.is_empty() {
+            issues.push(issue(
+                row,
+                "kelas",
+                "warning",
+                "Kelas kosong; kelas anggota tidak diubah".to_string(),
+            ));
+            None
+        } else {
+            Some(normalize_kelas(&raw_kelas).unwrap_or_else(|| {
+                issues.push(issue(
+                    row,
+                    "kelas",
+                    "warning",
+                    format!("Tingkat kelas '{}' tidak dikenali", raw_kelas),
+                ));
+                raw_kelas.to_uppercase()
+            }))
+        };
+
+        let raw_gender = cell("jenis_kelamin");
+        let gender = normalize_gender(&raw_gender);
+        if gender.is_none() && !raw_gender.is_empty() {
+            issues.push(issue(
+                row,
+                "jenis_kelamin",
+                "warning",
+                format!("Jenis kelamin '{}' tidak dikenali", raw_gender),
+            ));
+        }
+        let email = Some(cell("email")).filter(|v| !v.is_empty());
+        let phone = Some(cell("phone")).filter(|v| !v.is_empty());
+
+        let existing = match by_nis.get(nis.as_str()) {
+            Some(member) => Some(*member),
+            None => match by_name.get(&name.trim().to_lowercase()).map(Vec::as_slice) {
+                Some([member]) if !matched.contains(&member.id.unwrap_or_default()) => {
+                    Some(*member)
+                }
+                Some([_, _, ..]) => {
+                    issues.push(issue(
+                        row,
+                        "name",
+                        "error",
+                        format!(
+                            "Ada beberapa anggota tanpa NIS bernama '{}'; isi NIS-nya terlebih dahulu",
+                            name
+                        ),
+                    ));
+                    None
+                }
+                _ => None,
+            },
+        };
+        if issues[issues_before..]
+            .iter()
+            .any(|i| i.severity == "error")
+        {
+            continue;
+        }
+
+        match existing {
+            Some(member) => {
+                let member_id = member.id.unwrap_or_default();
+                matched.insert(member_id);
+                let mut changes: Vec<FieldChange> = [
+                    field_change("nis", &member.nis, &nis),
+                    field_change("name", &Some(member.name.clone()), &name),
+                ]
+                .into_iter()
+                .flatten()
+                .collect();
+                let optional_fields = [
+                    ("kelas", &member.kelas, kelas.as_deref()),
+                    ("jenis_kelamin", &member.jenis_kelamin, gender),
+                    ("email", &member.email, email.as_deref()),
+                    ("phone", &member.phone, phone.as_deref()),
+                ];
+                for (field, old_value, new_value) in optional_fields {
+                    if let Some(change) =
+                        new_value.and_then(|value| field_change(field, old_value, value))
+                    {
+                        changes.push(change);
+                    }
+                }
+                if !is_active(member) {
+                    changes.extend(field_change("status", &member.status, "Aktif"));
+                }
+
+                if changes.is_empty() {
+                    report.unchanged += 1;
+                } else {
+                    report.updated.push(RosterChange {
+                        row: Some(row),
+                        member_id: Some(member_id),
+                        nis,
+                        name,
+                        changes,
+                    });
+                }
+            }
+            None => {
+                let changes = [
+                    ("nis", Some(nis.as_str())),
+                    ("name", Some(name.as_str())),
+                    ("kelas", kelas.as_deref()),
+                    ("jenis_kelamin", gender),
+                    ("email", email.as_deref()),
+                    ("phone", phone.as_deref()),
+                ]
+                .into_iter()
+                .filter_map(|(field, value)| field_change(field, &None, value?))
+                .collect();
+                report.created.push(RosterChange {
+                    row: Some(row),
+                    member_id: None,
+                    nis: nis.clone(),
+                    name: name.clone(),
+                    changes,
+                });
+                new_members.push(NewMember {
+                    member_code: None,
+                    name,
+                    email,
+                    phone,
+                    kelas,
+                    jenis_kelamin: gender.map(str::to_string),
+                    status: None,
+                    member_type: None,
+                    valid_until: None,
+                    nis: Some(nis),
+                });
+            }
+        }
+    }
+
+    if deactivate_missing {
+        // Only students who came from a roster; teachers and staff are kept
+        for member in members.iter().filter(|m| {
+            m.nis.is_some()
+                && is_active(m)
+                && m.member_type.as_deref().unwrap_or("student") == "student"
+                && !matched.contains(&m.id.unwrap_or_default())
+        }) {
+            let open_loans: i64 = sqlx::query_scalar(
+                "SELECT COUNT(*) FROM loans WHERE member_id = ? AND status = 'borrowed'",
+            )
+            .bind(member.id)
+            .fetch_one(&*pool)
+            .await
+            .map_err(|e| e.to_string())?;
+            if open_loans > 0 {
+                issues.push(ImportIssue {
+                    row: 0,
+                    column: None,
+                    severity: "warning".to_string(),
+                    message: format!(
+                        "{} ({}) tidak ada di daftar tetapi masih meminjam {} buku",
+                        member.name,
+                        member.nis.as_deref().unwrap_or_default(),
+                        open_loans
+                    ),
+                });
+            }
+            report.deactivated.push(RosterChange {
+                row: None,
+                member_id: member.id,
+                nis: member.nis.clone().unwrap_or_default(),
+                name: member.name.clone(),
+                changes: field_change("status", &member.status, "Nonaktif")
+                    .into_iter()
+                    .collect(),
+            });
+        }
+    }
+
+    report.issues = issues;
+    if dry_run || report.issues.iter().any(|i| i.severity == "error") {
+        return Ok(report);
+    }
+
+    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+
+    for member in new_members {
+        let created = commands::insert_member(&mut tx, member).await?;
+        audit::record(
+            &mut tx,
+            &session,
+            "import",
+            "member",
+            created.id,
+            None,
+            audit::snapshot(&created),
+        )
+        .await?;
+    }
+
+    for (change, action) in report
+        .updated
+        .iter()
+        .map(|c| (c, "import"))
+        .chain(report.deactivated.iter().map(|c| (c, "deactivate")))
+    {
+        let before = sqlx::query_as::<_, Member>("SELECT * FROM members WHERE id = ?")
+            .bind(change.member_id)
+            .fetch_one(&mut *tx)
+            .await
+            .map_err(|e| e.to_string())?;
+        // Field names come from the fixed list above, never from the file
+        for field in &change.changes {
+            sqlx::query(&format!(
+                "UPDATE members SET {} = ? WHERE id = ?",
+                field.field
+            ))
+            .bind(&field.new_value)
+            .bind(change.member_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| e.to_string())?;
+        }
+        let after = sqlx::query_as::<_, Member>("SELECT * FROM members WHERE id = ?")
+            .bind(change.member_id)
+            .fetch_one(&mut *tx)
+            .await
+            .map_err(|e| e.to_string())?;
+        audit::record(
+            &mut tx,
+            &session,
+            action,
+            "member",
+            change.member_id,
+            audit::snapshot(&before),
+            audit::snapshot(&after),
+        )
+        .await?;
+    }
+
+    tx.commit().await.map_err(|e| e.to_string())?;
+
+    report.applied = true;
+    Ok(report)
+}
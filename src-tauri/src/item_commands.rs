@@ -0,0 +1,667 @@
+use crate::audit;
+use crate::auth::{Role, SessionStore};
+use crate::hold_commands;
+use crate::models::{BookItem, BookItemDetail, NewBookItem, StockCorrection};
+use chrono::{NaiveDate, Utc};
+use serde_json::json;
+use sqlx::{SqliteConnection, SqlitePool};
+use tauri::State;
+
+pub const ITEM_AVAILABLE: &str = "Tersedia";
+pub const ITEM_ON_LOAN: &str = "Dipinjam";
+pub const ITEM_ON_HOLD: &str = "Dipesan";
+pub const ITEM_LOST: &str = "Hilang";
+pub const ITEM_WITHDRAWN: &str = "Ditarik";
+pub const ITEM_IN_INSPECTION: &str = "Diperiksa";
+
+// Recompute a book's cached copy counters from its items. Lost and withdrawn
+// copies no longer count towards total_copy.
+pub async fn refresh_book_stock(conn: &mut SqliteConnection, book_id: i64) -> Result<(), String> {
+    sqlx::query(
+        r#"
+        UPDATE books SET
+            total_copy = (
+                SELECT COUNT(*) FROM book_items
+                WHERE book_id = books.id AND status NOT IN ('Hilang', 'Ditarik')
+            ),
+            available_copy = (
+                SELECT COUNT(*) FROM book_items
+                WHERE book_id = books.id AND status = 'Tersedia'
+            ),
+            status = CASE
+                WHEN status = 'Tidak Tersedia' THEN status
+                WHEN (SELECT COUNT(*) FROM book_items WHERE book_id = books.id AND status = 'Tersedia') > 0 THEN 'Tersedia'
+                ELSE 'Dipinjam'
+            END
+        WHERE id = ?
+        "#,
+    )
+    .bind(book_id)
+    .execute(conn)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    Ok(())
+}
+
+// Next free "<book barcode>-NN" code for a new copy of the book
+async fn next_item_barcode(conn: &mut SqliteConnection, book_id: i64) -> Result<String, String> {
+    let book_barcode: String =
+        sqlx::query_scalar("SELECT COALESCE(barcode, 'B-' || id) FROM books WHERE id = ?")
+            .bind(book_id)
+            .fetch_optional(&mut *conn)
+            .await
+            .map_err(|e| e.to_string())?
+            .ok_or_else(|| "Buku tidak ditemukan".to_string())?;
+    let prefix = format!("{}-", book_barcode);
+
+    let codes: Vec<String> =
+        sqlx::query_scalar("SELECT barcode FROM book_items WHERE barcode LIKE ?")
+            .bind(format!("{}%", prefix))
+            .fetch_all(&mut *conn)
+            .await
+            .map_err(|e| e.to_string())?;
+
+    let max_num = codes
+        .iter()
+        .filter_map(|code| code.strip_prefix(&prefix))
+        .filter_map(|num| num.parse::<i64>().ok())
+        .max()
+        .unwrap_or(0);
+
+    Ok(format!("{}{:02}", prefix, max_num + 1))
+}
+
+async fn insert_item(
+    conn: &mut SqliteConnection,
+    book_id: i64,
+    barcode: Option<String>,
+    acquisition_date: Option<NaiveDate>,
+    price: Option<i64>,
+    condition: Option<String>,
+    notes: Option<String>,
+) -> Result<i64, String> {
+    let barcode = match barcode {
+        Some(b) if !b.trim().is_empty() => b.trim().to_string(),
+        _ => next_item_barcode(conn, book_id).await?,
+    };
+
+    let existing_id: Option<i64> = sqlx::query_scalar(
+        "SELECT id FROM book_items WHERE barcode = ? UNION SELECT id FROM books WHERE barcode = ?",
+    )
+    .bind(&barcode)
+    .bind(&barcode)
+    .fetch_optional(&mut *conn)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    if existing_id.is_some() {
+        return Err(format!("Barcode '{}' sudah digunakan", barcode));
+    }
+
+    let res = sqlx::query(
+        "INSERT INTO book_items (book_id, barcode, acquisition_date, price, condition, status, notes) VALUES (?, ?, ?, ?, ?, 'Tersedia', ?)",
+    )
+    .bind(book_id)
+    .bind(barcode)
+    .bind(acquisition_date.unwrap_or_else(|| Utc::now().date_naive()))
+    .bind(price)
+    .bind(condition.unwrap_or_else(|| "Bagus".to_string()))
+    .bind(notes)
+    .execute(conn)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    Ok(res.last_insert_rowid())
+}
+
+// Create `count` new shelf copies with generated barcodes
+pub async fn create_items(
+    conn: &mut SqliteConnection,
+    book_id: i64,
+    count: i64,
+) -> Result<Vec<i64>, String> {
+    let mut ids = Vec::new();
+    for _ in 0..count {
+        ids.push(insert_item(conn, book_id, None, None, None, None, None).await?);
+    }
+    Ok(ids)
+}
+
+// Withdraw up to `count` copies that are currently on the shelf, newest first.
+// Returns how many were actually withdrawn.
+pub async fn withdraw_items(
+    conn: &mut SqliteConnection,
+    book_id: i64,
+    count: i64,
+) -> Result<i64, String> {
+    let res = sqlx::query(
+        r#"
+        UPDATE book_items SET status = 'Ditarik'
+        WHERE id IN (
+            SELECT id FROM book_items
+            WHERE book_id = ? AND status = 'Tersedia'
+            ORDER BY id DESC
+            LIMIT ?
+        )
+        "#,
+    )
+    .bind(book_id)
+    .bind(count)
+    .execute(conn)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    Ok(res.rows_affected() as i64)
+}
+
+// Mark a copy as lent out. The status check and the update are one statement,
+// so two desks can never hand out the same copy.
+pub async fn claim_item(
+    conn: &mut SqliteConnection,
+    book_id: i64,
+    item_id: Option<i64>,
+) -> Result<i64, String> {
+    // Without a scanned copy, prefer a shelf copy; a copy kept for a hold is only
+    // picked so the caller gets the reservation message below
+    let item_id = match item_id {
+        Some(id) => id,
+        None => sqlx::query_scalar(
+            "SELECT id FROM book_items WHERE book_id = ? AND status IN ('Tersedia', 'Dipesan') ORDER BY status = 'Tersedia' DESC, id ASC LIMIT 1",
+        )
+        .bind(book_id)
+        .fetch_optional(&mut *conn)
+        .await
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| "Stok buku habis".to_string())?,
+    };
+
+    let res = sqlx::query(
+        "UPDATE book_items SET status = 'Dipinjam' WHERE id = ? AND book_id = ? AND status = 'Tersedia'",
+    )
+    .bind(item_id)
+    .bind(book_id)
+    .execute(&mut *conn)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    if res.rows_affected() == 0 {
+        let status: Option<String> =
+            sqlx::query_scalar("SELECT status FROM book_items WHERE id = ? AND book_id = ?")
+                .bind(item_id)
+                .bind(book_id)
+                .fetch_optional(&mut *conn)
+                .await
+                .map_err(|e| e.to_string())?;
+
+        return Err(match status {
+            Some(status) if status == ITEM_ON_HOLD => {
+                "Eksemplar ini disiapkan untuk reservasi anggota lain".to_string()
+            }
+            Some(status) => format!("Eksemplar ini berstatus {}", status),
+            None => "Eksemplar tidak ditemukan untuk buku ini".to_string(),
+        });
+    }
+
+    Ok(item_id)
+}
+
+// Put a returned copy back on the shelf; copies that are not on loan or
+// waiting for inspection are left alone
+pub async fn release_item(
+    conn: &mut SqliteConnection,
+    item_id: i64,
+    condition: &str,
+) -> Result<(), String> {
+    sqlx::query(
+        "UPDATE book_items SET status = 'Tersedia', condition = ? WHERE id = ? AND status IN ('Dipinjam', 'Diperiksa')",
+    )
+    .bind(condition)
+    .bind(item_id)
+    .execute(conn)
+    .await
+    .map_err(|e| format!("Book item update failed: {}", e))?;
+
+    Ok(())
+}
+
+// A copy dropped at the self-service station stays off the shelf until staff
+// have looked at it
+pub async fn hold_for_inspection(conn: &mut SqliteConnection, item_id: i64) -> Result<(), String> {
+    sqlx::query("UPDATE book_items SET status = 'Diperiksa' WHERE id = ? AND status = 'Dipinjam'")
+        .bind(item_id)
+        .execute(conn)
+        .await
+        .map_err(|e| format!("Book item update failed: {}", e))?;
+
+    Ok(())
+}
+
+// A copy on loan that will not come back leaves the stock
+pub async fn mark_item_lost(conn: &mut SqliteConnection, item_id: i64) -> Result<(), String> {
+    sqlx::query("UPDATE book_items SET status = 'Hilang' WHERE id = ? AND status = 'Dipinjam'")
+        .bind(item_id)
+        .execute(conn)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(())
+}
+
+// A lost copy that turned up again goes back on the shelf
+pub async fn restore_lost_item(
+    conn: &mut SqliteConnection,
+    item_id: i64,
+    condition: &str,
+) -> Result<bool, String> {
+    let res = sqlx::query(
+        "UPDATE book_items SET status = 'Tersedia', condition = ? WHERE id = ? AND status = 'Hilang'",
+    )
+    .bind(condition)
+    .bind(item_id)
+    .execute(conn)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    Ok(res.rows_affected() > 0)
+}
+
+pub async fn find_item_by_barcode(
+    conn: &mut SqliteConnection,
+    barcode: &str,
+) -> Result<Option<BookItemDetail>, String> {
+    sqlx::query_as::<_, BookItemDetail>(
+        r#"
+        SELECT
+            bi.id, bi.book_id, bi.barcode,
+            b.title as book_title, b.isbn as book_isbn, b.rack_location,
+            bi.acquisition_date, bi.price, bi.condition, bi.status
+        FROM book_items bi
+        JOIN books b ON bi.book_id = b.id
+        WHERE bi.barcode = ? AND b.deleted_at IS NULL
+        "#,
+    )
+    .bind(barcode)
+    .fetch_optional(conn)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn get_book_items(
+    pool: State<'_, SqlitePool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+    book_id: i64,
+) -> Result<Vec<BookItem>, String> {
+    sessions.authorize(&token, Role::Viewer)?;
+
+    sqlx::query_as::<_, BookItem>("SELECT * FROM book_items WHERE book_id = ? ORDER BY barcode ASC")
+        .bind(book_id)
+        .fetch_all(&*pool)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn find_book_item(
+    pool: State<'_, SqlitePool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+    barcode: String,
+) -> Result<BookItemDetail, String> {
+    sessions.authorize(&token, Role::Viewer)?;
+
+    let mut conn = pool.acquire().await.map_err(|e| e.to_string())?;
+    find_item_by_barcode(&mut conn, barcode.trim())
+        .await?
+        .ok_or_else(|| "Eksemplar tidak ditemukan".to_string())
+}
+
+#[tauri::command]
+pub async fn add_book_item(
+    pool: State<'_, SqlitePool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+    item: NewBookItem,
+) -> Result<i64, String> {
+    let session = sessions.authorize(&token, Role::Staff)?;
+
+    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+
+    let id = insert_item(
+        &mut tx,
+        item.book_id,
+        item.barcode,
+        item.acquisition_date,
+        item.price,
+        item.condition,
+        item.notes,
+    )
+    .await?;
+    hold_commands::fill_waiting_holds(&mut tx, item.book_id).await?;
+    refresh_book_stock(&mut tx, item.book_id).await?;
+
+    let created = sqlx::query_as::<_, BookItem>("SELECT * FROM book_items WHERE id = ?")
+        .bind(id)
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+    audit::record(
+        &mut tx,
+        &session,
+        "create",
+        "book_item",
+        Some(id),
+        None,
+        audit::snapshot(&created),
+    )
+    .await?;
+
+    tx.commit().await.map_err(|e| e.to_string())?;
+
+    Ok(id)
+}
+
+#[tauri::command]
+pub async fn update_book_item(
+    pool: State<'_, SqlitePool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+    item: BookItem,
+) -> Result<(), String> {
+    let session = sessions.authorize(&token, Role::Staff)?;
+
+    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+
+    let before = sqlx::query_as::<_, BookItem>("SELECT * FROM book_items WHERE id = ?")
+        .bind(item.id)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| "Eksemplar tidak ditemukan".to_string())?;
+
+    // Loan state is owned by borrow/return; only shelf states can be set by hand
+    if item.status != before.status {
+        if before.status == ITEM_ON_LOAN {
+            return Err("Eksemplar sedang dipinjam, kembalikan terlebih dahulu".to_string());
+        }
+        if before.status == ITEM_ON_HOLD {
+            return Err(
+                "Eksemplar sedang disiapkan untuk reservasi, batalkan reservasi terlebih dahulu"
+                    .to_string(),
+            );
+        }
+        if before.status == ITEM_IN_INSPECTION {
+            return Err("Eksemplar menunggu pemeriksaan pengembalian mandiri".to_string());
+        }
+        if ![ITEM_AVAILABLE, ITEM_WITHDRAWN].contains(&item.status.as_str()) {
+            return Err(format!(
+                "Status eksemplar '{}' tidak dapat diatur manual",
+                item.status
+            ));
+        }
+    }
+
+    let duplicate: Option<i64> = sqlx::query_scalar(
+        "SELECT id FROM book_items WHERE barcode = ? AND id != ? UNION SELECT id FROM books WHERE barcode = ?",
+    )
+    .bind(&item.barcode)
+    .bind(item.id)
+    .bind(&item.barcode)
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    if duplicate.is_some() {
+        return Err(format!("Barcode '{}' sudah digunakan", item.barcode));
+    }
+
+    sqlx::query(
+        "UPDATE book_items SET barcode = ?, acquisition_date = ?, price = ?, condition = ?, status = ?, notes = ? WHERE id = ?",
+    )
+    .bind(&item.barcode)
+    .bind(item.acquisition_date)
+    .bind(item.price)
+    .bind(&item.condition)
+    .bind(&item.status)
+    .bind(&item.notes)
+    .bind(item.id)
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    hold_commands::fill_waiting_holds(&mut tx, before.book_id).await?;
+    refresh_book_stock(&mut tx, before.book_id).await?;
+
+    let after = sqlx::query_as::<_, BookItem>("SELECT * FROM book_items WHERE id = ?")
+        .bind(item.id)
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+    audit::record(
+        &mut tx,
+        &session,
+        "update",
+        "book_item",
+        item.id,
+        audit::snapshot(&before),
+        audit::snapshot(&after),
+    )
+    .await?;
+
+    tx.commit().await.map_err(|e| e.to_string())?;
+
+    Ok(())
+}
+
+// Repair copies and counters that no longer match the open loans
+#[tauri::command]
+pub async fn reconcile_stock(
+    pool: State<'_, SqlitePool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+) -> Result<Vec<StockCorrection>, String> {
+    let session = sessions.authorize(&token, Role::Admin)?;
+
+    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+    let mut corrections = Vec::new();
+
+    // Copies marked as lent out without an open loan go back on the shelf
+    let stray: Vec<(i64, i64, String, String)> = sqlx::query_as(
+        r#"
+        SELECT bi.id, bi.book_id, b.title, bi.barcode
+        FROM book_items bi
+        JOIN books b ON bi.book_id = b.id
+        WHERE bi.status = 'Dipinjam'
+        AND NOT EXISTS (SELECT 1 FROM loans l WHERE l.item_id = bi.id AND l.status = 'borrowed')
+        "#,
+    )
+    .fetch_all(&mut *tx)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    for (item_id, book_id, title, barcode) in stray {
+        sqlx::query("UPDATE book_items SET status = 'Tersedia' WHERE id = ?")
+            .bind(item_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| e.to_string())?;
+        corrections.push(StockCorrection {
+            book_id,
+            book_title: title,
+            item_barcode: Some(barcode),
+            field: "status".to_string(),
+            old_value: ITEM_ON_LOAN.to_string(),
+            new_value: ITEM_AVAILABLE.to_string(),
+            reason: "Tidak ada peminjaman aktif untuk eksemplar ini".to_string(),
+        });
+    }
+
+    // Copies kept aside for pickup need a ready hold that points at them
+    let stray_held: Vec<(i64, i64, String, String)> = sqlx::query_as(
+        r#"
+        SELECT bi.id, bi.book_id, b.title, bi.barcode
+        FROM book_items bi
+        JOIN books b ON bi.book_id = b.id
+        WHERE bi.status = 'Dipesan'
+        AND NOT EXISTS (SELECT 1 FROM holds h WHERE h.item_id = bi.id AND h.status = 'ready')
+        "#,
+    )
+    .fetch_all(&mut *tx)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    for (item_id, book_id, title, barcode) in stray_held {
+        sqlx::query("UPDATE book_items SET status = 'Tersedia' WHERE id = ?")
+            .bind(item_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| e.to_string())?;
+        corrections.push(StockCorrection {
+            book_id,
+            book_title: title,
+            item_barcode: Some(barcode),
+            field: "status".to_string(),
+            old_value: ITEM_ON_HOLD.to_string(),
+            new_value: ITEM_AVAILABLE.to_string(),
+            reason: "Tidak ada reservasi yang menunggu eksemplar ini".to_string(),
+        });
+    }
+
+    // Copies held by an open loan must be marked as lent out
+    let held: Vec<(i64, i64, String, String, String)> = sqlx::query_as(
+        r#"
+        SELECT DISTINCT bi.id, bi.book_id, b.title, bi.barcode, bi.status
+        FROM book_items bi
+        JOIN books b ON bi.book_id = b.id
+        JOIN loans l ON l.item_id = bi.id AND l.status = 'borrowed'
+        WHERE bi.status != 'Dipinjam'
+        "#,
+    )
+    .fetch_all(&mut *tx)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    for (item_id, book_id, title, barcode, status) in held {
+        sqlx::query("UPDATE book_items SET status = 'Dipinjam' WHERE id = ?")
+            .bind(item_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| e.to_string())?;
+        corrections.push(StockCorrection {
+            book_id,
+            book_title: title,
+            item_barcode: Some(barcode),
+            field: "status".to_string(),
+            old_value: status,
+            new_value: ITEM_ON_LOAN.to_string(),
+            reason: "Eksemplar masih tercatat dalam peminjaman aktif".to_string(),
+        });
+    }
+
+    // Open loans without a copy get one from the shelf, or a new copy if none is left
+    let unassigned: Vec<(i64, i64, String)> = sqlx::query_as(
+        r#"
+        SELECT l.id, l.book_id, b.title
+        FROM loans l
+        JOIN books b ON l.book_id = b.id
+        WHERE l.status = 'borrowed' AND l.item_id IS NULL
+        ORDER BY l.id ASC
+        "#,
+    )
+    .fetch_all(&mut *tx)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    for (loan_id, book_id, title) in unassigned {
+        let item_id = match claim_item(&mut tx, book_id, None).await {
+            Ok(id) => id,
+            Err(_) => {
+                let id = insert_item(&mut tx, book_id, None, None, None, None, None).await?;
+                claim_item(&mut tx, book_id, Some(id)).await?
+            }
+        };
+        sqlx::query("UPDATE loans SET item_id = ? WHERE id = ?")
+            .bind(item_id)
+            .bind(loan_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| e.to_string())?;
+
+        let barcode: String = sqlx::query_scalar("SELECT barcode FROM book_items WHERE id = ?")
+            .bind(item_id)
+            .fetch_one(&mut *tx)
+            .await
+            .map_err(|e| e.to_string())?;
+        corrections.push(StockCorrection {
+            book_id,
+            book_title: title,
+            item_barcode: Some(barcode),
+            field: "loan".to_string(),
+            old_value: "-".to_string(),
+            new_value: format!("Peminjaman #{}", loan_id),
+            reason: "Peminjaman aktif belum terhubung ke eksemplar".to_string(),
+        });
+    }
+
+    // Finally the cached counters on every book
+    let counters: Vec<(i64, String, i64, i64, i64, i64)> = sqlx::query_as(
+        r#"
+        SELECT b.id, b.title, b.total_copy, b.available_copy,
+            (SELECT COUNT(*) FROM book_items WHERE book_id = b.id AND status NOT IN ('Hilang', 'Ditarik')),
+            (SELECT COUNT(*) FROM book_items WHERE book_id = b.id AND status = 'Tersedia')
+        FROM books b
+        "#,
+    )
+    .fetch_all(&mut *tx)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    for (book_id, title, total, available, owned, on_shelf) in counters {
+        if total == owned && available == on_shelf {
+            continue;
+        }
+        refresh_book_stock(&mut tx, book_id).await?;
+
+        if total != owned {
+            corrections.push(StockCorrection {
+                book_id,
+                book_title: title.clone(),
+                item_barcode: None,
+                field: "total_copy".to_string(),
+                old_value: total.to_string(),
+                new_value: owned.to_string(),
+                reason: "Disesuaikan dengan jumlah eksemplar".to_string(),
+            });
+        }
+        if available != on_shelf {
+            corrections.push(StockCorrection {
+                book_id,
+                book_title: title,
+                item_barcode: None,
+                field: "available_copy".to_string(),
+                old_value: available.to_string(),
+                new_value: on_shelf.to_string(),
+                reason: "Disesuaikan dengan eksemplar yang ada di rak".to_string(),
+            });
+        }
+    }
+
+    if !corrections.is_empty() {
+        audit::record(
+            &mut tx,
+            &session,
+            "reconcile",
+            "stock",
+            None,
+            None,
+            Some(json!({ "corrections": corrections })),
+        )
+        .await?;
+    }
+
+    tx.commit().await.map_err(|e| e.to_string())?;
+
+    Ok(corrections)
+}
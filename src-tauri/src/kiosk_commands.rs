@@ -0,0 +1,109 @@
+use crate::auth::Role;
+use crate::commands;
+use crate::models::{BookAvailability, BookSearchQuery, BookSearchResult};
+use crate::search_commands;
+use sqlx::SqlitePool;
+use tauri::{AppHandle, State};
+
+// What this PC is used for, chosen at startup. The reading-room kiosk and the
+// self-service station only get their own commands registered, so nothing
+// else can be called without restarting the app normally.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Station {
+    Desk,
+    Kiosk,
+    SelfService,
+}
+
+pub struct StationMode(pub Station);
+
+const KIOSK_MAX_RESULTS: i64 = 50;
+
+// Chosen with the --kiosk or --self-service flag, or with
+// LIBRESPACE_STATION=kiosk or self-service
+pub fn station() -> Station {
+    let configured = std::env::var("LIBRESPACE_STATION")
+        .unwrap_or_default()
+        .trim()
+        .to_lowercase();
+    let flag = |name: &str| std::env::args().any(|arg| arg == format!("--{}", name));
+
+    if flag("kiosk") || configured == "kiosk" {
+        Station::Kiosk
+    } else if flag("self-service") || configured == "self-service" {
+        Station::SelfService
+    } else {
+        Station::Desk
+    }
+}
+
+// "desk", "kiosk" or "self_service"
+#[tauri::command]
+pub fn get_station_mode(mode: State<'_, StationMode>) -> String {
+    match mode.inner().0 {
+        Station::Desk => "desk",
+        Station::Kiosk => "kiosk",
+        Station::SelfService => "self_service",
+    }
+    .to_string()
+}
+
+#[tauri::command]
+pub async fn kiosk_search_books(
+    pool: State<'_, SqlitePool>,
+    query: BookSearchQuery,
+) -> Result<Vec<BookSearchResult>, String> {
+    let query = BookSearchQuery {
+        limit: Some(
+            query
+                .limit
+                .unwrap_or(KIOSK_MAX_RESULTS)
+                .min(KIOSK_MAX_RESULTS),
+        ),
+        ..query
+    };
+    search_commands::search(&pool, query).await
+}
+
+// Copies on the shelf and, for a title that is all out, when the first copy
+// is due back. Nothing about the borrowers is returned.
+#[tauri::command]
+pub async fn kiosk_book_availability(
+    pool: State<'_, SqlitePool>,
+    book_id: i64,
+) -> Result<BookAvailability, String> {
+    sqlx::query_as::<_, BookAvailability>(
+        r#"
+        SELECT b.id AS book_id, b.title, b.author, b.call_number, b.rack_location,
+               b.total_copy, b.available_copy,
+               (SELECT COUNT(*) FROM holds WHERE book_id = b.id AND status = 'waiting') AS holds_waiting,
+               (SELECT MIN(due_date) FROM loans WHERE book_id = b.id AND status = 'borrowed') AS next_due_date
+        FROM books b
+        WHERE b.id = ? AND b.deleted_at IS NULL
+        "#,
+    )
+    .bind(book_id)
+    .fetch_optional(&*pool)
+    .await
+    .map_err(|e| e.to_string())?
+    .ok_or("Buku tidak ditemukan".to_string())
+}
+
+// Closing the kiosk or the self-service station takes an administrator's
+// username and password
+#[tauri::command]
+pub async fn exit_kiosk(
+    app: AppHandle,
+    pool: State<'_, SqlitePool>,
+    username: String,
+    password: String,
+) -> Result<(), String> {
+    let mut conn = pool.acquire().await.map_err(|e| e.to_string())?;
+    let user = commands::authenticate(&mut conn, &username, &password).await?;
+    if Role::from_db(&user.role) != Some(Role::Admin) {
+        return Err("Hanya administrator yang dapat keluar dari mode kios".to_string());
+    }
+
+    app.exit(0);
+    Ok(())
+}
@@ -0,0 +1,462 @@
+use crate::auth::{Role, SessionStore};
+use crate::barcode;
+use crate::models::{LabelLayout, LabelRequest};
+use crate::printing::{self, fit_text, Branding, PT_TO_MM};
+use printpdf::{BuiltinFont, IndirectFontRef, Mm, PdfDocument, PdfLayerReference, Rect};
+use sqlx::{SqliteConnection, SqlitePool};
+use std::path::Path;
+use tauri::State;
+
+const PAGE_WIDTH: f32 = 210.0;
+const PAGE_HEIGHT: f32 = 297.0;
+// Blank modules required on each side of a barcode
+const QUIET_ZONE: f32 = 10.0;
+
+// Label sheets cut for A4 printers. Sizes are in mm; labels are filled row by row.
+fn layouts() -> Vec<LabelLayout> {
+    vec![
+        LabelLayout {
+            code: "tj103".to_string(),
+            name: "Tom & Jerry 103 (64 x 32 mm, 3 x 8)".to_string(),
+            label_width: 64.0,
+            label_height: 32.0,
+            columns: 3,
+            rows: 8,
+            margin_left: 6.0,
+            margin_top: 13.5,
+            gap_x: 3.0,
+            gap_y: 2.0,
+        },
+        LabelLayout {
+            code: "tj121".to_string(),
+            name: "Tom & Jerry 121 (38 x 18 mm, 5 x 14)".to_string(),
+            label_width: 38.0,
+            label_height: 18.0,
+            columns: 5,
+            rows: 14,
+            margin_left: 6.0,
+            margin_top: 16.0,
+            gap_x: 2.0,
+            gap_y: 1.0,
+        },
+    ]
+}
+
+// Something drawn on a page, positioned in mm from the top left corner. Text
+// is centered on `x` with its baseline at `y`.
+enum Shape {
+    Bar {
+        x: f32,
+        y: f32,
+        w: f32,
+        h: f32,
+    },
+    Text {
+        x: f32,
+        y: f32,
+        size: f32,
+        bold: bool,
+        text: String,
+    },
+}
+
+struct LabelItem {
+    barcode: String,
+    title: String,
+    author: String,
+    isbn: String,
+    call_number: Option<String>,
+    rack_location: Option<String>,
+}
+
+async fn items_for_request(
+    conn: &mut SqliteConnection,
+    request: &LabelRequest,
+) -> Result<Vec<LabelItem>, String> {
+    const SELECT: &str = r#"
+        SELECT bi.barcode, b.title, b.author, b.isbn, b.call_number, b.rack_location
+        FROM book_items bi
+        JOIN books b ON bi.book_id = b.id
+    "#;
+    type Row = (
+        String,
+        String,
+        String,
+        String,
+        Option<String>,
+        Option<String>,
+    );
+
+    let mut rows: Vec<Row> = Vec::new();
+    // A book gets one label per copy it still owns
+    for book_id in &request.book_ids {
+        let copies: Vec<Row> = sqlx::query_as(&format!(
+            "{} WHERE bi.book_id = ? AND bi.status NOT IN ('Hilang', 'Ditarik') ORDER BY bi.id ASC",
+            SELECT
+        ))
+        .bind(book_id)
+        .fetch_all(&mut *conn)
+        .await
+        .map_err(|e| e.to_string())?;
+        if copies.is_empty() {
+            return Err(format!("Buku #{} tidak memiliki eksemplar", book_id));
+        }
+        rows.extend(copies);
+    }
+    for item_id in &request.item_ids {
+        let copy: Row = sqlx::query_as(&format!("{} WHERE bi.id = ?", SELECT))
+            .bind(item_id)
+            .fetch_optional(&mut *conn)
+            .await
+            .map_err(|e| e.to_string())?
+            .ok_or_else(|| format!("Eksemplar #{} tidak ditemukan", item_id))?;
+        rows.push(copy);
+    }
+
+    if rows.is_empty() {
+        return Err("Pilih minimal satu buku atau eksemplar".to_string());
+    }
+
+    Ok(rows
+        .into_iter()
+        .map(
+            |(barcode, title, author, isbn, call_number, rack_location)| LabelItem {
+                barcode,
+                title,
+                author,
+                isbn,
+                call_number,
+                rack_location,
+            },
+        )
+        .collect())
+}
+
+fn barcode_shapes(modules: &[bool], x: f32, y: f32, w: f32, h: f32) -> Vec<Shape> {
+    let module = w / (modules.len() as f32 + 2.0 * QUIET_ZONE);
+    let mut shapes = Vec::new();
+    let mut i = 0;
+    while i < modules.len() {
+        if modules[i] {
+            let start = i;
+            while i < modules.len() && modules[i] {
+                i += 1;
+            }
+            shapes.push(Shape::Bar {
+                x: x + (QUIET_ZONE + start as f32) * module,
+                y,
+                w: (i - start) as f32 * module,
+                h,
+            });
+        } else {
+            i += 1;
+        }
+    }
+    shapes
+}
+
+fn barcode_label(
+    item: &LabelItem,
+    symbology: &str,
+    library: &str,
+    x: f32,
+    y: f32,
+    layout: &LabelLayout,
+) -> Result<Vec<Shape>, String> {
+    let (w, h) = (layout.label_width, layout.label_height);
+    // Font sizes are set for the 32 mm label and scaled for smaller ones
+    let unit = h / 32.0;
+    let center = x + w / 2.0;
+
+    let (value, modules) = match symbology {
+        "ean13" => {
+            let isbn = barcode::isbn13(&item.isbn).ok_or_else(|| {
+                format!(
+                    "ISBN '{}' ({}) tidak dapat dibuat EAN-13",
+                    item.isbn, item.title
+                )
+            })?;
+            let modules = barcode::ean13(&isbn)?;
+            (isbn, modules)
+        }
+        _ => (item.barcode.clone(), barcode::code128(&item.barcode)?),
+    };
+
+    let mut shapes = vec![
+        Shape::Text {
+            x: center,
+            y: y + 8.0 * unit,
+            size: 7.0 * unit,
+            bold: true,
+            text: fit_text(library, 7.0 * unit, w - 2.0),
+        },
+        Shape::Text {
+            x: center,
+            y: y + 11.5 * unit,
+            size: 6.0 * unit,
+            bold: false,
+            text: fit_text(&item.title, 6.0 * unit, w - 2.0),
+        },
+    ];
+    shapes.extend(barcode_shapes(
+        &modules,
+        x + 1.0,
+        y + 13.0 * unit,
+        w - 2.0,
+        13.0 * unit,
+    ));
+    shapes.push(Shape::Text {
+        x: center,
+        y: y + 29.5 * unit,
+        size: 8.0 * unit,
+        bold: false,
+        text: fit_text(&value, 8.0 * unit, w - 2.0),
+    });
+    Ok(shapes)
+}
+
+// Call number lines as entered, or the author's first three letters and the
+// title's first letter
+fn call_number_lines(item: &LabelItem) -> Vec<String> {
+    match item.call_number.as_deref().map(str::trim) {
+        Some(call_number) if !call_number.is_empty() => {
+            call_number.split_whitespace().map(str::to_string).collect()
+        }
+        _ => vec![
+            item.author
+                .chars()
+                .filter(|c| c.is_alphabetic())
+                .take(3)
+                .collect::<String>()
+                .to_uppercase(),
+            item.title
+                .chars()
+                .find(|c| c.is_alphanumeric())
+                .map(|c| c.to_lowercase().to_string())
+                .unwrap_or_default(),
+        ],
+    }
+}
+
+fn spine_label(item: &LabelItem, x: f32, y: f32, layout: &LabelLayout) -> Vec<Shape> {
+    let (w, h) = (layout.label_width, layout.label_height);
+    let center = x + w / 2.0;
+    let lines: Vec<String> = call_number_lines(item).into_iter().take(3).collect();
+
+    // The call number gets most of the label; the rack location sits underneath
+    let line_height = h * 0.72 / lines.len().max(1) as f32;
+    let size = (line_height * 0.8 / PT_TO_MM).min(h * 0.5);
+    let mut shapes: Vec<Shape> = lines
+        .iter()
+        .enumerate()
+        .map(|(i, line)| Shape::Text {
+            x: center,
+            y: y + (i as f32 + 0.85) * line_height,
+            size,
+            bold: true,
+            text: fit_text(line, size, w - 2.0),
+        })
+        .collect();
+
+    if let Some(rack) = item
+        .rack_location
+        .as_deref()
+        .filter(|r| !r.trim().is_empty())
+    {
+        let size = h * 0.14 / PT_TO_MM;
+        shapes.push(Shape::Text {
+            x: center,
+            y: y + h - h * 0.08,
+            size,
+            bold: false,
+            text: fit_text(&format!("Rak {}", rack), size, w - 2.0),
+        });
+    }
+    shapes
+}
+
+fn pdf_shape(
+    layer: &PdfLayerReference,
+    shape: &Shape,
+    regular: &IndirectFontRef,
+    bold_font: &IndirectFontRef,
+) {
+    match shape {
+        Shape::Bar { x, y, w, h } => layer.add_rect(Rect::new(
+            Mm(*x),
+            Mm(PAGE_HEIGHT - y - h),
+            Mm(x + w),
+            Mm(PAGE_HEIGHT - y),
+        )),
+        Shape::Text {
+            x,
+            y,
+            size,
+            bold,
+            text,
+        } => {
+            let width = text.len() as f32 * 0.6 * size * PT_TO_MM;
+            layer.use_text(
+                text.clone(),
+                *size,
+                Mm(x - width / 2.0),
+                Mm(PAGE_HEIGHT - y),
+                if *bold { bold_font } else { regular },
+            );
+        }
+    }
+}
+
+fn render_pdf(pages: &[Vec<Shape>]) -> Result<Vec<u8>, String> {
+    let (doc, page, layer) = PdfDocument::new("Label", Mm(PAGE_WIDTH), Mm(PAGE_HEIGHT), "Label");
+    let regular = doc
+        .add_builtin_font(BuiltinFont::Courier)
+        .map_err(|e| e.to_string())?;
+    let bold = doc
+        .add_builtin_font(BuiltinFont::CourierBold)
+        .map_err(|e| e.to_string())?;
+
+    let mut current = (page, layer);
+    for (i, shapes) in pages.iter().enumerate() {
+        if i > 0 {
+            current = doc.add_page(Mm(PAGE_WIDTH), Mm(PAGE_HEIGHT), "Label");
+        }
+        let layer = doc.get_page(current.0).get_layer(current.1);
+        for shape in shapes {
+            pdf_shape(&layer, shape, &regular, &bold);
+        }
+    }
+
+    doc.save_to_bytes().map_err(|e| e.to_string())
+}
+
+fn svg_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn render_svg(shapes: &[Shape]) -> String {
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}mm\" height=\"{h}mm\" viewBox=\"0 0 {w} {h}\">\n",
+        w = PAGE_WIDTH,
+        h = PAGE_HEIGHT
+    );
+    for shape in shapes {
+        match shape {
+            Shape::Bar { x, y, w, h } => svg.push_str(&format!(
+                "<rect x=\"{:.3}\" y=\"{:.3}\" width=\"{:.3}\" height=\"{:.3}\"/>\n",
+                x, y, w, h
+            )),
+            Shape::Text {
+                x,
+                y,
+                size,
+                bold,
+                text,
+            } => svg.push_str(&format!(
+                "<text x=\"{:.3}\" y=\"{:.3}\" font-family=\"Courier, monospace\" font-size=\"{:.3}\" font-weight=\"{}\" text-anchor=\"middle\">{}</text>\n",
+                x,
+                y,
+                size * PT_TO_MM,
+                if *bold { "bold" } else { "normal" },
+                svg_escape(text)
+            )),
+        }
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+// page-2.svg, page-3.svg, ... next to the first page's file
+fn svg_page_path(path: &str, page: usize) -> String {
+    if page == 0 {
+        return path.to_string();
+    }
+    let path = Path::new(path);
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    path.with_file_name(format!("{}-{}.svg", stem, page + 1))
+        .to_string_lossy()
+        .to_string()
+}
+
+#[tauri::command]
+pub async fn get_label_layouts(
+    sessions: State<'_, SessionStore>,
+    token: String,
+) -> Result<Vec<LabelLayout>, String> {
+    sessions.authorize(&token, Role::Viewer)?;
+    Ok(layouts())
+}
+
+// Render barcode or spine labels for the selected books and copies. Returns the
+// files written: one PDF, or one SVG per sheet.
+#[tauri::command]
+pub async fn generate_labels(
+    pool: State<'_, SqlitePool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+    request: LabelRequest,
+) -> Result<Vec<String>, String> {
+    sessions.authorize(&token, Role::Staff)?;
+
+    let layout = layouts()
+        .into_iter()
+        .find(|l| l.code == request.layout)
+        .ok_or_else(|| format!("Format lembar label '{}' tidak dikenal", request.layout))?;
+    let symbology = request.symbology.as_deref().unwrap_or("code128");
+    if symbology != "code128" && symbology != "ean13" {
+        return Err("Jenis barcode tidak valid (gunakan code128 atau ean13)".to_string());
+    }
+    if request.label_type != "barcode" && request.label_type != "spine" {
+        return Err("Jenis label tidak valid (gunakan barcode atau spine)".to_string());
+    }
+    if request.format != "pdf" && request.format != "svg" {
+        return Err("Format label tidak valid (gunakan pdf atau svg)".to_string());
+    }
+
+    let mut conn = pool.acquire().await.map_err(|e| e.to_string())?;
+    let items = items_for_request(&mut conn, &request).await?;
+    let branding = Branding::load(&mut conn).await;
+
+    // Labels already peeled off a used sheet are skipped
+    let per_page = (layout.columns * layout.rows) as usize;
+    let skip = (request.start_position.unwrap_or(1).max(1) as usize - 1) % per_page;
+
+    let mut pages: Vec<Vec<Shape>> = Vec::new();
+    for (i, item) in items.iter().enumerate() {
+        let slot = skip + i;
+        let (page, position) = (slot / per_page, slot % per_page);
+        if pages.len() <= page {
+            pages.push(Vec::new());
+        }
+        let column = (position % layout.columns as usize) as f32;
+        let row = (position / layout.columns as usize) as f32;
+        let x = layout.margin_left + column * (layout.label_width + layout.gap_x);
+        let y = layout.margin_top + row * (layout.label_height + layout.gap_y);
+
+        let shapes = match request.label_type.as_str() {
+            "spine" => spine_label(item, x, y, &layout),
+            _ => barcode_label(item, symbology, &branding.name, x, y, &layout)?,
+        };
+        pages[page].extend(shapes);
+    }
+
+    let path = printing::output_path(&mut conn, &request.path, "label", &request.format).await?;
+    if request.format == "pdf" {
+        printing::write_output(&path, &render_pdf(&pages)?)?;
+        return Ok(vec![path]);
+    }
+
+    let mut written = Vec::new();
+    for (i, shapes) in pages.iter().enumerate() {
+        let page_path = svg_page_path(&path, i);
+        printing::write_output(&page_path, render_svg(shapes).as_bytes())?;
+        written.push(page_path);
+    }
+    Ok(written)
+}
@@ -1,28 +1,91 @@
+mod audit;
+mod auth;
+mod barcode;
 mod commands;
 pub mod dashboard_commands;
 pub mod dashboard_models;
 mod db;
+pub mod fine_commands;
+pub mod eligibility_commands;
+pub mod hold_commands;
+pub mod import_commands;
+pub mod item_commands;
+pub mod kiosk_commands;
+pub mod label_commands;
+pub mod member_card_commands;
 mod models;
+mod paging;
+pub mod policy_commands;
+mod printing;
+pub mod receipt_commands;
+pub mod rollover_commands;
+pub mod search_commands;
+pub mod self_service_commands;
 pub mod settings_commands;
+pub mod user_commands;
+pub mod visit_commands;
 
+use auth::SessionStore;
+use kiosk_commands::{Station, StationMode};
 use tauri::Manager;
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
-    tauri::Builder::default()
+    let station = kiosk_commands::station();
+
+    let builder = tauri::Builder::default()
         .plugin(tauri_plugin_opener::init())
-        .setup(|app| {
+        .manage(SessionStore::default())
+        .manage(StationMode(station))
+        .setup(move |app| {
             let handle = app.handle().clone();
             tauri::async_runtime::block_on(async move {
                 let pool = db::init_db(&handle)
                     .await
                     .expect("Failed to initialize database");
+                // Late fines on open loans are brought up to date once a day
+                tauri::async_runtime::spawn(fine_commands::run_daily_accrual(pool.clone()));
                 handle.manage(pool);
             });
+            if station != Station::Desk {
+                if let Some(window) = app.get_webview_window("main") {
+                    window.set_fullscreen(true)?;
+                }
+            }
             Ok(())
+        });
+
+    // A station window can't be closed except through exit_kiosk
+    let builder = if station == Station::Desk {
+        builder
+    } else {
+        builder.on_window_event(|_, event| {
+            if let tauri::WindowEvent::CloseRequested { api, .. } = event {
+                api.prevent_close();
+            }
         })
-        .invoke_handler(tauri::generate_handler![
+    };
+
+    // The reading-room kiosk gets the public catalogue commands only, the
+    // self-service station its checkout and return commands only
+    let builder = match station {
+        Station::Kiosk => builder.invoke_handler(tauri::generate_handler![
+            kiosk_commands::get_station_mode,
+            kiosk_commands::kiosk_search_books,
+            kiosk_commands::kiosk_book_availability,
+            kiosk_commands::exit_kiosk
+        ]),
+        Station::SelfService => builder.invoke_handler(tauri::generate_handler![
+            kiosk_commands::get_station_mode,
+            self_service_commands::self_checkout_identify,
+            self_service_commands::self_checkout,
+            self_service_commands::self_checkout_receipt,
+            self_service_commands::self_return,
+            kiosk_commands::exit_kiosk
+        ]),
+        Station::Desk => builder.invoke_handler(tauri::generate_handler![
             commands::get_books,
+            search_commands::search_books,
             commands::add_book,
             commands::update_book,
             commands::delete_book,
@@ -32,12 +95,17 @@ pub fn run() {
             commands::delete_member,
             commands::borrow_book,
             commands::return_book,
+            commands::declare_loan_lost,
+            commands::mark_loan_found,
+            commands::renew_loan,
+            commands::get_loan_renewals,
             commands::get_recent_returns,
             commands::get_active_loans,
             commands::get_stats,
             commands::get_recent_activity,
             commands::get_weekly_circulation,
             commands::login,
+            commands::logout,
             commands::find_member_by_code,
             commands::find_book_by_isbn,
             commands::find_active_loan,
@@ -60,11 +128,74 @@ pub fn run() {
             settings_commands::reset_database,
             settings_commands::get_app_version,
             settings_commands::get_settings,
+            settings_commands::get_display_settings,
             settings_commands::update_setting,
+            audit::get_audit_log,
+            item_commands::get_book_items,
+            item_commands::find_book_item,
+            item_commands::add_book_item,
+            item_commands::update_book_item,
+            item_commands::reconcile_stock,
+            policy_commands::get_loan_policies,
+            policy_commands::get_loan_policy,
+            policy_commands::save_loan_policy,
+            hold_commands::place_hold,
+            hold_commands::get_holds,
+            hold_commands::cancel_hold,
+            hold_commands::expire_holds,
+            eligibility_commands::check_borrow_eligibility,
+            eligibility_commands::grant_borrow_override,
+            fine_commands::get_accrued_fines,
+            fine_commands::run_fine_accrual,
+            fine_commands::get_closure_days,
+            fine_commands::add_closure_day,
+            fine_commands::delete_closure_day,
+            fine_commands::get_fine_payments,
+            fine_commands::waive_fine,
+            fine_commands::reverse_fine_payment,
+            fine_commands::get_member_fine_balance,
+            fine_commands::get_daily_cash_total,
+            receipt_commands::print_loan_receipt,
+            receipt_commands::print_return_receipt,
+            receipt_commands::print_payment_receipt,
+            label_commands::get_label_layouts,
+            label_commands::generate_labels,
+            member_card_commands::set_member_photo,
+            member_card_commands::generate_member_cards,
+            import_commands::import_books,
+            import_commands::import_roster,
+            rollover_commands::run_academic_rollover,
+            rollover_commands::undo_academic_rollover,
+            rollover_commands::get_academic_rollovers,
+            rollover_commands::get_rollover_promotions,
+            rollover_commands::get_clearance_blockers,
+            rollover_commands::get_library_clearance,
+            rollover_commands::complete_graduation,
+            rollover_commands::print_clearance_letter,
+            user_commands::get_users,
+            user_commands::create_user,
+            user_commands::update_user_role,
+            user_commands::disable_user,
+            user_commands::enable_user,
+            user_commands::delete_user,
+            user_commands::reset_user_password,
+            user_commands::unlock_user,
+            user_commands::get_login_events,
             dashboard_commands::get_popular_categories,
             dashboard_commands::get_most_borrowed_books,
-            dashboard_commands::get_member_activity_stats
-        ])
+            dashboard_commands::get_member_activity_stats,
+            self_service_commands::get_pending_self_returns,
+            self_service_commands::inspect_self_return,
+            visit_commands::check_in_member,
+            visit_commands::check_in_guest,
+            visit_commands::check_out_visit,
+            visit_commands::get_visits,
+            visit_commands::get_visit_stats,
+            kiosk_commands::get_station_mode
+        ]),
+    };
+
+    builder
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
 }
@@ -0,0 +1,461 @@
+use crate::audit;
+use crate::auth::{Role, SessionStore};
+use crate::barcode;
+use crate::models::{Member, MemberCardRequest};
+use crate::printing::{self, fit_text, Branding, Jpeg, PT_TO_MM};
+use crate::settings_commands;
+use chrono::NaiveDate;
+use printpdf::image_crate::{imageops::FilterType, DynamicImage, GenericImageView};
+use printpdf::path::PaintMode;
+use printpdf::{BuiltinFont, IndirectFontRef, Mm, PdfDocument, PdfLayerReference, Rect};
+use qrcode::{Color, QrCode};
+use sqlx::{SqliteConnection, SqlitePool};
+use tauri::State;
+
+// ISO/IEC 7810 ID-1, the size of a bank card
+const CARD_WIDTH: f32 = 85.6;
+const CARD_HEIGHT: f32 = 54.0;
+const SHEET_WIDTH: f32 = 210.0;
+const SHEET_HEIGHT: f32 = 297.0;
+const SHEET_COLUMNS: usize = 2;
+const SHEET_ROWS: usize = 5;
+const SHEET_GAP: f32 = 3.0;
+
+const PHOTO_WIDTH: f32 = 20.0;
+const PHOTO_HEIGHT: f32 = 25.0;
+// Photos and the logo are scaled down to about 300 dpi at their printed size,
+// which keeps a whole school year's batch of cards to a manageable file
+const PHOTO_PIXELS: (u32, u32) = (240, 300);
+const LOGO_PIXELS: u32 = 120;
+// Blank modules required around a barcode and a QR code
+const BARCODE_QUIET_ZONE: f32 = 10.0;
+const QR_QUIET_ZONE: f32 = 4.0;
+
+#[derive(sqlx::FromRow)]
+struct CardMember {
+    member_code: String,
+    name: String,
+    kelas: Option<String>,
+    valid_until: Option<NaiveDate>,
+    photo: Option<String>,
+}
+
+async fn members_for_request(
+    conn: &mut SqliteConnection,
+    request: &MemberCardRequest,
+) -> Result<Vec<CardMember>, String> {
+    const SELECT: &str = "SELECT member_code, name, kelas, valid_until, photo FROM members";
+
+    let mut members = Vec::new();
+    for member_id in &request.member_ids {
+        let member: CardMember = sqlx::query_as(&format!("{} WHERE id = ?", SELECT))
+            .bind(member_id)
+            .fetch_optional(&mut *conn)
+            .await
+            .map_err(|e| e.to_string())?
+            .ok_or_else(|| format!("Anggota #{} tidak ditemukan", member_id))?;
+        members.push(member);
+    }
+
+    if let Some(kelas) = request
+        .kelas
+        .as_deref()
+        .map(str::trim)
+        .filter(|k| !k.is_empty())
+    {
+        let class_members: Vec<CardMember> = sqlx::query_as(&format!(
+            "{} WHERE kelas = ? AND (status = 'Aktif' OR status IS NULL) ORDER BY name ASC",
+            SELECT
+        ))
+        .bind(kelas)
+        .fetch_all(&mut *conn)
+        .await
+        .map_err(|e| e.to_string())?;
+        if class_members.is_empty() {
+            return Err(format!("Tidak ada anggota aktif di kelas {}", kelas));
+        }
+        members.extend(class_members);
+    }
+
+    if members.is_empty() {
+        return Err("Pilih minimal satu anggota atau kelas".to_string());
+    }
+    Ok(members)
+}
+
+// Draws one card whose top left corner is (x, y) mm from the top left of the
+// page. Coordinates passed to the methods are relative to the card.
+struct Card<'a> {
+    layer: &'a PdfLayerReference,
+    regular: &'a IndirectFontRef,
+    bold: &'a IndirectFontRef,
+    page_height: f32,
+    x: f32,
+    y: f32,
+}
+
+impl Card<'_> {
+    fn rect(&self, x: f32, y: f32, w: f32, h: f32, mode: PaintMode) {
+        self.layer.add_rect(
+            Rect::new(
+                Mm(self.x + x),
+                Mm(self.page_height - self.y - y - h),
+                Mm(self.x + x + w),
+                Mm(self.page_height - self.y - y),
+            )
+            .with_mode(mode),
+        );
+    }
+
+    // Text with its baseline at `y`, starting at `x`
+    fn text(&self, x: f32, y: f32, size: f32, bold: bool, text: &str) {
+        self.layer.use_text(
+            text,
+            size,
+            Mm(self.x + x),
+            Mm(self.page_height - self.y - y),
+            if bold { self.bold } else { self.regular },
+        );
+    }
+
+    // Text cut to `width` and centered on `center`
+    fn centered(&self, center: f32, y: f32, width: f32, size: f32, bold: bool, text: &str) {
+        let text = fit_text(text, size, width);
+        let text_width = text.len() as f32 * 0.6 * size * PT_TO_MM;
+        self.text(center - text_width / 2.0, y, size, bold, &text);
+    }
+
+    fn image(&self, image: &Jpeg, x: f32, y: f32, width: f32) {
+        let height = image.height(width);
+        image.place(
+            self.layer,
+            self.x + x,
+            self.page_height - self.y - y - height,
+            width,
+        );
+    }
+
+    // Horizontal runs of dark modules in one row as filled bars
+    fn module_row(&self, modules: &[bool], x: f32, y: f32, module: f32, height: f32) {
+        let mut i = 0;
+        while i < modules.len() {
+            if modules[i] {
+                let start = i;
+                while i < modules.len() && modules[i] {
+                    i += 1;
+                }
+                self.rect(
+                    x + start as f32 * module,
+                    y,
+                    (i - start) as f32 * module,
+                    height,
+                    PaintMode::Fill,
+                );
+            } else {
+                i += 1;
+            }
+        }
+    }
+
+    fn barcode(&self, data: &str, x: f32, y: f32, w: f32, h: f32) -> Result<(), String> {
+        let modules = barcode::code128(data)?;
+        let module = w / (modules.len() as f32 + 2.0 * BARCODE_QUIET_ZONE);
+        self.module_row(&modules, x + BARCODE_QUIET_ZONE * module, y, module, h);
+        Ok(())
+    }
+
+    fn qr(&self, data: &str, x: f32, y: f32, size: f32) -> Result<(), String> {
+        let code = QrCode::new(data.as_bytes()).map_err(|e| e.to_string())?;
+        let width = code.width();
+        let module = size / (width as f32 + 2.0 * QR_QUIET_ZONE);
+        let origin = QR_QUIET_ZONE * module;
+        let dark: Vec<bool> = code.to_colors().iter().map(|c| *c == Color::Dark).collect();
+        for (row, modules) in dark.chunks(width).enumerate() {
+            self.module_row(
+                modules,
+                x + origin,
+                y + origin + row as f32 * module,
+                module,
+                module,
+            );
+        }
+        Ok(())
+    }
+}
+
+// Scale an image down so it fits in `max_width` x `max_height` pixels
+fn shrink(image: DynamicImage, max_width: u32, max_height: u32) -> DynamicImage {
+    let (w, h) = image.dimensions();
+    if w <= max_width && h <= max_height {
+        image
+    } else {
+        image.resize(max_width, max_height, FilterType::Triangle)
+    }
+}
+
+fn draw_card(
+    card: &Card,
+    member: &CardMember,
+    header: &str,
+    branding: &Branding,
+    logo: Option<&Jpeg>,
+    symbology: &str,
+) -> Result<(), String> {
+    // Header: logo on the left, card title and library name beside it
+    let mut header_left = 3.0;
+    if let Some(logo) = logo {
+        let width = (8.0 / logo.height(1.0)).min(14.0);
+        card.image(logo, 3.0, 2.0, width);
+        header_left += width + 2.0;
+    }
+    let header_center = (header_left + CARD_WIDTH - 3.0) / 2.0;
+    let header_width = CARD_WIDTH - 3.0 - header_left;
+    card.centered(header_center, 5.5, header_width, 7.0, true, header);
+    card.centered(header_center, 9.5, header_width, 6.5, false, &branding.name);
+    card.rect(3.0, 11.8, CARD_WIDTH - 6.0, 0.3, PaintMode::Fill);
+
+    let photo = member
+        .photo
+        .as_deref()
+        .and_then(printing::load_image)
+        .map(|image| image.resize_to_fill(PHOTO_PIXELS.0, PHOTO_PIXELS.1, FilterType::Triangle));
+    match photo {
+        Some(photo) => card.image(&Jpeg::encode(&photo)?, 3.0, 15.0, PHOTO_WIDTH),
+        None => {
+            card.rect(3.0, 15.0, PHOTO_WIDTH, PHOTO_HEIGHT, PaintMode::Stroke);
+            card.centered(
+                3.0 + PHOTO_WIDTH / 2.0,
+                28.5,
+                PHOTO_WIDTH,
+                7.0,
+                false,
+                "FOTO",
+            );
+        }
+    }
+
+    // A QR code takes the right side, leaving the details a narrower column
+    let details_left = 26.0;
+    let details_width = if symbology == "qr" {
+        33.0
+    } else {
+        CARD_WIDTH - 3.0 - details_left
+    };
+    let valid_until = member
+        .valid_until
+        .map(|d| d.format("%d-%m-%Y").to_string())
+        .unwrap_or_else(|| "-".to_string());
+    card.text(
+        details_left,
+        19.0,
+        8.0,
+        true,
+        &fit_text(&member.name, 8.0, details_width),
+    );
+    let rows = [
+        format!("Kelas       : {}", member.kelas.as_deref().unwrap_or("-")),
+        format!("No. Anggota : {}", member.member_code),
+        format!("Berlaku s/d : {}", valid_until),
+    ];
+    for (i, row) in rows.iter().enumerate() {
+        card.text(
+            details_left,
+            24.5 + i as f32 * 4.5,
+            6.0,
+            false,
+            &fit_text(row, 6.0, details_width),
+        );
+    }
+
+    if symbology == "qr" {
+        let size = 22.0;
+        let left = CARD_WIDTH - 3.0 - size;
+        card.qr(&member.member_code, left, 14.5, size)?;
+        card.centered(
+            left + size / 2.0,
+            39.0,
+            size + 2.0,
+            6.0,
+            false,
+            &member.member_code,
+        );
+    } else {
+        card.barcode(&member.member_code, details_left, 40.5, details_width, 8.5)?;
+        card.centered(
+            details_left + details_width / 2.0,
+            52.0,
+            details_width,
+            6.0,
+            false,
+            &member.member_code,
+        );
+    }
+    Ok(())
+}
+
+fn render_cards(
+    members: &[CardMember],
+    header: &str,
+    branding: &Branding,
+    symbology: &str,
+    sheet: bool,
+) -> Result<Vec<u8>, String> {
+    let (page_width, page_height, per_page) = if sheet {
+        (SHEET_WIDTH, SHEET_HEIGHT, SHEET_COLUMNS * SHEET_ROWS)
+    } else {
+        (CARD_WIDTH, CARD_HEIGHT, 1)
+    };
+    // Cards are centered on the sheet with a cutting outline around each
+    let margin_left =
+        (SHEET_WIDTH - SHEET_COLUMNS as f32 * (CARD_WIDTH + SHEET_GAP) + SHEET_GAP) / 2.0;
+    let margin_top =
+        (SHEET_HEIGHT - SHEET_ROWS as f32 * (CARD_HEIGHT + SHEET_GAP) + SHEET_GAP) / 2.0;
+
+    let logo = branding.logo.as_ref().map(Jpeg::encode).transpose()?;
+
+    let (doc, page, layer) =
+        PdfDocument::new("Kartu Anggota", Mm(page_width), Mm(page_height), "Kartu");
+    let regular = doc
+        .add_builtin_font(BuiltinFont::Courier)
+        .map_err(|e| e.to_string())?;
+    let bold = doc
+        .add_builtin_font(BuiltinFont::CourierBold)
+        .map_err(|e| e.to_string())?;
+
+    let mut current = (page, layer);
+    for (i, chunk) in members.chunks(per_page).enumerate() {
+        if i > 0 {
+            current = doc.add_page(Mm(page_width), Mm(page_height), "Kartu");
+        }
+        let layer = doc.get_page(current.0).get_layer(current.1);
+        layer.set_outline_thickness(0.3);
+
+        for (position, member) in chunk.iter().enumerate() {
+            let (x, y) = if sheet {
+                (
+                    margin_left + (position % SHEET_COLUMNS) as f32 * (CARD_WIDTH + SHEET_GAP),
+                    margin_top + (position / SHEET_COLUMNS) as f32 * (CARD_HEIGHT + SHEET_GAP),
+                )
+            } else {
+                (0.0, 0.0)
+            };
+            let card = Card {
+                layer: &layer,
+                regular: &regular,
+                bold: &bold,
+                page_height,
+                x,
+                y,
+            };
+            if sheet {
+                card.rect(0.0, 0.0, CARD_WIDTH, CARD_HEIGHT, PaintMode::Stroke);
+            }
+            draw_card(&card, member, header, branding, logo.as_ref(), symbology)?;
+        }
+    }
+
+    let bytes = doc.save_to_bytes().map_err(|e| e.to_string())?;
+    printing::share_identical_images(bytes)
+}
+
+// Set or clear (with None or an empty path) the photo printed on a member's card
+#[tauri::command]
+pub async fn set_member_photo(
+    pool: State<'_, SqlitePool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+    member_id: i64,
+    photo: Option<String>,
+) -> Result<(), String> {
+    let session = sessions.authorize(&token, Role::Staff)?;
+
+    let photo = photo
+        .map(|p| p.trim().to_string())
+        .filter(|p| !p.is_empty());
+    if let Some(path) = &photo {
+        if printing::load_image(path).is_none() {
+            return Err(format!("File foto '{}' tidak dapat dibuka", path));
+        }
+    }
+
+    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+
+    let before = sqlx::query_as::<_, Member>("SELECT * FROM members WHERE id = ?")
+        .bind(member_id)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?
+        .ok_or("Anggota tidak ditemukan")?;
+
+    sqlx::query("UPDATE members SET photo = ? WHERE id = ?")
+        .bind(&photo)
+        .bind(member_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let after = sqlx::query_as::<_, Member>("SELECT * FROM members WHERE id = ?")
+        .bind(member_id)
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+    audit::record(
+        &mut tx,
+        &session,
+        "update",
+        "member",
+        Some(member_id),
+        audit::snapshot(&before),
+        audit::snapshot(&after),
+    )
+    .await?;
+
+    tx.commit().await.map_err(|e| e.to_string())?;
+
+    Ok(())
+}
+
+// Render membership cards to one PDF and return its path
+#[tauri::command]
+pub async fn generate_member_cards(
+    pool: State<'_, SqlitePool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+    request: MemberCardRequest,
+) -> Result<String, String> {
+    sessions.authorize(&token, Role::Staff)?;
+
+    let symbology = request.symbology.as_deref().unwrap_or("code128");
+    if symbology != "code128" && symbology != "qr" {
+        return Err("Jenis kode tidak valid (gunakan code128 atau qr)".to_string());
+    }
+    let layout = request.layout.as_deref().unwrap_or("a4");
+    if layout != "a4" && layout != "card" {
+        return Err("Tata letak kartu tidak valid (gunakan a4 atau card)".to_string());
+    }
+
+    let mut conn = pool.acquire().await.map_err(|e| e.to_string())?;
+    let members = members_for_request(&mut conn, &request).await?;
+    let mut branding = Branding::load(&mut conn).await;
+    branding.logo = branding
+        .logo
+        .map(|logo| shrink(logo, LOGO_PIXELS, LOGO_PIXELS));
+    let header = settings_commands::get_setting_string(
+        &mut *conn,
+        "card_header",
+        "KARTU ANGGOTA PERPUSTAKAAN",
+    )
+    .await;
+
+    let path = printing::output_path(&mut conn, &request.path, "kartu_anggota", "pdf").await?;
+    // A whole year's batch takes a while, so it is drawn off the async runtime
+    let symbology = symbology.to_string();
+    let sheet = layout == "a4";
+    let bytes = tokio::task::spawn_blocking(move || {
+        render_cards(&members, &header, &branding, &symbology, sheet)
+    })
+    .await
+    .map_err(|e| e.to_string())??;
+    printing::write_output(&path, &bytes)?;
+    Ok(path)
+}
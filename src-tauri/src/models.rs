@@ -1,4 +1,4 @@
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, NaiveDate, Utc};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
@@ -17,6 +17,59 @@ pub struct Book {
     pub cover: Option<String>,
     pub status: Option<String>,
     pub created_at: Option<DateTime<Utc>>,
+    pub collection_type: Option<String>,
+    pub price: Option<i64>,
+    pub call_number: Option<String>,
+    pub keywords: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct BookItem {
+    pub id: Option<i64>,
+    pub book_id: i64,
+    pub barcode: String,
+    pub acquisition_date: Option<NaiveDate>,
+    pub price: Option<i64>,
+    pub condition: String,
+    pub status: String,
+    pub notes: Option<String>,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NewBookItem {
+    pub book_id: i64,
+    pub barcode: Option<String>,
+    pub acquisition_date: Option<NaiveDate>,
+    pub price: Option<i64>,
+    pub condition: Option<String>,
+    pub notes: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct BookItemDetail {
+    pub id: i64,
+    pub book_id: i64,
+    pub barcode: String,
+    pub book_title: String,
+    pub book_isbn: String,
+    pub rack_location: Option<String>,
+    pub acquisition_date: Option<NaiveDate>,
+    pub price: Option<i64>,
+    pub condition: String,
+    pub status: String,
+}
+
+// One repair made by reconcile_stock
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StockCorrection {
+    pub book_id: i64,
+    pub book_title: String,
+    pub item_barcode: Option<String>,
+    pub field: String,
+    pub old_value: String,
+    pub new_value: String,
+    pub reason: String,
 }
 
 #[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
@@ -30,6 +83,10 @@ pub struct Member {
     pub jenis_kelamin: Option<String>,
     pub status: Option<String>,
     pub joined_at: Option<DateTime<Utc>>,
+    pub member_type: Option<String>,
+    pub valid_until: Option<NaiveDate>,
+    pub photo: Option<String>,
+    pub nis: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
@@ -43,6 +100,23 @@ pub struct Loan {
     pub status: String,
     pub book_condition: Option<String>,
     pub damage_category: Option<String>,
+    pub item_id: Option<i64>,
+    pub renewal_count: i64,
+    pub accrued_fine: i64,
+    pub fine_accrued_at: Option<DateTime<Utc>>,
+    pub lost_at: Option<DateTime<Utc>>,
+    pub found_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct LoanRenewal {
+    pub id: i64,
+    pub loan_id: i64,
+    pub previous_due_date: DateTime<Utc>,
+    pub new_due_date: DateTime<Utc>,
+    pub renewed_by: Option<i64>,
+    pub renewed_by_name: Option<String>,
+    pub renewed_at: DateTime<Utc>,
 }
 
 #[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
@@ -63,6 +137,21 @@ pub struct User {
     pub name: String,
     pub role: String,
     pub avatar: Option<String>,
+    pub status: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AuthSession {
+    pub token: String,
+    pub user: User,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NewUser {
+    pub username: String,
+    pub password: String,
+    pub name: String,
+    pub role: String,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -74,9 +163,11 @@ pub struct NewMember {
     pub kelas: Option<String>,
     pub jenis_kelamin: Option<String>,
     pub status: Option<String>,
+    pub member_type: Option<String>,
+    pub valid_until: Option<NaiveDate>,
+    pub nis: Option<String>,
 }
 
-#[allow(dead_code)]
 #[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
 pub struct Fine {
     pub id: Option<i64>,
@@ -101,7 +192,523 @@ pub struct FineWithDetails {
     pub due_date: DateTime<Utc>,
     pub return_date: Option<DateTime<Utc>>,
     pub amount: i64,
+    pub paid_amount: i64,
+    pub waived_amount: i64,
+    pub remaining_amount: i64,
     pub fine_type: String,
     pub status: String,
     pub paid_at: Option<DateTime<Utc>>,
 }
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct FinePayment {
+    pub id: i64,
+    pub fine_id: i64,
+    pub entry_type: String,
+    pub amount: i64,
+    pub method: Option<String>,
+    pub reason: Option<String>,
+    pub reversal_of: Option<i64>,
+    pub received_by: Option<i64>,
+    pub received_by_name: Option<String>,
+    pub reversed: bool,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct MemberFineBalance {
+    pub member_id: i64,
+    pub total_amount: i64,
+    pub paid_amount: i64,
+    pub waived_amount: i64,
+    pub remaining_amount: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DailyCashTotal {
+    pub date: NaiveDate,
+    pub cash_amount: i64,
+    pub transfer_amount: i64,
+    pub total_amount: i64,
+    pub waived_amount: i64,
+    pub payment_count: i64,
+    pub reversal_count: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct AuditLogEntry {
+    pub id: i64,
+    pub user_id: Option<i64>,
+    pub username: String,
+    pub action: String,
+    pub entity: String,
+    pub entity_id: Option<i64>,
+    pub before_data: Option<String>,
+    pub after_data: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct AuditLogFilter {
+    pub date_from: Option<String>,
+    pub date_to: Option<String>,
+    pub user_id: Option<i64>,
+    pub entity: Option<String>,
+    pub entity_id: Option<i64>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct LoginEvent {
+    pub id: i64,
+    pub username: String,
+    pub user_id: Option<i64>,
+    pub success: bool,
+    pub reason: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct LoanPolicy {
+    pub id: Option<i64>,
+    pub member_type: String,
+    pub collection_type: String,
+    pub loan_days: i64,
+    pub max_loans: i64,
+    pub max_renewals: i64,
+    pub fine_per_day: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct Hold {
+    pub id: i64,
+    pub book_id: i64,
+    pub member_id: i64,
+    pub item_id: Option<i64>,
+    pub status: String,
+    pub created_at: DateTime<Utc>,
+    pub ready_at: Option<DateTime<Utc>>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub closed_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct HoldWithDetails {
+    pub id: i64,
+    pub book_id: i64,
+    pub book_title: String,
+    pub member_id: i64,
+    pub member_name: String,
+    pub member_code: String,
+    pub item_barcode: Option<String>,
+    pub status: String,
+    pub queue_position: Option<i64>,
+    pub created_at: DateTime<Utc>,
+    pub ready_at: Option<DateTime<Utc>>,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EligibilityBlock {
+    pub code: String,
+    pub message: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BorrowEligibility {
+    pub member_id: i64,
+    pub eligible: bool,
+    pub reasons: Vec<EligibilityBlock>,
+    pub override_available: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct BorrowOverride {
+    pub id: i64,
+    pub member_id: i64,
+    pub reason: String,
+    pub granted_by: Option<i64>,
+    pub created_at: DateTime<Utc>,
+    pub used_at: Option<DateTime<Utc>>,
+    pub loan_id: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct ClosureDay {
+    pub date: NaiveDate,
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AccruedFine {
+    pub loan_id: i64,
+    pub book_id: i64,
+    pub book_title: String,
+    pub member_id: i64,
+    pub member_name: String,
+    pub member_code: String,
+    pub member_kelas: Option<String>,
+    pub due_date: DateTime<Utc>,
+    pub days_late: i64,
+    pub chargeable_days: i64,
+    pub amount: i64,
+    pub capped: bool,
+}
+
+// Where and how a receipt is printed. format is "pdf" or "escpos"; path is a
+// file, or a printer device for escpos. paper_width defaults to the setting.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReceiptOutput {
+    pub format: String,
+    pub paper_width: Option<i64>,
+    pub path: String,
+}
+
+// An A4 label sheet; all sizes in mm
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LabelLayout {
+    pub code: String,
+    pub name: String,
+    pub label_width: f32,
+    pub label_height: f32,
+    pub columns: u32,
+    pub rows: u32,
+    pub margin_left: f32,
+    pub margin_top: f32,
+    pub gap_x: f32,
+    pub gap_y: f32,
+}
+
+// label_type is "barcode" or "spine"; symbology "code128" (copy barcode, the
+// default) or "ean13" (ISBN); format "pdf" or "svg". start_position is the
+// first free label on a partly used sheet, counted from 1.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LabelRequest {
+    #[serde(default)]
+    pub book_ids: Vec<i64>,
+    #[serde(default)]
+    pub item_ids: Vec<i64>,
+    pub label_type: String,
+    pub symbology: Option<String>,
+    pub layout: String,
+    pub format: String,
+    pub start_position: Option<i64>,
+    pub path: Option<String>,
+}
+
+// Membership cards for the given members, or for every active member of
+// `kelas`. symbology is "code128" (the default) or "qr"; layout "a4" fits ten
+// cards on a sheet (the default), "card" gives one card-sized page per member
+// for card printers.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MemberCardRequest {
+    #[serde(default)]
+    pub member_ids: Vec<i64>,
+    pub kelas: Option<String>,
+    pub symbology: Option<String>,
+    pub layout: Option<String>,
+    pub path: Option<String>,
+}
+
+// A problem found while checking an import file. `row` is the spreadsheet row
+// number, counting the header as row 1; severity is "error" or "warning".
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ImportIssue {
+    pub row: usize,
+    pub column: Option<String>,
+    pub severity: String,
+    pub message: String,
+}
+
+// Outcome of import_books. Nothing is written on a dry run or when any row
+// has an error; `imported` counts the books created.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BookImportReport {
+    pub dry_run: bool,
+    pub total_rows: usize,
+    pub valid_rows: usize,
+    pub imported: usize,
+    pub issues: Vec<ImportIssue>,
+}
+
+// A member field the roster import changes
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FieldChange {
+    pub field: String,
+    pub old_value: Option<String>,
+    pub new_value: Option<String>,
+}
+
+// One member in the roster import preview. member_id is None for members the
+// import creates; row is None for members missing from the roster.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RosterChange {
+    pub row: Option<usize>,
+    pub member_id: Option<i64>,
+    pub nis: String,
+    pub name: String,
+    pub changes: Vec<FieldChange>,
+}
+
+// Outcome of import_roster. Nothing is written on a dry run or when any row
+// has an error; `applied` tells whether the changes were saved.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RosterImportReport {
+    pub dry_run: bool,
+    pub applied: bool,
+    pub total_rows: usize,
+    pub unchanged: usize,
+    pub created: Vec<RosterChange>,
+    pub updated: Vec<RosterChange>,
+    pub deactivated: Vec<RosterChange>,
+    pub issues: Vec<ImportIssue>,
+}
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct AcademicRollover {
+    pub id: i64,
+    pub academic_year: String,
+    pub promotion_map: String,
+    pub performed_by: i64,
+    pub performed_by_name: Option<String>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub undone_at: Option<DateTime<Utc>>,
+    pub promoted_count: i64,
+    pub graduated_count: i64,
+    pub blocked_count: i64,
+}
+
+// A member's move in a rollover. outcome is "promoted", "graduated",
+// "blocked" (a graduate with open loans or unpaid fines) or, in previews only,
+// "skipped" (the grade is not in the promotion map).
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct MemberPromotion {
+    pub id: Option<i64>,
+    pub rollover_id: Option<i64>,
+    pub member_id: i64,
+    pub member_name: String,
+    pub nis: Option<String>,
+    pub old_kelas: Option<String>,
+    pub new_kelas: Option<String>,
+    pub outcome: String,
+    pub open_loans: i64,
+    pub unpaid_amount: i64,
+    pub cleared_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RolloverReport {
+    pub rollover_id: Option<i64>,
+    pub academic_year: String,
+    pub dry_run: bool,
+    pub promoted: Vec<MemberPromotion>,
+    pub graduated: Vec<MemberPromotion>,
+    pub blocked: Vec<MemberPromotion>,
+    pub skipped: Vec<MemberPromotion>,
+}
+
+// Whether a member may receive the library clearance letter ("surat bebas
+// pustaka"): no borrowed books and nothing left to pay
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LibraryClearance {
+    pub member_id: i64,
+    pub member_name: String,
+    pub nis: Option<String>,
+    pub kelas: Option<String>,
+    pub open_loans: i64,
+    pub unpaid_amount: i64,
+    pub cleared: bool,
+}
+
+// Catalogue search. `query` is matched word by word against the start of words
+// in the title, author, publisher, category and keywords; it may be empty when
+// only the filters are used.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BookSearchQuery {
+    #[serde(default)]
+    pub query: String,
+    pub category: Option<String>,
+    pub year_from: Option<i64>,
+    pub year_to: Option<i64>,
+    #[serde(default)]
+    pub available_only: bool,
+    pub rack: Option<String>,
+    pub limit: Option<i64>,
+}
+
+// A search hit. The matched words in title_highlight and snippet are wrapped
+// in <mark></mark>; rank is the bm25 score, lower is more relevant.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BookSearchResult {
+    pub book: Book,
+    pub rank: f64,
+    pub title_highlight: String,
+    pub snippet: Option<String>,
+}
+
+// Paging, sorting and filtering for the list commands. Either `page` (from 1)
+// or the `cursor` returned with the previous page selects the rows; sort_by
+// must be one of the columns the command allows and sort_dir is "asc" or
+// "desc". Without sort_by the list keeps its usual order.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ListQuery<F> {
+    pub page: Option<i64>,
+    pub cursor: Option<String>,
+    pub page_size: Option<i64>,
+    pub sort_by: Option<String>,
+    pub sort_dir: Option<String>,
+    #[serde(default)]
+    pub filter: F,
+}
+
+// One page of a list; next_cursor is None on the last page
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub total: i64,
+    pub next_cursor: Option<String>,
+}
+
+// `search` matches the start of words in the catalogue, like search_books
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct BookFilter {
+    pub search: Option<String>,
+    pub category: Option<String>,
+    pub rack: Option<String>,
+    pub collection_type: Option<String>,
+    pub status: Option<String>,
+    #[serde(default)]
+    pub available_only: bool,
+}
+
+// `search` looks in the name, member code and NIS
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct MemberFilter {
+    pub search: Option<String>,
+    pub kelas: Option<String>,
+    pub status: Option<String>,
+    pub member_type: Option<String>,
+}
+
+// `search` looks in the book title and the member's name and code
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct LoanFilter {
+    pub search: Option<String>,
+    pub member_id: Option<i64>,
+    pub book_id: Option<i64>,
+    pub kelas: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct FineFilter {
+    pub search: Option<String>,
+    pub member_id: Option<i64>,
+    pub status: Option<String>,
+    pub fine_type: Option<String>,
+    pub kelas: Option<String>,
+}
+
+// What the reading-room kiosk shows for a title. next_due_date is the due date
+// of the loan that should come back first when no copy is on the shelf.
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct BookAvailability {
+    pub book_id: i64,
+    pub title: String,
+    pub author: String,
+    pub call_number: Option<String>,
+    pub rack_location: Option<String>,
+    pub total_copy: i64,
+    pub available_copy: i64,
+    pub holds_waiting: i64,
+    pub next_due_date: Option<DateTime<Utc>>,
+}
+
+// The member whose card was scanned at the self-service station. Only what
+// they need to recognise their own card is shown; max_books is what may still
+// be taken in this visit.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SelfServiceMember {
+    pub member_code: String,
+    pub name: String,
+    pub kelas: Option<String>,
+    pub open_loans: i64,
+    pub max_books: i64,
+    pub eligible: bool,
+    pub reasons: Vec<EligibilityBlock>,
+}
+
+// One book scanned at the self-checkout; error is set when it was not lent
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SelfCheckoutItem {
+    pub barcode: String,
+    pub book_title: Option<String>,
+    pub loan_id: Option<i64>,
+    pub due_date: Option<DateTime<Utc>>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SelfReturnResult {
+    pub self_return_id: i64,
+    pub book_title: String,
+    pub item_barcode: String,
+    pub due_date: DateTime<Utc>,
+    pub late_fine: i64,
+}
+
+// A book returned at the self-service station waiting for staff to check it
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct PendingSelfReturn {
+    pub id: i64,
+    pub loan_id: i64,
+    pub item_id: Option<i64>,
+    pub item_barcode: Option<String>,
+    pub book_id: i64,
+    pub book_title: String,
+    pub member_name: String,
+    pub member_code: String,
+    pub due_date: DateTime<Utc>,
+    pub returned_at: Option<DateTime<Utc>>,
+}
+
+// A guestbook entry; member_id and member_code are empty for guests
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct Visit {
+    pub id: i64,
+    pub member_id: Option<i64>,
+    pub member_code: Option<String>,
+    pub visitor_name: String,
+    pub purpose: Option<String>,
+    pub kelas: Option<String>,
+    pub jenis_kelamin: Option<String>,
+    pub checked_in_at: DateTime<Utc>,
+    pub checked_out_at: Option<DateTime<Utc>>,
+}
+
+// A visitor who is not a member, e.g. a parent or a guest from another school
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NewGuestVisit {
+    pub name: String,
+    pub purpose: String,
+    pub jenis_kelamin: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct VisitCount {
+    pub label: String,
+    pub count: i64,
+}
+
+// Visits in a day, week (Monday to Sunday) or calendar month
+#[derive(Debug, Serialize, Deserialize)]
+pub struct VisitStats {
+    pub period: String,
+    pub date_from: NaiveDate,
+    pub date_to: NaiveDate,
+    pub total: i64,
+    pub members: i64,
+    pub guests: i64,
+    pub by_day: Vec<VisitCount>,
+    pub by_kelas: Vec<VisitCount>,
+    pub by_jenis_kelamin: Vec<VisitCount>,
+}
@@ -0,0 +1,126 @@
+use crate::models::{ListQuery, Page};
+use sqlx::sqlite::SqliteRow;
+use sqlx::{FromRow, QueryBuilder, Sqlite, SqlitePool};
+
+pub const DEFAULT_PAGE_SIZE: i64 = 50;
+pub const MAX_PAGE_SIZE: i64 = 500;
+
+// The fixed SQL of a list command. `from` holds the joins and the conditions
+// every row must meet and ends in a WHERE clause, so filters can be appended
+// with " AND ...". sort_columns maps the names callers may sort by to SQL
+// columns; nothing else from the caller ever reaches the ORDER BY.
+pub struct ListSql {
+    pub select: &'static str,
+    pub from: &'static str,
+    pub sort_columns: &'static [(&'static str, &'static str)],
+    pub default_order: &'static str,
+    pub tiebreak: &'static str,
+}
+
+fn order_by<F>(sql: &ListSql, query: &ListQuery<F>) -> Result<String, String> {
+    let sort_by = match query.sort_by.as_deref().filter(|s| !s.is_empty()) {
+        Some(sort_by) => sort_by,
+        None => return Ok(sql.default_order.to_string()),
+    };
+    let column = sql
+        .sort_columns
+        .iter()
+        .find(|(name, _)| *name == sort_by)
+        .map(|(_, column)| *column)
+        .ok_or_else(|| format!("Tidak bisa mengurutkan berdasarkan '{}'", sort_by))?;
+    let direction = match query
+        .sort_dir
+        .as_deref()
+        .unwrap_or("asc")
+        .to_lowercase()
+        .as_str()
+    {
+        "asc" => "ASC",
+        "desc" => "DESC",
+        other => return Err(format!("Arah urutan '{}' tidak valid", other)),
+    };
+
+    // The tiebreak keeps rows with equal sort values in a stable order across pages
+    Ok(format!(
+        "{} {}, {} {}",
+        column, direction, sql.tiebreak, direction
+    ))
+}
+
+// (page size, offset). The cursor is the offset of the next row as text.
+fn bounds<F>(query: &ListQuery<F>) -> Result<(i64, i64), String> {
+    let page_size = query
+        .page_size
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(1, MAX_PAGE_SIZE);
+    let offset = match (&query.cursor, query.page) {
+        (Some(cursor), _) => cursor
+            .parse::<i64>()
+            .ok()
+            .filter(|offset| *offset >= 0)
+            .ok_or("Cursor halaman tidak valid")?,
+        (None, Some(page)) if page >= 1 => (page - 1) * page_size,
+        (None, Some(_)) => return Err("Nomor halaman dimulai dari 1".to_string()),
+        (None, None) => 0,
+    };
+    Ok((page_size, offset))
+}
+
+// " AND (a LIKE ? OR b LIKE ? ...)" matching `text` anywhere in the columns
+pub fn push_like(builder: &mut QueryBuilder<'_, Sqlite>, columns: &[&str], text: &str) {
+    let pattern = format!("%{}%", text.trim());
+    builder.push(" AND (");
+    for (i, column) in columns.iter().enumerate() {
+        if i > 0 {
+            builder.push(" OR ");
+        }
+        builder
+            .push(*column)
+            .push(" LIKE ")
+            .push_bind(pattern.clone());
+    }
+    builder.push(")");
+}
+
+// Count the matching rows and fetch the requested page. push_filters appends
+// the caller's filters and is run once for each of the two queries.
+pub async fn fetch_page<T, F>(
+    pool: &SqlitePool,
+    sql: &ListSql,
+    query: &ListQuery<F>,
+    push_filters: impl Fn(&mut QueryBuilder<'_, Sqlite>),
+) -> Result<Page<T>, String>
+where
+    T: for<'r> FromRow<'r, SqliteRow> + Send + Unpin,
+{
+    let order = order_by(sql, query)?;
+    let (page_size, offset) = bounds(query)?;
+
+    let mut count: QueryBuilder<Sqlite> =
+        QueryBuilder::new(format!("SELECT COUNT(*) {}", sql.from));
+    push_filters(&mut count);
+    let total: i64 = count
+        .build_query_scalar()
+        .fetch_one(pool)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let mut rows: QueryBuilder<Sqlite> = QueryBuilder::new(format!("{} {}", sql.select, sql.from));
+    push_filters(&mut rows);
+    rows.push(format!(" ORDER BY {} LIMIT ", order))
+        .push_bind(page_size)
+        .push(" OFFSET ")
+        .push_bind(offset);
+    let items: Vec<T> = rows
+        .build_query_as()
+        .fetch_all(pool)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let next = offset + items.len() as i64;
+    Ok(Page {
+        next_cursor: (!items.is_empty() && next < total).then(|| next.to_string()),
+        items,
+        total,
+    })
+}
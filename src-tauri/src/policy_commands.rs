@@ -0,0 +1,166 @@
+use crate::audit;
+use crate::auth::{Role, SessionStore};
+use crate::models::LoanPolicy;
+use sqlx::{SqliteConnection, SqlitePool};
+use tauri::State;
+
+pub const MEMBER_TYPES: [&str; 3] = ["student", "teacher", "staff"];
+pub const COLLECTION_TYPES: [&str; 3] = ["reference", "fiction", "textbook"];
+
+pub fn validate_member_type(member_type: &str) -> Result<(), String> {
+    if MEMBER_TYPES.contains(&member_type) {
+        Ok(())
+    } else {
+        Err(format!(
+            "Jenis anggota '{}' tidak valid (gunakan student, teacher, atau staff)",
+            member_type
+        ))
+    }
+}
+
+pub fn validate_collection_type(collection_type: &str) -> Result<(), String> {
+    if COLLECTION_TYPES.contains(&collection_type) {
+        Ok(())
+    } else {
+        Err(format!(
+            "Jenis koleksi '{}' tidak valid (gunakan reference, fiction, atau textbook)",
+            collection_type
+        ))
+    }
+}
+
+// The policy that applies when this member borrows this book
+pub async fn policy_for(
+    conn: &mut SqliteConnection,
+    member_id: i64,
+    book_id: i64,
+) -> Result<LoanPolicy, String> {
+    let (member_type, collection_type): (String, String) = sqlx::query_as(
+        "SELECT (SELECT member_type FROM members WHERE id = ?), (SELECT collection_type FROM books WHERE id = ?)",
+    )
+    .bind(member_id)
+    .bind(book_id)
+    .fetch_one(&mut *conn)
+    .await
+    .map_err(|_| "Anggota atau buku tidak ditemukan".to_string())?;
+
+    sqlx::query_as::<_, LoanPolicy>(
+        "SELECT * FROM loan_policies WHERE member_type = ? AND collection_type = ?",
+    )
+    .bind(&member_type)
+    .bind(&collection_type)
+    .fetch_optional(conn)
+    .await
+    .map_err(|e| e.to_string())?
+    .ok_or_else(|| {
+        format!(
+            "Kebijakan peminjaman untuk {} / {} belum diatur",
+            member_type, collection_type
+        )
+    })
+}
+
+#[tauri::command]
+pub async fn get_loan_policies(
+    pool: State<'_, SqlitePool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+) -> Result<Vec<LoanPolicy>, String> {
+    sessions.authorize(&token, Role::Viewer)?;
+
+    sqlx::query_as::<_, LoanPolicy>(
+        "SELECT * FROM loan_policies ORDER BY member_type ASC, collection_type ASC",
+    )
+    .fetch_all(&*pool)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn get_loan_policy(
+    pool: State<'_, SqlitePool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+    member_id: i64,
+    book_id: i64,
+) -> Result<LoanPolicy, String> {
+    sessions.authorize(&token, Role::Viewer)?;
+
+    let mut conn = pool.acquire().await.map_err(|e| e.to_string())?;
+    policy_for(&mut conn, member_id, book_id).await
+}
+
+// Create or replace the policy for one member type / collection type pair
+#[tauri::command]
+pub async fn save_loan_policy(
+    pool: State<'_, SqlitePool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+    policy: LoanPolicy,
+) -> Result<(), String> {
+    let session = sessions.authorize(&token, Role::Admin)?;
+
+    validate_member_type(&policy.member_type)?;
+    validate_collection_type(&policy.collection_type)?;
+    if policy.loan_days < 1 || policy.max_loans < 1 {
+        return Err("Lama peminjaman dan batas pinjaman minimal 1".to_string());
+    }
+    if policy.max_renewals < 0 || policy.fine_per_day < 0 {
+        return Err("Batas perpanjangan dan denda tidak boleh negatif".to_string());
+    }
+
+    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+
+    let before = sqlx::query_as::<_, LoanPolicy>(
+        "SELECT * FROM loan_policies WHERE member_type = ? AND collection_type = ?",
+    )
+    .bind(&policy.member_type)
+    .bind(&policy.collection_type)
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    sqlx::query(
+        r#"
+        INSERT INTO loan_policies (member_type, collection_type, loan_days, max_loans, max_renewals, fine_per_day)
+        VALUES (?, ?, ?, ?, ?, ?)
+        ON CONFLICT (member_type, collection_type) DO UPDATE SET
+            loan_days = excluded.loan_days,
+            max_loans = excluded.max_loans,
+            max_renewals = excluded.max_renewals,
+            fine_per_day = excluded.fine_per_day
+        "#,
+    )
+    .bind(&policy.member_type)
+    .bind(&policy.collection_type)
+    .bind(policy.loan_days)
+    .bind(policy.max_loans)
+    .bind(policy.max_renewals)
+    .bind(policy.fine_per_day)
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    let after = sqlx::query_as::<_, LoanPolicy>(
+        "SELECT * FROM loan_policies WHERE member_type = ? AND collection_type = ?",
+    )
+    .bind(&policy.member_type)
+    .bind(&policy.collection_type)
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(|e| e.to_string())?;
+    audit::record(
+        &mut tx,
+        &session,
+        if before.is_some() { "update" } else { "create" },
+        "loan_policy",
+        after.id,
+        before.as_ref().and_then(audit::snapshot),
+        audit::snapshot(&after),
+    )
+    .await?;
+
+    tx.commit().await.map_err(|e| e.to_string())?;
+
+    Ok(())
+}
@@ -0,0 +1,319 @@
+use printpdf::image_crate::{
+    self, codecs::jpeg::JpegEncoder, imageops::FilterType, DynamicImage, GenericImageView,
+};
+use printpdf::lopdf::{self, Object, ObjectId};
+use printpdf::{
+    ColorBits, ColorSpace, Image, ImageFilter, ImageTransform, ImageXObject, Mm, PdfLayerReference,
+    Px,
+};
+use sqlx::SqliteConnection;
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::settings_commands;
+
+pub const PT_TO_MM: f32 = 25.4 / 72.0;
+
+// Library name, address and logo shown on everything the app prints
+pub struct Branding {
+    pub name: String,
+    pub address: String,
+    pub logo: Option<DynamicImage>,
+}
+
+impl Branding {
+    pub async fn load(conn: &mut SqliteConnection) -> Branding {
+        let name =
+            settings_commands::get_setting_string(&mut *conn, "library_name", "Perpustakaan").await;
+        let address =
+            settings_commands::get_setting_string(&mut *conn, "library_address", "").await;
+        let logo_path = settings_commands::get_setting_string(&mut *conn, "library_logo", "").await;
+
+        Branding {
+            name,
+            address,
+            logo: load_image(&logo_path),
+        }
+    }
+}
+
+// Open an image file with any transparency flattened onto white. A missing or
+// unreadable file gives None, so a bad logo path never stops a print job.
+pub fn load_image(path: &str) -> Option<DynamicImage> {
+    if path.trim().is_empty() {
+        return None;
+    }
+
+    let image = image_crate::open(path.trim()).ok()?;
+    if !image.color().has_alpha() {
+        return Some(DynamicImage::ImageRgb8(image.to_rgb8()));
+    }
+
+    let image = image.to_rgba8();
+    let flattened = image_crate::RgbImage::from_fn(image.width(), image.height(), |x, y| {
+        let [r, g, b, a] = image.get_pixel(x, y).0;
+        let blend = |c: u8| ((c as u32 * a as u32 + 255 * (255 - a as u32)) / 255) as u8;
+        image_crate::Rgb([blend(r), blend(g), blend(b)])
+    });
+
+    Some(DynamicImage::ImageRgb8(flattened))
+}
+
+// The built-in PDF fonts and most thermal printers only know plain ASCII
+pub fn ascii(text: &str) -> String {
+    text.chars()
+        .map(|c| {
+            if c.is_ascii() && !c.is_ascii_control() {
+                c
+            } else {
+                '?'
+            }
+        })
+        .collect()
+}
+
+// Courier glyphs are 0.6 em wide, which lets text be centered and cut to fit
+// without font metrics
+pub fn fit_text(text: &str, size: f32, width: f32) -> String {
+    let max_chars = (width / (0.6 * size * PT_TO_MM)) as usize;
+    let text = ascii(text);
+    if text.len() <= max_chars {
+        text
+    } else {
+        let mut cut: String = text.chars().take(max_chars.saturating_sub(2)).collect();
+        cut.push_str("..");
+        cut
+    }
+}
+
+// Split text into lines of at most `columns` characters, breaking at spaces
+pub fn wrap(text: &str, columns: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut current = String::new();
+
+    for word in ascii(text).split_whitespace() {
+        let mut word = word.to_string();
+        while word.len() > columns {
+            if !current.is_empty() {
+                lines.push(std::mem::take(&mut current));
+            }
+            let rest = word.split_off(columns);
+            lines.push(word);
+            word = rest;
+        }
+        if !current.is_empty() && current.len() + 1 + word.len() > columns {
+            lines.push(std::mem::take(&mut current));
+        }
+        if !current.is_empty() {
+            current.push(' ');
+        }
+        current.push_str(&word);
+    }
+    if !current.is_empty() || lines.is_empty() {
+        lines.push(current);
+    }
+
+    lines
+}
+
+// Draw an image `width` mm wide with its lower left corner at (x, y)
+pub fn place_image(layer: &PdfLayerReference, image: &DynamicImage, x: f32, y: f32, width: f32) {
+    let dpi = image.width() as f32 * 25.4 / width;
+    Image::from_dynamic_image(image).add_to_layer(
+        layer.clone(),
+        ImageTransform {
+            translate_x: Some(Mm(x)),
+            translate_y: Some(Mm(y)),
+            dpi: Some(dpi),
+            ..Default::default()
+        },
+    );
+}
+
+// An image stored JPEG compressed, encoded once however many times it is
+// drawn. Photos drawn on hundreds of pages would otherwise make the PDF huge.
+#[derive(Clone)]
+pub struct Jpeg {
+    xobject: ImageXObject,
+}
+
+impl Jpeg {
+    pub fn encode(image: &DynamicImage) -> Result<Jpeg, String> {
+        let rgb = image.to_rgb8();
+        let mut data = Vec::new();
+        JpegEncoder::new_with_quality(&mut data, 85)
+            .encode_image(&rgb)
+            .map_err(|e| e.to_string())?;
+
+        Ok(Jpeg {
+            xobject: ImageXObject {
+                width: Px(rgb.width() as usize),
+                height: Px(rgb.height() as usize),
+                color_space: ColorSpace::Rgb,
+                bits_per_component: ColorBits::Bit8,
+                interpolate: true,
+                image_data: data,
+                image_filter: Some(ImageFilter::DCT),
+                smask: None,
+                clipping_bbox: None,
+            },
+        })
+    }
+
+    // Height in mm when drawn `width` mm wide
+    pub fn height(&self, width: f32) -> f32 {
+        width * self.xobject.height.0 as f32 / self.xobject.width.0 as f32
+    }
+
+    // Like place_image: (x, y) is the bottom left corner in mm
+    pub fn place(&self, layer: &PdfLayerReference, x: f32, y: f32, width: f32) {
+        Image::from(self.xobject.clone()).add_to_layer(
+            layer.clone(),
+            ImageTransform {
+                translate_x: Some(Mm(x)),
+                translate_y: Some(Mm(y)),
+                dpi: Some(self.xobject.width.0 as f32 * 25.4 / width),
+                ..Default::default()
+            },
+        );
+    }
+}
+
+// printpdf stores an image once for every time it is drawn, so a logo on 900
+// cards is in the file 900 times. Point every copy of an identical image at
+// the first one and drop the others.
+pub fn share_identical_images(pdf: Vec<u8>) -> Result<Vec<u8>, String> {
+    let mut doc = lopdf::Document::load_mem(&pdf).map_err(|e| e.to_string())?;
+
+    let mut first: HashMap<&[u8], ObjectId> = HashMap::new();
+    let mut duplicates: HashMap<ObjectId, ObjectId> = HashMap::new();
+    for (id, object) in &doc.objects {
+        if let Object::Stream(stream) = object {
+            let is_image =
+                matches!(stream.dict.get(b"Subtype"), Ok(Object::Name(name)) if name == b"Image");
+            if is_image {
+                let original = *first.entry(stream.content.as_slice()).or_insert(*id);
+                if original != *id {
+                    duplicates.insert(*id, original);
+                }
+            }
+        }
+    }
+    if duplicates.is_empty() {
+        return Ok(pdf);
+    }
+
+    for object in doc.objects.values_mut() {
+        repoint(object, &duplicates);
+    }
+    doc.prune_objects();
+
+    let mut bytes = Vec::new();
+    doc.save_to(&mut bytes).map_err(|e| e.to_string())?;
+    Ok(bytes)
+}
+
+fn repoint(object: &mut Object, duplicates: &HashMap<ObjectId, ObjectId>) {
+    match object {
+        Object::Reference(id) => {
+            if let Some(original) = duplicates.get(id) {
+                *id = *original;
+            }
+        }
+        Object::Array(items) => items.iter_mut().for_each(|item| repoint(item, duplicates)),
+        Object::Dictionary(dict) => dict
+            .iter_mut()
+            .for_each(|(_, value)| repoint(value, duplicates)),
+        Object::Stream(stream) => stream
+            .dict
+            .iter_mut()
+            .for_each(|(_, value)| repoint(value, duplicates)),
+        _ => {}
+    }
+}
+
+// Height in mm of an image drawn `width` mm wide
+pub fn image_height(image: &DynamicImage, width: f32) -> f32 {
+    width * image.height() as f32 / image.width() as f32
+}
+
+// ESC/POS "GS v 0" raster command printing `image` centered on a line of
+// `line_dots` dots, scaled down to at most `max_width` x `max_height` dots
+pub fn escpos_raster(
+    image: &DynamicImage,
+    line_dots: u32,
+    max_width: u32,
+    max_height: u32,
+) -> Vec<u8> {
+    let (w, h) = image.dimensions();
+    let scale = (max_width as f32 / w as f32)
+        .min(max_height as f32 / h as f32)
+        .min(1.0);
+    let width = ((w as f32 * scale) as u32).max(1);
+    let height = ((h as f32 * scale) as u32).max(1);
+    let gray = image
+        .resize_exact(width, height, FilterType::Triangle)
+        .to_luma8();
+
+    let offset = line_dots.saturating_sub(width) / 2;
+    let bytes_per_row = line_dots.div_ceil(8);
+    let mut out = vec![
+        0x1d,
+        0x76,
+        0x30,
+        0x00,
+        (bytes_per_row & 0xff) as u8,
+        (bytes_per_row >> 8) as u8,
+        (height & 0xff) as u8,
+        (height >> 8) as u8,
+    ];
+
+    for y in 0..height {
+        let mut row = vec![0u8; bytes_per_row as usize];
+        for x in 0..width {
+            if gray.get_pixel(x, y).0[0] < 128 {
+                let dot = offset + x;
+                row[(dot / 8) as usize] |= 0x80 >> (dot % 8);
+            }
+        }
+        out.extend(row);
+    }
+
+    out
+}
+
+// Where to write: the given path, or a new `prefix_<timestamp>` file in the
+// barcode folder from settings
+pub async fn output_path(
+    conn: &mut SqliteConnection,
+    path: &Option<String>,
+    prefix: &str,
+    extension: &str,
+) -> Result<String, String> {
+    if let Some(path) = path.as_deref().filter(|p| !p.trim().is_empty()) {
+        return Ok(path.trim().to_string());
+    }
+
+    let folder = settings_commands::get_setting_string(&mut *conn, "barcode_path", "").await;
+    if folder.is_empty() {
+        return Err("Lokasi penyimpanan file cetak belum diatur di pengaturan".to_string());
+    }
+    let file = format!(
+        "{}_{}.{}",
+        prefix,
+        chrono::Local::now().format("%Y%m%d_%H%M%S"),
+        extension
+    );
+    Ok(Path::new(&folder).join(file).to_string_lossy().to_string())
+}
+
+// Printers and devices are written like files: a path such as /dev/usb/lp0 or
+// \\.\COM3 sends the bytes straight to the printer
+pub fn write_output(path: &str, bytes: &[u8]) -> Result<(), String> {
+    let path = path.trim();
+    if path.is_empty() {
+        return Err("Lokasi penyimpanan wajib diisi".to_string());
+    }
+
+    std::fs::write(path, bytes).map_err(|e| format!("Gagal menulis ke {}: {}", path, e))
+}
@@ -0,0 +1,556 @@
+use crate::auth::{Role, SessionStore};
+use crate::models::ReceiptOutput;
+use crate::printing::{self, Branding};
+use crate::settings_commands;
+use chrono::{DateTime, Local, Utc};
+use printpdf::{BuiltinFont, Mm, PdfDocument};
+use sqlx::{SqliteConnection, SqlitePool};
+use tauri::State;
+
+// Side margin of the PDF version, in mm
+const PDF_MARGIN: f32 = 3.0;
+
+enum Line {
+    Title(String),
+    Center(String),
+    Text(String),
+    Pair(String, String),
+    Rule,
+}
+
+// One printed line, already padded to the paper's column count
+struct Row {
+    text: String,
+    bold: bool,
+}
+
+struct Paper {
+    width_mm: f32,
+    columns: usize,
+    dots: u32,
+}
+
+impl Paper {
+    fn from_width(width: i64) -> Result<Paper, String> {
+        match width {
+            58 => Ok(Paper {
+                width_mm: 58.0,
+                columns: 32,
+                dots: 384,
+            }),
+            80 => Ok(Paper {
+                width_mm: 80.0,
+                columns: 48,
+                dots: 576,
+            }),
+            _ => Err("Lebar kertas struk harus 58 atau 80 mm".to_string()),
+        }
+    }
+}
+
+fn rupiah(amount: i64) -> String {
+    let digits = amount.abs().to_string();
+    let mut grouped = String::new();
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            grouped.push('.');
+        }
+        grouped.push(c);
+    }
+    format!("{}Rp {}", if amount < 0 { "-" } else { "" }, grouped)
+}
+
+fn local_time(time: DateTime<Utc>) -> String {
+    time.with_timezone(&Local)
+        .format("%d-%m-%Y %H:%M")
+        .to_string()
+}
+
+fn local_date(time: DateTime<Utc>) -> String {
+    time.with_timezone(&Local).format("%d-%m-%Y").to_string()
+}
+
+fn layout(lines: &[Line], columns: usize) -> Vec<Row> {
+    let centered = |text: &str, bold: bool| {
+        printing::wrap(text, columns)
+            .into_iter()
+            .map(|line| Row {
+                text: format!("{:>width$}", line, width = (columns + line.len()) / 2),
+                bold,
+            })
+            .collect::<Vec<_>>()
+    };
+
+    let mut rows = Vec::new();
+    for line in lines {
+        match line {
+            Line::Title(text) => rows.extend(centered(text, true)),
+            Line::Center(text) => rows.extend(centered(text, false)),
+            Line::Text(text) => rows.extend(
+                printing::wrap(text, columns)
+                    .into_iter()
+                    .map(|text| Row { text, bold: false }),
+            ),
+            Line::Pair(label, value) => {
+                let label = printing::ascii(label);
+                let value = printing::ascii(value);
+                if label.len() + 1 + value.len() <= columns {
+                    rows.push(Row {
+                        text: format!("{}{:>width$}", label, value, width = columns - label.len()),
+                        bold: false,
+                    });
+                } else {
+                    rows.extend(
+                        printing::wrap(&label, columns)
+                            .into_iter()
+                            .map(|text| Row { text, bold: false }),
+                    );
+                    rows.extend(printing::wrap(&value, columns).into_iter().map(|line| Row {
+                        text: format!("{:>width$}", line, width = columns),
+                        bold: false,
+                    }));
+                }
+            }
+            Line::Rule => rows.push(Row {
+                text: "-".repeat(columns),
+                bold: false,
+            }),
+        }
+    }
+
+    rows
+}
+
+fn header(branding: &Branding, title: &str) -> Vec<Line> {
+    let mut lines = vec![Line::Title(branding.name.clone())];
+    if !branding.address.is_empty() {
+        lines.push(Line::Center(branding.address.clone()));
+    }
+    lines.push(Line::Rule);
+    lines.push(Line::Title(title.to_string()));
+    lines.push(Line::Pair("Dicetak".to_string(), local_time(Utc::now())));
+    lines.push(Line::Rule);
+    lines
+}
+
+fn render_escpos(branding: &Branding, rows: &[Row], paper: &Paper) -> Vec<u8> {
+    // ESC @ resets the printer
+    let mut out = vec![0x1b, 0x40];
+
+    if let Some(logo) = &branding.logo {
+        out.extend(printing::escpos_raster(
+            logo,
+            paper.dots,
+            paper.dots / 2,
+            160,
+        ));
+        out.push(b'\n');
+    }
+
+    for row in rows {
+        out.extend([0x1b, 0x45, row.bold as u8]);
+        out.extend(row.text.trim_end().as_bytes());
+        out.push(b'\n');
+    }
+
+    // Bold off, feed past the tear bar and cut
+    out.extend([0x1b, 0x45, 0x00, 0x1b, 0x64, 0x04, 0x1d, 0x56, 0x01]);
+    out
+}
+
+fn render_pdf(
+    branding: &Branding,
+    title: &str,
+    rows: &[Row],
+    paper: &Paper,
+) -> Result<Vec<u8>, String> {
+    let text_width_pt = (paper.width_mm - 2.0 * PDF_MARGIN) * 72.0 / 25.4;
+    // Courier glyphs are 0.6 em wide, so this fits exactly `columns` characters
+    let font_size = text_width_pt / paper.columns as f32 / 0.6;
+    let line_height = font_size * 1.3 * 25.4 / 72.0;
+
+    let logo_width = paper.width_mm / 2.0;
+    let logo_height = branding
+        .logo
+        .as_ref()
+        .map(|logo| printing::image_height(logo, logo_width) + 2.0)
+        .unwrap_or(0.0);
+    let page_height = 2.0 * PDF_MARGIN + logo_height + rows.len() as f32 * line_height;
+
+    let (doc, page, layer) = PdfDocument::new(title, Mm(paper.width_mm), Mm(page_height), "Struk");
+    let layer = doc.get_page(page).get_layer(layer);
+    let regular = doc
+        .add_builtin_font(BuiltinFont::Courier)
+        .map_err(|e| e.to_string())?;
+    let bold = doc
+        .add_builtin_font(BuiltinFont::CourierBold)
+        .map_err(|e| e.to_string())?;
+
+    let mut y = page_height - PDF_MARGIN;
+    if let Some(logo) = &branding.logo {
+        y -= logo_height;
+        printing::place_image(
+            &layer,
+            logo,
+            (paper.width_mm - logo_width) / 2.0,
+            y + 2.0,
+            logo_width,
+        );
+    }
+
+    for row in rows {
+        y -= line_height;
+        layer.use_text(
+            row.text.clone(),
+            font_size,
+            Mm(PDF_MARGIN),
+            Mm(y + line_height * 0.25),
+            if row.bold { &bold } else { &regular },
+        );
+    }
+
+    doc.save_to_bytes().map_err(|e| e.to_string())
+}
+
+async fn print(
+    conn: &mut SqliteConnection,
+    title: &str,
+    body: Vec<Line>,
+    output: &ReceiptOutput,
+) -> Result<String, String> {
+    let width = match output.paper_width {
+        Some(width) => width,
+        None => settings_commands::get_setting_i64(&mut *conn, "receipt_paper_width", 58).await,
+    };
+    let paper = Paper::from_width(width)?;
+    let branding = Branding::load(conn).await;
+
+    let mut lines = header(&branding, title);
+    lines.extend(body);
+    let rows = layout(&lines, paper.columns);
+
+    let bytes = match output.format.as_str() {
+        "pdf" => render_pdf(&branding, title, &rows, &paper)?,
+        "escpos" => render_escpos(&branding, &rows, &paper),
+        _ => return Err("Format struk tidak valid (gunakan pdf atau escpos)".to_string()),
+    };
+    printing::write_output(&output.path, &bytes)?;
+
+    Ok(output.path.clone())
+}
+
+#[derive(sqlx::FromRow)]
+struct ReceiptLoan {
+    id: i64,
+    member_id: i64,
+    member_name: String,
+    member_code: String,
+    member_kelas: Option<String>,
+    book_title: String,
+    item_barcode: Option<String>,
+    loan_date: DateTime<Utc>,
+    due_date: DateTime<Utc>,
+    return_date: Option<DateTime<Utc>>,
+    status: String,
+    book_condition: Option<String>,
+    damage_category: Option<String>,
+}
+
+// The loans on one receipt, which must all belong to the same member
+async fn receipt_loans(
+    conn: &mut SqliteConnection,
+    loan_ids: &[i64],
+) -> Result<Vec<ReceiptLoan>, String> {
+    if loan_ids.is_empty() {
+        return Err("Pilih minimal satu peminjaman".to_string());
+    }
+
+    let mut loans = Vec::new();
+    for loan_id in loan_ids {
+        let loan = sqlx::query_as::<_, ReceiptLoan>(
+            r#"
+            SELECT
+                l.id, l.member_id, m.name as member_name, m.member_code, m.kelas as member_kelas,
+                b.title as book_title, bi.barcode as item_barcode,
+                l.loan_date, l.due_date, l.return_date, l.status, l.book_condition, l.damage_category
+            FROM loans l
+            JOIN members m ON l.member_id = m.id
+            JOIN books b ON l.book_id = b.id
+            LEFT JOIN book_items bi ON l.item_id = bi.id
+            WHERE l.id = ?
+            "#,
+        )
+        .bind(loan_id)
+        .fetch_optional(&mut *conn)
+        .await
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("Peminjaman #{} tidak ditemukan", loan_id))?;
+        loans.push(loan);
+    }
+
+    if loans.iter().any(|l| l.member_id != loans[0].member_id) {
+        return Err("Semua peminjaman dalam satu struk harus milik anggota yang sama".to_string());
+    }
+
+    Ok(loans)
+}
+
+fn member_lines(name: &str, code: &str, kelas: &Option<String>) -> Vec<Line> {
+    let mut lines = vec![
+        Line::Pair("Anggota".to_string(), code.to_string()),
+        Line::Pair("Nama".to_string(), name.to_string()),
+    ];
+    if let Some(kelas) = kelas {
+        lines.push(Line::Pair("Kelas".to_string(), kelas.clone()));
+    }
+    lines.push(Line::Rule);
+    lines
+}
+
+#[tauri::command]
+pub async fn print_loan_receipt(
+    pool: State<'_, SqlitePool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+    loan_ids: Vec<i64>,
+    output: ReceiptOutput,
+) -> Result<String, String> {
+    let session = sessions.authorize(&token, Role::Staff)?;
+
+    let mut conn = pool.acquire().await.map_err(|e| e.to_string())?;
+    loan_receipt(&mut conn, &loan_ids, &session.username, &output).await
+}
+
+// Also printed by the self-service station, which has no staff name
+pub(crate) async fn loan_receipt(
+    conn: &mut SqliteConnection,
+    loan_ids: &[i64],
+    staff: &str,
+    output: &ReceiptOutput,
+) -> Result<String, String> {
+    let loans = receipt_loans(&mut *conn, loan_ids).await?;
+
+    let first = &loans[0];
+    let mut body = member_lines(&first.member_name, &first.member_code, &first.member_kelas);
+    for loan in &loans {
+        body.push(Line::Text(loan.book_title.clone()));
+        if let Some(barcode) = &loan.item_barcode {
+            body.push(Line::Pair("  Kode".to_string(), barcode.clone()));
+        }
+        body.push(Line::Pair(
+            "  Dipinjam".to_string(),
+            local_date(loan.loan_date),
+        ));
+        body.push(Line::Pair(
+            "  Jatuh tempo".to_string(),
+            local_date(loan.due_date),
+        ));
+    }
+    body.push(Line::Rule);
+    body.push(Line::Pair(
+        "Jumlah buku".to_string(),
+        loans.len().to_string(),
+    ));
+    body.push(Line::Pair("Petugas".to_string(), staff.to_string()));
+    body.push(Line::Rule);
+    body.push(Line::Center(
+        "Harap kembalikan buku sebelum jatuh tempo".to_string(),
+    ));
+
+    print(&mut *conn, "BUKTI PEMINJAMAN", body, output).await
+}
+
+#[tauri::command]
+pub async fn print_return_receipt(
+    pool: State<'_, SqlitePool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+    loan_ids: Vec<i64>,
+    output: ReceiptOutput,
+) -> Result<String, String> {
+    let session = sessions.authorize(&token, Role::Staff)?;
+
+    let mut conn = pool.acquire().await.map_err(|e| e.to_string())?;
+    let loans = receipt_loans(&mut conn, &loan_ids).await?;
+    if loans.iter().any(|l| l.status != "returned") {
+        return Err("Struk pengembalian hanya untuk buku yang sudah dikembalikan".to_string());
+    }
+
+    let first = &loans[0];
+    let mut body = member_lines(&first.member_name, &first.member_code, &first.member_kelas);
+    let mut total_fine = 0;
+    let mut total_remaining = 0;
+    for loan in &loans {
+        body.push(Line::Text(loan.book_title.clone()));
+        if let Some(barcode) = &loan.item_barcode {
+            body.push(Line::Pair("  Kode".to_string(), barcode.clone()));
+        }
+        body.push(Line::Pair(
+            "  Jatuh tempo".to_string(),
+            local_date(loan.due_date),
+        ));
+        if let Some(returned) = loan.return_date {
+            body.push(Line::Pair("  Kembali".to_string(), local_date(returned)));
+        }
+        let condition = match (&loan.book_condition, &loan.damage_category) {
+            (Some(condition), Some(category)) => format!("{} {}", condition, category),
+            (Some(condition), None) => condition.clone(),
+            _ => "-".to_string(),
+        };
+        body.push(Line::Pair("  Kondisi".to_string(), condition));
+
+        let fines: Vec<(String, i64, i64)> = sqlx::query_as(
+            "SELECT f.fine_type, f.amount, fb.remaining_amount FROM fines f JOIN fine_balances fb ON fb.fine_id = f.id WHERE f.loan_id = ? AND f.status != 'Cancelled' ORDER BY f.id ASC",
+        )
+        .bind(loan.id)
+        .fetch_all(&mut *conn)
+        .await
+        .map_err(|e| e.to_string())?;
+
+        for (fine_type, amount, remaining) in fines {
+            body.push(Line::Pair(format!("  Denda {}", fine_type), rupiah(amount)));
+            total_fine += amount;
+            total_remaining += remaining;
+        }
+    }
+    body.push(Line::Rule);
+    body.push(Line::Pair(
+        "Jumlah buku".to_string(),
+        loans.len().to_string(),
+    ));
+    body.push(Line::Pair("Total denda".to_string(), rupiah(total_fine)));
+    body.push(Line::Pair(
+        "Belum dibayar".to_string(),
+        rupiah(total_remaining),
+    ));
+    body.push(Line::Pair("Petugas".to_string(), session.username.clone()));
+    body.push(Line::Rule);
+    body.push(Line::Center("Terima kasih".to_string()));
+
+    print(&mut conn, "BUKTI PENGEMBALIAN", body, &output).await
+}
+
+#[derive(sqlx::FromRow)]
+struct ReceiptPayment {
+    id: i64,
+    entry_type: String,
+    amount: i64,
+    method: Option<String>,
+    reason: Option<String>,
+    reversal_of: Option<i64>,
+    created_at: Option<DateTime<Utc>>,
+    received_by_name: Option<String>,
+    fine_type: String,
+    fine_amount: i64,
+    remaining_after: i64,
+    member_remaining: i64,
+    book_title: String,
+    member_name: String,
+    member_code: String,
+    member_kelas: Option<String>,
+}
+
+// Receipt for one ledger entry: a payment, a waiver or a reversal
+#[tauri::command]
+pub async fn print_payment_receipt(
+    pool: State<'_, SqlitePool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+    payment_id: i64,
+    output: ReceiptOutput,
+) -> Result<String, String> {
+    sessions.authorize(&token, Role::Staff)?;
+
+    let mut conn = pool.acquire().await.map_err(|e| e.to_string())?;
+
+    // The fine's balance right after this entry, so a reprint shows the same figure
+    let payment = sqlx::query_as::<_, ReceiptPayment>(
+        r#"
+        SELECT
+            p.id, p.entry_type, p.amount, p.method, p.reason, p.reversal_of, p.created_at,
+            u.name as received_by_name,
+            f.fine_type, f.amount as fine_amount,
+            f.amount - (SELECT SUM(p2.amount) FROM fine_payments p2 WHERE p2.fine_id = f.id AND p2.id <= p.id) as remaining_after,
+            (SELECT COALESCE(SUM(remaining_amount), 0) FROM fine_balances WHERE member_id = m.id) as member_remaining,
+            b.title as book_title, m.name as member_name, m.member_code, m.kelas as member_kelas
+        FROM fine_payments p
+        JOIN fines f ON p.fine_id = f.id
+        JOIN loans l ON f.loan_id = l.id
+        JOIN books b ON l.book_id = b.id
+        JOIN members m ON l.member_id = m.id
+        LEFT JOIN users u ON p.received_by = u.id
+        WHERE p.id = ?
+        "#,
+    )
+    .bind(payment_id)
+    .fetch_optional(&mut *conn)
+    .await
+    .map_err(|e| e.to_string())?
+    .ok_or_else(|| "Pembayaran denda tidak ditemukan".to_string())?;
+
+    let title = match (payment.reversal_of.is_some(), payment.entry_type.as_str()) {
+        (true, _) => "PEMBATALAN PEMBAYARAN",
+        (false, "waiver") => "PEMBEBASAN DENDA",
+        _ => "BUKTI PEMBAYARAN DENDA",
+    };
+
+    let mut body = member_lines(
+        &payment.member_name,
+        &payment.member_code,
+        &payment.member_kelas,
+    );
+    body.push(Line::Pair(
+        "No. transaksi".to_string(),
+        format!("#{}", payment.id),
+    ));
+    if let Some(created_at) = payment.created_at {
+        body.push(Line::Pair("Waktu".to_string(), local_time(created_at)));
+    }
+    if let Some(original) = payment.reversal_of {
+        body.push(Line::Pair(
+            "Membatalkan".to_string(),
+            format!("#{}", original),
+        ));
+    }
+    body.push(Line::Text(format!(
+        "Denda {}: {}",
+        payment.fine_type, payment.book_title
+    )));
+    body.push(Line::Pair(
+        "Jumlah denda".to_string(),
+        rupiah(payment.fine_amount),
+    ));
+    body.push(Line::Rule);
+    let label = if payment.entry_type == "waiver" {
+        "Dibebaskan"
+    } else {
+        "Dibayar"
+    };
+    body.push(Line::Pair(label.to_string(), rupiah(payment.amount)));
+    if let Some(method) = &payment.method {
+        let method = if method == "cash" {
+            "Tunai"
+        } else {
+            "Transfer"
+        };
+        body.push(Line::Pair("Metode".to_string(), method.to_string()));
+    }
+    if let Some(reason) = &payment.reason {
+        body.push(Line::Text(format!("Alasan: {}", reason)));
+    }
+    body.push(Line::Pair(
+        "Sisa denda ini".to_string(),
+        rupiah(payment.remaining_after),
+    ));
+    body.push(Line::Pair(
+        "Sisa tagihan anggota".to_string(),
+        rupiah(payment.member_remaining),
+    ));
+    if let Some(staff) = &payment.received_by_name {
+        body.push(Line::Pair("Petugas".to_string(), staff.clone()));
+    }
+    body.push(Line::Rule);
+    body.push(Line::Center("Simpan struk ini sebagai bukti".to_string()));
+
+    print(&mut conn, title, body, &output).await
+}
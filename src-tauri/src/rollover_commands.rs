@@ -0,0 +1,662 @@
+use crate::audit;
+use crate::auth::{Role, SessionStore};
+use crate::models::{AcademicRollover, LibraryClearance, MemberPromotion, RolloverReport};
+use crate::printing::{self, Branding};
+use crate::settings_commands;
+use chrono::{Local, Utc};
+use printpdf::{BuiltinFont, Mm, PdfDocument};
+use sqlx::{SqliteConnection, SqlitePool};
+use std::collections::HashMap;
+use tauri::State;
+
+const PROMOTION_SELECT: &str = r#"
+    SELECT mp.id, mp.rollover_id, mp.member_id, m.name AS member_name, m.nis,
+           mp.old_kelas, mp.new_kelas, mp.outcome, mp.open_loans, mp.unpaid_amount,
+           mp.cleared_at
+    FROM member_promotions mp
+    JOIN members m ON mp.member_id = m.id
+"#;
+
+// Grade -> next grade, None for a graduating grade. Keys are compared in
+// upper case.
+type PromotionMap = HashMap<String, Option<String>>;
+
+// id, name, nis, kelas, status
+type Student = (i64, String, Option<String>, Option<String>, Option<String>);
+
+async fn load_promotion_map(conn: &mut SqliteConnection) -> Result<PromotionMap, String> {
+    let json = settings_commands::get_setting_string(&mut *conn, "promotion_map", "").await;
+    serde_json::from_str(&json)
+        .map_err(|_| "Peta kenaikan kelas di pengaturan tidak valid".to_string())
+}
+
+// Borrowed books and unpaid fines that keep a member from clearance
+async fn clearance_blockers(
+    conn: &mut SqliteConnection,
+    member_id: i64,
+) -> Result<(i64, i64), String> {
+    sqlx::query_as(
+        r#"
+        SELECT
+            (SELECT COUNT(*) FROM loans WHERE member_id = ? AND status = 'borrowed'),
+            (SELECT COALESCE(SUM(remaining_amount), 0) FROM fine_balances WHERE member_id = ?)
+        "#,
+    )
+    .bind(member_id)
+    .bind(member_id)
+    .fetch_one(&mut *conn)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+async fn clearance(
+    conn: &mut SqliteConnection,
+    member_id: i64,
+) -> Result<LibraryClearance, String> {
+    let (member_name, nis, kelas): (String, Option<String>, Option<String>) =
+        sqlx::query_as("SELECT name, nis, kelas FROM members WHERE id = ?")
+            .bind(member_id)
+            .fetch_optional(&mut *conn)
+            .await
+            .map_err(|e| e.to_string())?
+            .ok_or("Anggota tidak ditemukan")?;
+    let (open_loans, unpaid_amount) = clearance_blockers(conn, member_id).await?;
+
+    Ok(LibraryClearance {
+        member_id,
+        member_name,
+        nis,
+        kelas,
+        open_loans,
+        unpaid_amount,
+        cleared: open_loans == 0 && unpaid_amount <= 0,
+    })
+}
+
+fn blocker_message(clearance: &LibraryClearance) -> String {
+    let mut reasons = Vec::new();
+    if clearance.open_loans > 0 {
+        reasons.push(format!("masih meminjam {} buku", clearance.open_loans));
+    }
+    if clearance.unpaid_amount > 0 {
+        reasons.push(format!("denda belum lunas Rp {}", clearance.unpaid_amount));
+    }
+    format!(
+        "{} belum bebas pustaka: {}",
+        clearance.member_name,
+        reasons.join(", ")
+    )
+}
+
+// Move every active student up one grade using the promotion map (from
+// `promotion_map`, or the setting of that name). Graduates are deactivated
+// when they have no borrowed books and no unpaid fines; the rest are listed
+// as blocked and stay active until complete_graduation. A dry run only
+// returns the plan.
+#[tauri::command]
+pub async fn run_academic_rollover(
+    pool: State<'_, SqlitePool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+    academic_year: String,
+    promotion_map: Option<PromotionMap>,
+    dry_run: bool,
+) -> Result<RolloverReport, String> {
+    let session = sessions.authorize(&token, Role::Admin)?;
+
+    let academic_year = academic_year.trim().to_string();
+    if academic_year.is_empty() {
+        return Err("Tahun ajaran wajib diisi".to_string());
+    }
+
+    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+
+    let promotion_map = match promotion_map {
+        Some(map) => map,
+        None => load_promotion_map(&mut tx).await?,
+    };
+    if promotion_map.is_empty() {
+        return Err("Peta kenaikan kelas kosong".to_string());
+    }
+    let promotion_map: PromotionMap = promotion_map
+        .into_iter()
+        .map(|(grade, next)| {
+            (
+                grade.trim().to_uppercase(),
+                next.map(|n| n.trim().to_uppercase())
+                    .filter(|n| !n.is_empty()),
+            )
+        })
+        .collect();
+
+    let already_run: Option<i64> = sqlx::query_scalar(
+        "SELECT id FROM academic_rollovers WHERE academic_year = ? AND undone_at IS NULL",
+    )
+    .bind(&academic_year)
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(|e| e.to_string())?;
+    if already_run.is_some() {
+        return Err(format!(
+            "Kenaikan kelas tahun ajaran {} sudah dijalankan",
+            academic_year
+        ));
+    }
+
+    // Graduates still blocked from an earlier year wait for complete_graduation
+    // and are not rolled over again
+    let students: Vec<Student> = sqlx::query_as(
+        r#"
+            SELECT id, name, nis, kelas, status FROM members m
+            WHERE (status = 'Aktif' OR status IS NULL)
+              AND COALESCE(member_type, 'student') = 'student'
+              AND NOT EXISTS (
+                  SELECT 1 FROM member_promotions mp
+                  JOIN academic_rollovers ar ON mp.rollover_id = ar.id
+                  WHERE mp.member_id = m.id AND mp.outcome = 'blocked'
+                    AND mp.cleared_at IS NULL AND ar.undone_at IS NULL
+              )
+            ORDER BY kelas ASC, name ASC
+            "#,
+    )
+    .fetch_all(&mut *tx)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    let mut report = RolloverReport {
+        rollover_id: None,
+        academic_year: academic_year.clone(),
+        dry_run,
+        promoted: Vec::new(),
+        graduated: Vec::new(),
+        blocked: Vec::new(),
+        skipped: Vec::new(),
+    };
+    let mut old_statuses = HashMap::new();
+
+    for (member_id, member_name, nis, kelas, status) in students {
+        old_statuses.insert(member_id, status);
+        let current = kelas.clone().unwrap_or_default();
+        let (grade, section) = current
+            .trim()
+            .split_once(' ')
+            .unwrap_or((current.trim(), ""));
+        let mut promotion = MemberPromotion {
+            id: None,
+            rollover_id: None,
+            member_id,
+            member_name,
+            nis,
+            old_kelas: kelas.clone(),
+            new_kelas: kelas,
+            outcome: "skipped".to_string(),
+            open_loans: 0,
+            unpaid_amount: 0,
+            cleared_at: None,
+        };
+
+        match promotion_map.get(&grade.to_uppercase()) {
+            None => report.skipped.push(promotion),
+            Some(Some(next)) => {
+                promotion.new_kelas =
+                    Some(format!("{} {}", next, section.trim()).trim().to_string());
+                promotion.outcome = "promoted".to_string();
+                report.promoted.push(promotion);
+            }
+            Some(None) => {
+                let (open_loans, unpaid_amount) = clearance_blockers(&mut tx, member_id).await?;
+                promotion.open_loans = open_loans;
+                promotion.unpaid_amount = unpaid_amount;
+                if open_loans == 0 && unpaid_amount <= 0 {
+                    promotion.outcome = "graduated".to_string();
+                    report.graduated.push(promotion);
+                } else {
+                    promotion.outcome = "blocked".to_string();
+                    report.blocked.push(promotion);
+                }
+            }
+        }
+    }
+
+    if dry_run {
+        return Ok(report);
+    }
+
+    let map_json = serde_json::to_string(&promotion_map).map_err(|e| e.to_string())?;
+    let rollover_id = sqlx::query(
+        "INSERT INTO academic_rollovers (academic_year, promotion_map, performed_by) VALUES (?, ?, ?)",
+    )
+    .bind(&academic_year)
+    .bind(map_json)
+    .bind(session.user_id)
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| e.to_string())?
+    .last_insert_rowid();
+
+    for promotion in report
+        .promoted
+        .iter_mut()
+        .chain(report.graduated.iter_mut())
+        .chain(report.blocked.iter_mut())
+    {
+        match promotion.outcome.as_str() {
+            "promoted" => {
+                sqlx::query("UPDATE members SET kelas = ? WHERE id = ?")
+                    .bind(&promotion.new_kelas)
+                    .bind(promotion.member_id)
+                    .execute(&mut *tx)
+                    .await
+                    .map_err(|e| e.to_string())?;
+            }
+            "graduated" => {
+                sqlx::query("UPDATE members SET status = 'Nonaktif' WHERE id = ?")
+                    .bind(promotion.member_id)
+                    .execute(&mut *tx)
+                    .await
+                    .map_err(|e| e.to_string())?;
+            }
+            _ => {}
+        }
+
+        let id = sqlx::query(
+            r#"
+            INSERT INTO member_promotions
+            (rollover_id, member_id, old_kelas, new_kelas, old_status, outcome, open_loans, unpaid_amount)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(rollover_id)
+        .bind(promotion.member_id)
+        .bind(&promotion.old_kelas)
+        .bind(&promotion.new_kelas)
+        .bind(old_statuses.get(&promotion.member_id).cloned().flatten())
+        .bind(&promotion.outcome)
+        .bind(promotion.open_loans)
+        .bind(promotion.unpaid_amount)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?
+        .last_insert_rowid();
+        promotion.id = Some(id);
+        promotion.rollover_id = Some(rollover_id);
+    }
+
+    audit::record(
+        &mut tx,
+        &session,
+        "rollover",
+        "academic_rollover",
+        Some(rollover_id),
+        None,
+        Some(serde_json::json!({
+            "academic_year": academic_year,
+            "promoted": report.promoted.len(),
+            "graduated": report.graduated.len(),
+            "blocked": report.blocked.len(),
+            "skipped": report.skipped.len(),
+        })),
+    )
+    .await?;
+
+    tx.commit().await.map_err(|e| e.to_string())?;
+
+    report.rollover_id = Some(rollover_id);
+    Ok(report)
+}
+
+// Put every member of the latest rollover back in their old class and status
+#[tauri::command]
+pub async fn undo_academic_rollover(
+    pool: State<'_, SqlitePool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+    rollover_id: i64,
+) -> Result<i64, String> {
+    let session = sessions.authorize(&token, Role::Admin)?;
+
+    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+
+    let latest: Option<i64> =
+        sqlx::query_scalar("SELECT MAX(id) FROM academic_rollovers WHERE undone_at IS NULL")
+            .fetch_one(&mut *tx)
+            .await
+            .map_err(|e| e.to_string())?;
+    if latest != Some(rollover_id) {
+        return Err("Hanya kenaikan kelas terakhir yang dapat dibatalkan".to_string());
+    }
+
+    let restored = sqlx::query(
+        r#"
+        UPDATE members SET
+            kelas = (SELECT old_kelas FROM member_promotions WHERE rollover_id = ? AND member_id = members.id),
+            status = (SELECT old_status FROM member_promotions WHERE rollover_id = ? AND member_id = members.id)
+        WHERE id IN (SELECT member_id FROM member_promotions WHERE rollover_id = ?)
+        "#,
+    )
+    .bind(rollover_id)
+    .bind(rollover_id)
+    .bind(rollover_id)
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| e.to_string())?
+    .rows_affected() as i64;
+
+    sqlx::query("UPDATE academic_rollovers SET undone_at = ?, undone_by = ? WHERE id = ?")
+        .bind(Utc::now())
+        .bind(session.user_id)
+        .bind(rollover_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    audit::record(
+        &mut tx,
+        &session,
+        "undo",
+        "academic_rollover",
+        Some(rollover_id),
+        None,
+        Some(serde_json::json!({ "restored": restored })),
+    )
+    .await?;
+
+    tx.commit().await.map_err(|e| e.to_string())?;
+
+    Ok(restored)
+}
+
+#[tauri::command]
+pub async fn get_academic_rollovers(
+    pool: State<'_, SqlitePool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+) -> Result<Vec<AcademicRollover>, String> {
+    sessions.authorize(&token, Role::Viewer)?;
+
+    sqlx::query_as::<_, AcademicRollover>(
+        r#"
+        SELECT ar.id, ar.academic_year, ar.promotion_map, ar.performed_by,
+               u.name AS performed_by_name, ar.created_at, ar.undone_at,
+               (SELECT COUNT(*) FROM member_promotions WHERE rollover_id = ar.id AND outcome = 'promoted') AS promoted_count,
+               (SELECT COUNT(*) FROM member_promotions WHERE rollover_id = ar.id AND outcome = 'graduated') AS graduated_count,
+               (SELECT COUNT(*) FROM member_promotions WHERE rollover_id = ar.id AND outcome = 'blocked') AS blocked_count
+        FROM academic_rollovers ar
+        LEFT JOIN users u ON ar.performed_by = u.id
+        ORDER BY ar.id DESC
+        "#,
+    )
+    .fetch_all(&*pool)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn get_rollover_promotions(
+    pool: State<'_, SqlitePool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+    rollover_id: i64,
+) -> Result<Vec<MemberPromotion>, String> {
+    sessions.authorize(&token, Role::Viewer)?;
+
+    sqlx::query_as::<_, MemberPromotion>(&format!(
+        "{} WHERE mp.rollover_id = ? ORDER BY mp.outcome ASC, mp.new_kelas ASC, m.name ASC",
+        PROMOTION_SELECT
+    ))
+    .bind(rollover_id)
+    .fetch_all(&*pool)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+// Graduates still waiting for library clearance, with their current loans and
+// fines rather than those at rollover time
+#[tauri::command]
+pub async fn get_clearance_blockers(
+    pool: State<'_, SqlitePool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+) -> Result<Vec<MemberPromotion>, String> {
+    sessions.authorize(&token, Role::Viewer)?;
+
+    let mut conn = pool.acquire().await.map_err(|e| e.to_string())?;
+    let mut blocked = sqlx::query_as::<_, MemberPromotion>(&format!(
+        r#"{}
+        JOIN academic_rollovers ar ON mp.rollover_id = ar.id
+        WHERE mp.outcome = 'blocked' AND mp.cleared_at IS NULL AND ar.undone_at IS NULL
+        ORDER BY mp.old_kelas ASC, m.name ASC
+        "#,
+        PROMOTION_SELECT
+    ))
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    for promotion in blocked.iter_mut() {
+        let (open_loans, unpaid_amount) =
+            clearance_blockers(&mut conn, promotion.member_id).await?;
+        promotion.open_loans = open_loans;
+        promotion.unpaid_amount = unpaid_amount;
+    }
+    Ok(blocked)
+}
+
+#[tauri::command]
+pub async fn get_library_clearance(
+    pool: State<'_, SqlitePool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+    member_id: i64,
+) -> Result<LibraryClearance, String> {
+    sessions.authorize(&token, Role::Viewer)?;
+
+    let mut conn = pool.acquire().await.map_err(|e| e.to_string())?;
+    clearance(&mut conn, member_id).await
+}
+
+// Deactivate a graduate who was blocked at rollover once their loans and
+// fines are settled
+#[tauri::command]
+pub async fn complete_graduation(
+    pool: State<'_, SqlitePool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+    member_id: i64,
+) -> Result<(), String> {
+    let session = sessions.authorize(&token, Role::Staff)?;
+
+    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+
+    let promotion_ids: Vec<i64> = sqlx::query_scalar(
+        r#"
+        SELECT mp.id FROM member_promotions mp
+        JOIN academic_rollovers ar ON mp.rollover_id = ar.id
+        WHERE mp.member_id = ? AND mp.outcome = 'blocked' AND mp.cleared_at IS NULL
+          AND ar.undone_at IS NULL
+        "#,
+    )
+    .bind(member_id)
+    .fetch_all(&mut *tx)
+    .await
+    .map_err(|e| e.to_string())?;
+    if promotion_ids.is_empty() {
+        return Err("Anggota tidak sedang menunggu bebas pustaka".to_string());
+    }
+
+    let status = clearance(&mut tx, member_id).await?;
+    if !status.cleared {
+        return Err(blocker_message(&status));
+    }
+
+    sqlx::query("UPDATE members SET status = 'Nonaktif' WHERE id = ?")
+        .bind(member_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+    let cleared_at = Utc::now();
+    for promotion_id in promotion_ids {
+        sqlx::query(
+            "UPDATE member_promotions SET outcome = 'graduated', cleared_at = ? WHERE id = ?",
+        )
+        .bind(cleared_at)
+        .bind(promotion_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+    }
+
+    audit::record(
+        &mut tx,
+        &session,
+        "graduate",
+        "member",
+        Some(member_id),
+        None,
+        audit::snapshot(&status),
+    )
+    .await?;
+
+    tx.commit().await.map_err(|e| e.to_string())?;
+
+    Ok(())
+}
+
+// A wrapped paragraph of the clearance letter followed by a blank line
+fn paragraph(lines: &mut Vec<(String, bool)>, text: &str) {
+    lines.extend(printing::wrap(text, 55).into_iter().map(|l| (l, false)));
+    lines.push((String::new(), false));
+}
+
+// The library clearance letter ("surat keterangan bebas pustaka") as an A4
+// PDF; refused while the member still has loans or fines
+#[tauri::command]
+pub async fn print_clearance_letter(
+    pool: State<'_, SqlitePool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+    member_id: i64,
+    path: Option<String>,
+) -> Result<String, String> {
+    let session = sessions.authorize(&token, Role::Staff)?;
+
+    let mut conn = pool.acquire().await.map_err(|e| e.to_string())?;
+    let status = clearance(&mut conn, member_id).await?;
+    if !status.cleared {
+        return Err(blocker_message(&status));
+    }
+    let branding = Branding::load(&mut conn).await;
+    let staff_name: String = sqlx::query_scalar("SELECT name FROM users WHERE id = ?")
+        .bind(session.user_id)
+        .fetch_optional(&mut *conn)
+        .await
+        .map_err(|e| e.to_string())?
+        .unwrap_or_else(|| session.username.clone());
+
+    let (doc, page, layer) = PdfDocument::new("Surat Bebas Pustaka", Mm(210.0), Mm(297.0), "Surat");
+    let layer = doc.get_page(page).get_layer(layer);
+    let regular = doc
+        .add_builtin_font(BuiltinFont::Courier)
+        .map_err(|e| e.to_string())?;
+    let bold = doc
+        .add_builtin_font(BuiltinFont::CourierBold)
+        .map_err(|e| e.to_string())?;
+
+    // Text is placed from the top of the page down, 25 mm margins
+    let mut y = 272.0;
+    if let Some(logo) = &branding.logo {
+        let height = printing::image_height(logo, 20.0);
+        printing::place_image(&layer, logo, 25.0, y - height + 5.0, 20.0);
+    }
+    let centered = |text: &str, size: f32, y: f32, bold_text: bool| {
+        let text = printing::fit_text(text, size, 160.0);
+        let width = text.len() as f32 * 0.6 * size * printing::PT_TO_MM;
+        layer.use_text(
+            text,
+            size,
+            Mm(105.0 - width / 2.0),
+            Mm(y),
+            if bold_text { &bold } else { &regular },
+        );
+    };
+    centered(&branding.name, 14.0, y, true);
+    y -= 6.0;
+    if !branding.address.is_empty() {
+        centered(&branding.address, 9.0, y, false);
+    }
+    y -= 8.0;
+    layer.add_rect(printpdf::Rect::new(Mm(25.0), Mm(y), Mm(185.0), Mm(y + 0.5)));
+
+    y -= 14.0;
+    centered("SURAT KETERANGAN BEBAS PUSTAKA", 13.0, y, true);
+
+    let today = Local::now().format("%d-%m-%Y").to_string();
+    let mut lines: Vec<(String, bool)> = Vec::new();
+    paragraph(
+        &mut lines,
+        &format!(
+            "Yang bertanda tangan di bawah ini, petugas {}, menerangkan bahwa:",
+            branding.name
+        ),
+    );
+    for (label, value) in [
+        ("Nama", status.member_name.clone()),
+        ("NIS", status.nis.clone().unwrap_or_else(|| "-".to_string())),
+        (
+            "Kelas",
+            status.kelas.clone().unwrap_or_else(|| "-".to_string()),
+        ),
+    ] {
+        lines.push((
+            format!("    {:<8}: {}", label, printing::ascii(&value)),
+            true,
+        ));
+    }
+    lines.push((String::new(), false));
+    paragraph(
+        &mut lines,
+        "tidak memiliki pinjaman buku maupun denda yang belum diselesaikan di perpustakaan, sehingga dinyatakan BEBAS PUSTAKA.",
+    );
+    paragraph(
+        &mut lines,
+        "Demikian surat keterangan ini dibuat untuk dipergunakan sebagaimana mestinya.",
+    );
+
+    y -= 16.0;
+    for (line, bold_line) in &lines {
+        layer.use_text(
+            line.clone(),
+            11.0,
+            Mm(25.0),
+            Mm(y),
+            if *bold_line { &bold } else { &regular },
+        );
+        y -= 6.0;
+    }
+
+    y -= 6.0;
+    for (i, line) in [today, "Petugas Perpustakaan".to_string()]
+        .iter()
+        .enumerate()
+    {
+        layer.use_text(
+            line.clone(),
+            11.0,
+            Mm(120.0),
+            Mm(y - i as f32 * 6.0),
+            &regular,
+        );
+    }
+    layer.use_text(
+        printing::ascii(&staff_name),
+        11.0,
+        Mm(120.0),
+        Mm(y - 36.0),
+        &bold,
+    );
+
+    let bytes = doc.save_to_bytes().map_err(|e| e.to_string())?;
+    let path = printing::output_path(&mut conn, &path, "bebas_pustaka", "pdf").await?;
+    printing::write_output(&path, &bytes)?;
+    Ok(path)
+}
@@ -0,0 +1,117 @@
+use crate::auth::{Role, SessionStore};
+use crate::models::{Book, BookSearchQuery, BookSearchResult};
+use sqlx::{FromRow, QueryBuilder, Row, Sqlite, SqlitePool};
+use tauri::State;
+
+const DEFAULT_LIMIT: i64 = 50;
+const MAX_LIMIT: i64 = 200;
+
+// Turn what the user typed into an FTS5 expression: every word must match the
+// start of a word in the index, so "harry pot" finds "Harry Potter". Only
+// letters and digits are kept, which leaves no FTS5 syntax to escape.
+pub(crate) fn match_expression(query: &str) -> Option<String> {
+    let terms: Vec<String> = query
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|term| !term.is_empty())
+        .map(|term| format!("\"{}\"*", term))
+        .collect();
+    if terms.is_empty() {
+        None
+    } else {
+        Some(terms.join(" "))
+    }
+}
+
+#[tauri::command]
+pub async fn search_books(
+    pool: State<'_, SqlitePool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+    query: BookSearchQuery,
+) -> Result<Vec<BookSearchResult>, String> {
+    sessions.authorize(&token, Role::Viewer)?;
+
+    search(&pool, query).await
+}
+
+pub(crate) async fn search(
+    pool: &SqlitePool,
+    query: BookSearchQuery,
+) -> Result<Vec<BookSearchResult>, String> {
+    let expression = match_expression(&query.query);
+    let mut builder: QueryBuilder<Sqlite> = match &expression {
+        // Title and author weigh most in the ranking, then keywords and
+        // category, then publisher
+        Some(expression) => {
+            let mut builder = QueryBuilder::new(
+                r#"
+                SELECT b.*,
+                       bm25(books_fts, 10.0, 6.0, 2.0, 3.0, 4.0) AS rank,
+                       highlight(books_fts, 0, '<mark>', '</mark>') AS title_highlight,
+                       snippet(books_fts, -1, '<mark>', '</mark>', '…', 12) AS snippet
+                FROM books_fts
+                JOIN books b ON b.id = books_fts.rowid
+                WHERE books_fts MATCH "#,
+            );
+            builder.push_bind(expression.clone());
+            builder
+        }
+        None => QueryBuilder::new(
+            r#"
+            SELECT b.*, 0.0 AS rank, b.title AS title_highlight, NULL AS snippet
+            FROM books b
+            WHERE 1 = 1"#,
+        ),
+    };
+
+    builder.push(" AND b.deleted_at IS NULL");
+    if let Some(category) = query.category.filter(|c| !c.trim().is_empty()) {
+        builder
+            .push(" AND b.category = ")
+            .push_bind(category.trim().to_string())
+            .push(" COLLATE NOCASE");
+    }
+    if let Some(year_from) = query.year_from {
+        builder
+            .push(" AND b.published_year >= ")
+            .push_bind(year_from);
+    }
+    if let Some(year_to) = query.year_to {
+        builder.push(" AND b.published_year <= ").push_bind(year_to);
+    }
+    if query.available_only {
+        builder.push(" AND b.available_copy > 0");
+    }
+    if let Some(rack) = query.rack.filter(|r| !r.trim().is_empty()) {
+        builder
+            .push(" AND b.rack_location = ")
+            .push_bind(rack.trim().to_string())
+            .push(" COLLATE NOCASE");
+    }
+
+    if expression.is_some() {
+        builder.push(" ORDER BY rank ASC, b.title ASC");
+    } else {
+        builder.push(" ORDER BY b.title ASC");
+    }
+    builder
+        .push(" LIMIT ")
+        .push_bind(query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT));
+
+    let rows = builder
+        .build()
+        .fetch_all(pool)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let mut results = Vec::new();
+    for row in rows {
+        results.push(BookSearchResult {
+            book: Book::from_row(&row).map_err(|e| e.to_string())?,
+            rank: row.try_get("rank").unwrap_or(0.0),
+            title_highlight: row.try_get("title_highlight").unwrap_or_default(),
+            snippet: row.try_get("snippet").unwrap_or(None),
+        });
+    }
+    Ok(results)
+}
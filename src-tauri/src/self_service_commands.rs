@@ -0,0 +1,374 @@
+use crate::audit;
+use crate::auth::{Role, Session, SessionStore};
+use crate::commands;
+use crate::eligibility_commands;
+use crate::item_commands;
+use crate::models::{
+    Loan, Member, PendingSelfReturn, ReceiptOutput, SelfCheckoutItem, SelfReturnResult,
+    SelfServiceMember,
+};
+use crate::receipt_commands;
+use crate::settings_commands;
+use chrono::{DateTime, Utc};
+use sqlx::{SqliteConnection, SqlitePool};
+use tauri::State;
+
+// Shown as the staff name on receipts printed by the station
+const STATION_NAME: &str = "Mandiri";
+
+// Station actions are made by the member, not a user account: they are logged
+// without a user id, under "mandiri:<member code>"
+fn station_session(member_code: &str) -> Session {
+    Session::station(format!("mandiri:{}", member_code))
+}
+
+async fn station_member(conn: &mut SqliteConnection, member_code: &str) -> Result<Member, String> {
+    let member = sqlx::query_as::<_, Member>("SELECT * FROM members WHERE member_code = ?")
+        .bind(member_code.trim())
+        .fetch_optional(&mut *conn)
+        .await
+        .map_err(|e| e.to_string())?
+        .ok_or("Kartu anggota tidak dikenali")?;
+
+    if member.status.as_deref() == Some("Nonaktif") {
+        return Err("Anggota berstatus Nonaktif tidak dapat meminjam buku".to_string());
+    }
+    Ok(member)
+}
+
+// How many books a member may scan in one visit to the station. The loan
+// limit of their policy still applies on top of this.
+async fn station_limit(conn: &mut SqliteConnection) -> i64 {
+    settings_commands::get_setting_i64(&mut *conn, "self_checkout_max_books", 3)
+        .await
+        .max(1)
+}
+
+// A scanned code is a copy barcode, or the ISBN or barcode of a title when the
+// copy label can't be read
+async fn scanned_book(
+    conn: &mut SqliteConnection,
+    code: &str,
+) -> Result<(i64, Option<i64>, String), String> {
+    if let Some(item) = item_commands::find_item_by_barcode(&mut *conn, code).await? {
+        return Ok((item.book_id, Some(item.id), item.book_title));
+    }
+
+    sqlx::query_as::<_, (i64, String)>(
+        "SELECT id, title FROM books WHERE (isbn = ? OR barcode = ?) AND deleted_at IS NULL",
+    )
+    .bind(code)
+    .bind(code)
+    .fetch_optional(&mut *conn)
+    .await
+    .map_err(|e| e.to_string())?
+    .map(|(book_id, title)| (book_id, None, title))
+    .ok_or_else(|| format!("Buku dengan kode {} tidak ditemukan", code))
+}
+
+#[tauri::command]
+pub async fn self_checkout_identify(
+    pool: State<'_, SqlitePool>,
+    member_code: String,
+) -> Result<SelfServiceMember, String> {
+    let mut conn = pool.acquire().await.map_err(|e| e.to_string())?;
+    let member = station_member(&mut conn, &member_code).await?;
+    let member_id = member.id.unwrap_or_default();
+
+    let open_loans: i64 = sqlx::query_scalar(
+        "SELECT COUNT(*) FROM loans WHERE member_id = ? AND status = 'borrowed'",
+    )
+    .bind(member_id)
+    .fetch_one(&mut *conn)
+    .await
+    .map_err(|e| e.to_string())?;
+    let reasons = eligibility_commands::blocking_reasons(&mut conn, member_id, None).await?;
+    let override_available = eligibility_commands::pending_override(&mut conn, member_id)
+        .await?
+        .is_some();
+    let max_books = station_limit(&mut conn).await;
+
+    Ok(SelfServiceMember {
+        member_code: member.member_code,
+        name: member.name,
+        kelas: member.kelas,
+        open_loans,
+        max_books,
+        eligible: reasons.is_empty() || override_available,
+        reasons,
+    })
+}
+
+// One book in its own transaction. Nothing can fail after the commit, so an
+// Err always means the book was not lent.
+async fn lend_scanned(
+    pool: &SqlitePool,
+    session: &Session,
+    member_id: i64,
+    code: &str,
+) -> Result<(i64, String, DateTime<Utc>), String> {
+    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+    let (book_id, item_id, title) = scanned_book(&mut tx, code).await?;
+    let loan_id = commands::borrow(&mut tx, session, book_id, member_id, None, item_id).await?;
+    let due_date: DateTime<Utc> = sqlx::query_scalar("SELECT due_date FROM loans WHERE id = ?")
+        .bind(loan_id)
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+    tx.commit().await.map_err(|e| e.to_string())?;
+
+    Ok((loan_id, title, due_date))
+}
+
+// Lend each scanned book with the same checks as the desk. Every book is lent
+// on its own, so one refused book doesn't stop the others.
+#[tauri::command]
+pub async fn self_checkout(
+    pool: State<'_, SqlitePool>,
+    member_code: String,
+    barcodes: Vec<String>,
+) -> Result<Vec<SelfCheckoutItem>, String> {
+    let mut conn = pool.acquire().await.map_err(|e| e.to_string())?;
+    let member = station_member(&mut conn, &member_code).await?;
+    let member_id = member.id.unwrap_or_default();
+    let session = station_session(&member.member_code);
+
+    let mut codes: Vec<String> = Vec::new();
+    for code in barcodes.iter().map(|b| b.trim()).filter(|b| !b.is_empty()) {
+        if !codes.iter().any(|c| c == code) {
+            codes.push(code.to_string());
+        }
+    }
+    if codes.is_empty() {
+        return Err("Pindai minimal satu buku".to_string());
+    }
+    let max_books = station_limit(&mut conn).await;
+    if codes.len() as i64 > max_books {
+        return Err(format!(
+            "Maksimal {} buku sekali pinjam di stasiun mandiri",
+            max_books
+        ));
+    }
+    drop(conn);
+
+    let mut items = Vec::new();
+    for code in codes {
+        let item = match lend_scanned(&pool, &session, member_id, &code).await {
+            Ok((loan_id, title, due_date)) => SelfCheckoutItem {
+                barcode: code,
+                book_title: Some(title),
+                loan_id: Some(loan_id),
+                due_date: Some(due_date),
+                error: None,
+            },
+            Err(e) => SelfCheckoutItem {
+                barcode: code,
+                book_title: None,
+                loan_id: None,
+                due_date: None,
+                error: Some(e),
+            },
+        };
+        items.push(item);
+    }
+
+    Ok(items)
+}
+
+// Loan receipt on the station's own printer
+#[tauri::command]
+pub async fn self_checkout_receipt(
+    pool: State<'_, SqlitePool>,
+    member_code: String,
+    loan_ids: Vec<i64>,
+) -> Result<String, String> {
+    let mut conn = pool.acquire().await.map_err(|e| e.to_string())?;
+    let member = station_member(&mut conn, &member_code).await?;
+
+    let printer =
+        settings_commands::get_setting_string(&mut *conn, "self_checkout_printer", "").await;
+    if printer.trim().is_empty() {
+        return Err("Printer struk stasiun mandiri belum diatur".to_string());
+    }
+
+    // Only the member's own open loans can be printed from the station
+    for loan_id in &loan_ids {
+        let own: Option<i64> = sqlx::query_scalar(
+            "SELECT id FROM loans WHERE id = ? AND member_id = ? AND status = 'borrowed'",
+        )
+        .bind(loan_id)
+        .bind(member.id)
+        .fetch_optional(&mut *conn)
+        .await
+        .map_err(|e| e.to_string())?;
+        if own.is_none() {
+            return Err(format!(
+                "Peminjaman #{} tidak dapat dicetak di sini",
+                loan_id
+            ));
+        }
+    }
+
+    let output = ReceiptOutput {
+        format: "escpos".to_string(),
+        paper_width: None,
+        path: printer,
+    };
+    receipt_commands::loan_receipt(&mut conn, &loan_ids, STATION_NAME, &output).await
+}
+
+// Close the loan of a copy dropped at the station. The late fine is charged
+// now, but the copy stays off the shelf until staff inspect it.
+#[tauri::command]
+pub async fn self_return(
+    pool: State<'_, SqlitePool>,
+    barcode: String,
+) -> Result<SelfReturnResult, String> {
+    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+
+    let item = item_commands::find_item_by_barcode(&mut tx, barcode.trim())
+        .await?
+        .ok_or("Pindai kode eksemplar yang tertempel pada buku")?;
+    let (loan_id, member_code): (i64, String) = sqlx::query_as(
+        r#"
+        SELECT l.id, m.member_code FROM loans l
+        JOIN members m ON l.member_id = m.id
+        WHERE l.item_id = ? AND l.status = 'borrowed'
+        "#,
+    )
+    .bind(item.id)
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(|e| e.to_string())?
+    .ok_or("Buku ini tidak sedang dipinjam")?;
+
+    let loan: Loan =
+        commands::close_loan(&mut tx, &station_session(&member_code), loan_id, None).await?;
+
+    let self_return_id = sqlx::query("INSERT INTO self_returns (loan_id, item_id) VALUES (?, ?)")
+        .bind(loan_id)
+        .bind(item.id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?
+        .last_insert_rowid();
+
+    let late_fine: i64 = sqlx::query_scalar(
+        "SELECT COALESCE(SUM(amount), 0) FROM fines WHERE loan_id = ? AND fine_type = 'Late'",
+    )
+    .bind(loan_id)
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    tx.commit().await.map_err(|e| e.to_string())?;
+
+    Ok(SelfReturnResult {
+        self_return_id,
+        book_title: item.book_title,
+        item_barcode: item.barcode,
+        due_date: loan.due_date,
+        late_fine,
+    })
+}
+
+#[tauri::command]
+pub async fn get_pending_self_returns(
+    pool: State<'_, SqlitePool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+) -> Result<Vec<PendingSelfReturn>, String> {
+    sessions.authorize(&token, Role::Viewer)?;
+
+    sqlx::query_as::<_, PendingSelfReturn>(
+        r#"
+        SELECT sr.id, sr.loan_id, sr.item_id, bi.barcode AS item_barcode,
+               l.book_id, b.title AS book_title, m.name AS member_name, m.member_code,
+               l.due_date, sr.returned_at
+        FROM self_returns sr
+        JOIN loans l ON sr.loan_id = l.id
+        JOIN books b ON l.book_id = b.id
+        JOIN members m ON l.member_id = m.id
+        LEFT JOIN book_items bi ON sr.item_id = bi.id
+        WHERE sr.inspected_at IS NULL
+        ORDER BY sr.returned_at ASC, sr.id ASC
+        "#,
+    )
+    .fetch_all(&*pool)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+// Record the condition of a self-returned copy, charge any damage fine and put
+// it back on the shelf, as the desk does on a normal return
+#[tauri::command]
+pub async fn inspect_self_return(
+    pool: State<'_, SqlitePool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+    self_return_id: i64,
+    book_condition: String,
+    damage_category: Option<String>,
+) -> Result<(), String> {
+    let session = sessions.authorize(&token, Role::Staff)?;
+
+    if book_condition.trim().is_empty() {
+        return Err("Kondisi buku wajib diisi".to_string());
+    }
+
+    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+
+    let loan_id: i64 = sqlx::query_scalar(
+        "SELECT loan_id FROM self_returns WHERE id = ? AND inspected_at IS NULL",
+    )
+    .bind(self_return_id)
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(|e| e.to_string())?
+    .ok_or("Pengembalian mandiri tidak ditemukan atau sudah diperiksa")?;
+    let loan = sqlx::query_as::<_, Loan>("SELECT * FROM loans WHERE id = ?")
+        .bind(loan_id)
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    commands::charge_damage_fine(&mut tx, &loan, &book_condition, damage_category.as_deref())
+        .await?;
+    sqlx::query("UPDATE loans SET book_condition = ?, damage_category = ? WHERE id = ?")
+        .bind(&book_condition)
+        .bind(&damage_category)
+        .bind(loan_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+    commands::shelve_copy(&mut tx, &loan, &book_condition, damage_category.as_deref()).await?;
+    item_commands::refresh_book_stock(&mut tx, loan.book_id).await?;
+
+    sqlx::query("UPDATE self_returns SET inspected_at = ?, inspected_by = ? WHERE id = ?")
+        .bind(Utc::now())
+        .bind(session.user_id)
+        .bind(self_return_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let inspected = sqlx::query_as::<_, Loan>("SELECT * FROM loans WHERE id = ?")
+        .bind(loan_id)
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+    audit::record(
+        &mut tx,
+        &session,
+        "inspect",
+        "loan",
+        Some(loan_id),
+        audit::snapshot(&loan),
+        audit::snapshot(&inspected),
+    )
+    .await?;
+
+    tx.commit().await.map_err(|e| e.to_string())?;
+
+    Ok(())
+}
@@ -1,64 +1,119 @@
+use crate::audit;
+use crate::auth::{self, Role, SessionStore};
 use crate::models::User;
 use chrono;
-use sqlx::SqlitePool;
+use serde_json::json;
+use sqlx::{Executor, Sqlite, SqlitePool};
 use std::fs;
 use tauri::{AppHandle, Manager, State}; // Added this import as it's used in backup_database
 
 #[tauri::command]
 pub async fn update_profile(
     pool: State<'_, SqlitePool>,
-    user_id: i64,
+    sessions: State<'_, SessionStore>,
+    token: String,
     name: String,
     avatar: Option<String>,
 ) -> Result<User, String> {
+    let session = sessions.authorize(&token, Role::Viewer)?;
+
+    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+
+    let before = sqlx::query_as::<_, User>(
+        "SELECT id, username, name, role, avatar, status FROM users WHERE id = ?",
+    )
+    .bind(session.user_id)
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(|e| e.to_string())?;
+
     sqlx::query("UPDATE users SET name = ?, avatar = ? WHERE id = ?")
         .bind(&name)
         .bind(&avatar)
-        .bind(user_id)
-        .execute(&*pool)
+        .bind(session.user_id)
+        .execute(&mut *tx)
         .await
         .map_err(|e| e.to_string())?;
 
     let user = sqlx::query_as::<_, User>(
-        "SELECT id, username, name, role, avatar FROM users WHERE id = ?",
+        "SELECT id, username, name, role, avatar, status FROM users WHERE id = ?",
     )
-    .bind(user_id)
-    .fetch_one(&*pool)
+    .bind(session.user_id)
+    .fetch_one(&mut *tx)
     .await
     .map_err(|e| e.to_string())?;
 
+    audit::record(
+        &mut tx,
+        &session,
+        "update_profile",
+        "user",
+        Some(session.user_id),
+        audit::snapshot(&before),
+        audit::snapshot(&user),
+    )
+    .await?;
+
+    tx.commit().await.map_err(|e| e.to_string())?;
+
     Ok(user)
 }
 
 #[tauri::command]
 pub async fn change_password(
     pool: State<'_, SqlitePool>,
-    user_id: i64,
+    sessions: State<'_, SessionStore>,
+    token: String,
     old_password: String,
     new_password: String,
 ) -> Result<(), String> {
+    let session = sessions.authorize(&token, Role::Viewer)?;
+
     let current_password: String = sqlx::query_scalar("SELECT password FROM users WHERE id = ?")
-        .bind(user_id)
+        .bind(session.user_id)
         .fetch_one(&*pool)
         .await
         .map_err(|e| e.to_string())?;
 
-    if current_password != old_password {
+    if !auth::verify_password(&old_password, &current_password) {
         return Err("Kata sandi lama salah".to_string());
     }
 
+    let hashed = auth::hash_password(&new_password)?;
+    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+
     sqlx::query("UPDATE users SET password = ? WHERE id = ?")
-        .bind(new_password)
-        .bind(user_id)
-        .execute(&*pool)
+        .bind(hashed)
+        .bind(session.user_id)
+        .execute(&mut *tx)
         .await
         .map_err(|e| e.to_string())?;
 
+    // Password hashes are never copied into the log
+    audit::record(
+        &mut tx,
+        &session,
+        "change_password",
+        "user",
+        Some(session.user_id),
+        None,
+        None,
+    )
+    .await?;
+
+    tx.commit().await.map_err(|e| e.to_string())?;
+
     Ok(())
 }
 
 #[tauri::command]
-pub async fn backup_database(app_handle: AppHandle) -> Result<String, String> {
+pub async fn backup_database(
+    app_handle: AppHandle,
+    sessions: State<'_, SessionStore>,
+    token: String,
+) -> Result<String, String> {
+    sessions.authorize(&token, Role::Admin)?;
+
     let app_dir = app_handle
         .path()
         .app_data_dir()
@@ -84,32 +139,122 @@ pub async fn backup_database(app_handle: AppHandle) -> Result<String, String> {
 }
 
 #[tauri::command]
-pub async fn reset_database(pool: State<'_, SqlitePool>) -> Result<(), String> {
+pub async fn reset_database(
+    pool: State<'_, SqlitePool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+) -> Result<(), String> {
+    let session = sessions.authorize(&token, Role::Admin)?;
+
     let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
 
+    let (books, members, loans, fines): (i64, i64, i64, i64) = sqlx::query_as(
+        "SELECT (SELECT COUNT(*) FROM books), (SELECT COUNT(*) FROM members), (SELECT COUNT(*) FROM loans), (SELECT COUNT(*) FROM fines)",
+    )
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    sqlx::query("DELETE FROM fine_payments")
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+    sqlx::query("DELETE FROM fines")
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+    sqlx::query("DELETE FROM self_returns")
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+    sqlx::query("DELETE FROM loan_renewals")
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+    sqlx::query("DELETE FROM borrow_overrides")
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
     sqlx::query("DELETE FROM loans")
         .execute(&mut *tx)
         .await
         .map_err(|e| e.to_string())?;
+    sqlx::query("DELETE FROM holds")
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+    sqlx::query("DELETE FROM book_items")
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
     sqlx::query("DELETE FROM books")
         .execute(&mut *tx)
         .await
         .map_err(|e| e.to_string())?;
-    sqlx::query("DELETE FROM members")
+    sqlx::query("DELETE FROM member_promotions")
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+    sqlx::query("DELETE FROM academic_rollovers")
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+    sqlx::query("DELETE FROM visits")
         .execute(&mut *tx)
         .await
         .map_err(|e| e.to_string())?;
-    // We don't delete users to avoid locking ourselves out, or we reset admin password
-    sqlx::query("UPDATE users SET password = 'admin123' WHERE username = 'admin'")
+    sqlx::query("DELETE FROM members")
         .execute(&mut *tx)
         .await
         .map_err(|e| e.to_string())?;
+    // Users are kept as-is so the current accounts and their passwords stay valid
+    // The audit log is kept too, so the reset itself stays on record
+
+    audit::record(
+        &mut tx,
+        &session,
+        "reset",
+        "database",
+        None,
+        Some(json!({ "books": books, "members": members, "loans": loans, "fines": fines })),
+        None,
+    )
+    .await?;
 
     tx.commit().await.map_err(|e| e.to_string())?;
 
     Ok(())
 }
 
+// Read a numeric setting, falling back to `default` when it is missing or malformed
+pub async fn get_setting_i64<'c, E>(executor: E, key: &str, default: i64) -> i64
+where
+    E: Executor<'c, Database = Sqlite>,
+{
+    sqlx::query_scalar::<_, String>("SELECT value FROM settings WHERE key = ?")
+        .bind(key)
+        .fetch_optional(executor)
+        .await
+        .ok()
+        .flatten()
+        .and_then(|v| v.trim().parse::<i64>().ok())
+        .unwrap_or(default)
+}
+
+pub async fn get_setting_string<'c, E>(executor: E, key: &str, default: &str) -> String
+where
+    E: Executor<'c, Database = Sqlite>,
+{
+    sqlx::query_scalar::<_, String>("SELECT value FROM settings WHERE key = ?")
+        .bind(key)
+        .fetch_optional(executor)
+        .await
+        .ok()
+        .flatten()
+        .filter(|v| !v.trim().is_empty())
+        .unwrap_or_else(|| default.to_string())
+}
+
 #[tauri::command]
 pub async fn get_app_version() -> Result<String, String> {
     Ok(env!("CARGO_PKG_VERSION").to_string())
@@ -118,7 +263,11 @@ pub async fn get_app_version() -> Result<String, String> {
 #[tauri::command]
 pub async fn get_settings(
     pool: State<'_, SqlitePool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
 ) -> Result<std::collections::HashMap<String, String>, String> {
+    sessions.authorize(&token, Role::Viewer)?;
+
     let rows: Vec<(String, String)> = sqlx::query_as("SELECT key, value FROM settings")
         .fetch_all(&*pool)
         .await
@@ -131,18 +280,62 @@ pub async fn get_settings(
     Ok(map)
 }
 
+// The display preferences the login screen needs before anyone is signed in
+#[tauri::command]
+pub async fn get_display_settings(
+    pool: State<'_, SqlitePool>,
+) -> Result<std::collections::HashMap<String, String>, String> {
+    let rows: Vec<(String, String)> =
+        sqlx::query_as("SELECT key, value FROM settings WHERE key IN ('theme', 'language')")
+            .fetch_all(&*pool)
+            .await
+            .map_err(|e| e.to_string())?;
+
+    Ok(rows.into_iter().collect())
+}
+
 #[tauri::command]
 pub async fn update_setting(
     pool: State<'_, SqlitePool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
     key: String,
     value: String,
 ) -> Result<(), String> {
+    // Display preferences are per-desk; everything else (fine rates, paths) is admin-only
+    let required = match key.as_str() {
+        "theme" | "language" => Role::Viewer,
+        _ => Role::Admin,
+    };
+    let session = sessions.authorize(&token, required)?;
+
+    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+
+    let previous: Option<String> = sqlx::query_scalar("SELECT value FROM settings WHERE key = ?")
+        .bind(&key)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+
     sqlx::query("INSERT OR REPLACE INTO settings (key, value) VALUES (?, ?)")
-        .bind(key)
-        .bind(value)
-        .execute(&*pool)
+        .bind(&key)
+        .bind(&value)
+        .execute(&mut *tx)
         .await
         .map_err(|e| e.to_string())?;
 
+    audit::record(
+        &mut tx,
+        &session,
+        "update",
+        "setting",
+        None,
+        previous.map(|v| json!({ "key": key, "value": v })),
+        Some(json!({ "key": key, "value": value })),
+    )
+    .await?;
+
+    tx.commit().await.map_err(|e| e.to_string())?;
+
     Ok(())
 }
@@ -0,0 +1,421 @@
+use crate::audit;
+use crate::auth::{self, Role, SessionStore};
+use crate::models::{LoginEvent, NewUser, User};
+use serde_json::json;
+use sqlx::{SqliteConnection, SqlitePool};
+use tauri::State;
+
+const MIN_PASSWORD_LENGTH: usize = 6;
+
+fn validate_role(role: &str) -> Result<(), String> {
+    match Role::from_db(role) {
+        Some(_) => Ok(()),
+        None => Err(format!(
+            "Peran '{}' tidak valid (gunakan admin, staff, atau viewer)",
+            role
+        )),
+    }
+}
+
+fn validate_password(password: &str) -> Result<(), String> {
+    if password.chars().count() < MIN_PASSWORD_LENGTH {
+        return Err(format!(
+            "Kata sandi minimal {} karakter",
+            MIN_PASSWORD_LENGTH
+        ));
+    }
+    Ok(())
+}
+
+async fn find_user(conn: &mut SqliteConnection, user_id: i64) -> Result<User, String> {
+    sqlx::query_as::<_, User>(
+        "SELECT id, username, name, role, avatar, status FROM users WHERE id = ?",
+    )
+    .bind(user_id)
+    .fetch_optional(conn)
+    .await
+    .map_err(|e| e.to_string())?
+    .ok_or_else(|| "Pengguna tidak ditemukan".to_string())
+}
+
+// Refuse to take away the only remaining active admin account
+async fn ensure_not_last_admin(conn: &mut SqliteConnection, user: &User) -> Result<(), String> {
+    if user.role != "admin" || user.status == "Nonaktif" {
+        return Ok(());
+    }
+
+    let other_admins: i64 = sqlx::query_scalar(
+        "SELECT COUNT(*) FROM users WHERE role = 'admin' AND status = 'Aktif' AND id != ?",
+    )
+    .bind(user.id)
+    .fetch_one(conn)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    if other_admins == 0 {
+        return Err("Admin terakhir tidak dapat dihapus atau diturunkan".to_string());
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn get_users(
+    pool: State<'_, SqlitePool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+) -> Result<Vec<User>, String> {
+    sessions.authorize(&token, Role::Admin)?;
+
+    sqlx::query_as::<_, User>(
+        "SELECT id, username, name, role, avatar, status FROM users ORDER BY name ASC",
+    )
+    .fetch_all(&*pool)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn create_user(
+    pool: State<'_, SqlitePool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+    user: NewUser,
+) -> Result<i64, String> {
+    let session = sessions.authorize(&token, Role::Admin)?;
+
+    let username = user.username.trim().to_string();
+    if username.is_empty() {
+        return Err("Username tidak boleh kosong".to_string());
+    }
+    validate_role(&user.role)?;
+    validate_password(&user.password)?;
+
+    let hashed = auth::hash_password(&user.password)?;
+    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+
+    let existing_id: Option<i64> = sqlx::query_scalar("SELECT id FROM users WHERE username = ?")
+        .bind(&username)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    if existing_id.is_some() {
+        return Err(format!("Username '{}' sudah digunakan", username));
+    }
+
+    let res = sqlx::query(
+        "INSERT INTO users (username, password, name, role, status) VALUES (?, ?, ?, ?, 'Aktif')",
+    )
+    .bind(username)
+    .bind(hashed)
+    .bind(user.name)
+    .bind(user.role)
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    let id = res.last_insert_rowid();
+    let created = find_user(&mut tx, id).await?;
+    audit::record(
+        &mut tx,
+        &session,
+        "create",
+        "user",
+        Some(id),
+        None,
+        audit::snapshot(&created),
+    )
+    .await?;
+
+    tx.commit().await.map_err(|e| e.to_string())?;
+
+    Ok(id)
+}
+
+#[tauri::command]
+pub async fn update_user_role(
+    pool: State<'_, SqlitePool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+    user_id: i64,
+    role: String,
+) -> Result<(), String> {
+    let session = sessions.authorize(&token, Role::Admin)?;
+    validate_role(&role)?;
+
+    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+
+    let before = find_user(&mut tx, user_id).await?;
+    if role != "admin" {
+        ensure_not_last_admin(&mut tx, &before).await?;
+    }
+
+    sqlx::query("UPDATE users SET role = ? WHERE id = ?")
+        .bind(&role)
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let after = find_user(&mut tx, user_id).await?;
+    audit::record(
+        &mut tx,
+        &session,
+        "update_role",
+        "user",
+        Some(user_id),
+        audit::snapshot(&before),
+        audit::snapshot(&after),
+    )
+    .await?;
+
+    tx.commit().await.map_err(|e| e.to_string())?;
+
+    // Open sessions still carry the old role
+    sessions.revoke_user(user_id);
+
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn disable_user(
+    pool: State<'_, SqlitePool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+    user_id: i64,
+) -> Result<(), String> {
+    let session = sessions.authorize(&token, Role::Admin)?;
+
+    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+
+    let before = find_user(&mut tx, user_id).await?;
+    ensure_not_last_admin(&mut tx, &before).await?;
+
+    sqlx::query("UPDATE users SET status = 'Nonaktif' WHERE id = ?")
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let after = find_user(&mut tx, user_id).await?;
+    audit::record(
+        &mut tx,
+        &session,
+        "disable",
+        "user",
+        Some(user_id),
+        audit::snapshot(&before),
+        audit::snapshot(&after),
+    )
+    .await?;
+
+    tx.commit().await.map_err(|e| e.to_string())?;
+
+    sessions.revoke_user(user_id);
+
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn enable_user(
+    pool: State<'_, SqlitePool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+    user_id: i64,
+) -> Result<(), String> {
+    let session = sessions.authorize(&token, Role::Admin)?;
+
+    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+
+    let before = find_user(&mut tx, user_id).await?;
+
+    sqlx::query("UPDATE users SET status = 'Aktif' WHERE id = ?")
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let after = find_user(&mut tx, user_id).await?;
+    audit::record(
+        &mut tx,
+        &session,
+        "enable",
+        "user",
+        Some(user_id),
+        audit::snapshot(&before),
+        audit::snapshot(&after),
+    )
+    .await?;
+
+    tx.commit().await.map_err(|e| e.to_string())?;
+
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn delete_user(
+    pool: State<'_, SqlitePool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+    user_id: i64,
+) -> Result<(), String> {
+    let session = sessions.authorize(&token, Role::Admin)?;
+
+    if session.user_id == user_id {
+        return Err("Tidak dapat menghapus akun yang sedang digunakan".to_string());
+    }
+
+    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+
+    let before = find_user(&mut tx, user_id).await?;
+    ensure_not_last_admin(&mut tx, &before).await?;
+
+    // Renewals, payments, overrides, rollovers, inspections and visits keep the
+    // id of the account that recorded them
+    let has_history: bool = sqlx::query_scalar(
+        r#"
+        SELECT EXISTS (SELECT 1 FROM loan_renewals WHERE renewed_by = ?1)
+            OR EXISTS (SELECT 1 FROM borrow_overrides WHERE granted_by = ?1)
+            OR EXISTS (SELECT 1 FROM fine_payments WHERE received_by = ?1)
+            OR EXISTS (SELECT 1 FROM academic_rollovers WHERE performed_by = ?1 OR undone_by = ?1)
+            OR EXISTS (SELECT 1 FROM self_returns WHERE inspected_by = ?1)
+            OR EXISTS (SELECT 1 FROM visits WHERE recorded_by = ?1)
+        "#,
+    )
+    .bind(user_id)
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(|e| e.to_string())?;
+    if has_history {
+        return Err(
+            "Pengguna sudah memiliki riwayat transaksi dan tidak dapat dihapus. Nonaktifkan akun ini sebagai gantinya"
+                .to_string(),
+        );
+    }
+
+    sqlx::query("DELETE FROM users WHERE id = ?")
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    audit::record(
+        &mut tx,
+        &session,
+        "delete",
+        "user",
+        Some(user_id),
+        audit::snapshot(&before),
+        None,
+    )
+    .await?;
+
+    tx.commit().await.map_err(|e| e.to_string())?;
+
+    sessions.revoke_user(user_id);
+
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn reset_user_password(
+    pool: State<'_, SqlitePool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+    user_id: i64,
+    new_password: String,
+) -> Result<(), String> {
+    let session = sessions.authorize(&token, Role::Admin)?;
+    validate_password(&new_password)?;
+
+    let hashed = auth::hash_password(&new_password)?;
+    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+
+    find_user(&mut tx, user_id).await?;
+
+    sqlx::query("UPDATE users SET password = ? WHERE id = ?")
+        .bind(hashed)
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    audit::record(
+        &mut tx,
+        &session,
+        "reset_password",
+        "user",
+        Some(user_id),
+        None,
+        None,
+    )
+    .await?;
+
+    tx.commit().await.map_err(|e| e.to_string())?;
+
+    sessions.revoke_user(user_id);
+
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn unlock_user(
+    pool: State<'_, SqlitePool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+    username: String,
+) -> Result<(), String> {
+    let session = sessions.authorize(&token, Role::Admin)?;
+
+    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+
+    let user_id: Option<i64> = sqlx::query_scalar("SELECT id FROM users WHERE username = ?")
+        .bind(&username)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    auth::clear_failed_logins(&mut tx, &username).await?;
+
+    audit::record(
+        &mut tx,
+        &session,
+        "unlock",
+        "user",
+        user_id,
+        None,
+        Some(json!({ "username": username })),
+    )
+    .await?;
+
+    tx.commit().await.map_err(|e| e.to_string())?;
+
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn get_login_events(
+    pool: State<'_, SqlitePool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+    username: Option<String>,
+    limit: Option<i64>,
+) -> Result<Vec<LoginEvent>, String> {
+    sessions.authorize(&token, Role::Admin)?;
+
+    sqlx::query_as::<_, LoginEvent>(
+        r#"
+        SELECT id, username, user_id, success, reason, created_at
+        FROM login_events
+        WHERE ? IS NULL OR username = ?
+        ORDER BY created_at DESC, id DESC
+        LIMIT ?
+        "#,
+    )
+    .bind(&username)
+    .bind(&username)
+    .bind(limit.unwrap_or(200))
+    .fetch_all(&*pool)
+    .await
+    .map_err(|e| e.to_string())
+}
@@ -0,0 +1,263 @@
+use crate::auth::{Role, SessionStore};
+use crate::models::{Member, NewGuestVisit, Visit, VisitCount, VisitStats};
+use chrono::{Datelike, Duration, NaiveDate, Utc};
+use sqlx::{SqliteConnection, SqlitePool};
+use tauri::State;
+
+const VISIT_COLUMNS: &str = r#"
+    SELECT v.id, v.member_id, m.member_code, v.visitor_name, v.purpose, v.kelas,
+           v.jenis_kelamin, v.checked_in_at, v.checked_out_at
+    FROM visits v
+    LEFT JOIN members m ON v.member_id = m.id
+"#;
+
+async fn visit_by_id(conn: &mut SqliteConnection, visit_id: i64) -> Result<Visit, String> {
+    sqlx::query_as::<_, Visit>(&format!("{} WHERE v.id = ?", VISIT_COLUMNS))
+        .bind(visit_id)
+        .fetch_optional(conn)
+        .await
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| "Kunjungan tidak ditemukan".to_string())
+}
+
+#[tauri::command]
+pub async fn check_in_member(
+    pool: State<'_, SqlitePool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+    member_code: String,
+    purpose: Option<String>,
+) -> Result<Visit, String> {
+    let session = sessions.authorize(&token, Role::Staff)?;
+    let mut conn = pool.acquire().await.map_err(|e| e.to_string())?;
+
+    let member = sqlx::query_as::<_, Member>("SELECT * FROM members WHERE member_code = ?")
+        .bind(member_code.trim())
+        .fetch_optional(&mut *conn)
+        .await
+        .map_err(|e| e.to_string())?
+        .ok_or("Anggota tidak ditemukan")?;
+
+    // Scanning the card twice in one visit must not count the member twice
+    let open: Option<i64> = sqlx::query_scalar(
+        r#"
+        SELECT id FROM visits
+        WHERE member_id = ? AND checked_out_at IS NULL
+          AND date(checked_in_at, 'localtime') = date('now', 'localtime')
+        "#,
+    )
+    .bind(member.id)
+    .fetch_optional(&mut *conn)
+    .await
+    .map_err(|e| e.to_string())?;
+    if open.is_some() {
+        return Err(format!(
+            "{} sudah tercatat berkunjung hari ini",
+            member.name
+        ));
+    }
+
+    let visit_id = sqlx::query(
+        "INSERT INTO visits (member_id, visitor_name, purpose, kelas, jenis_kelamin, checked_in_at, recorded_by) VALUES (?, ?, ?, ?, ?, ?, ?)",
+    )
+    .bind(member.id)
+    .bind(&member.name)
+    .bind(purpose.filter(|p| !p.trim().is_empty()))
+    .bind(&member.kelas)
+    .bind(&member.jenis_kelamin)
+    .bind(Utc::now())
+    .bind(session.user_id)
+    .execute(&mut *conn)
+    .await
+    .map_err(|e| e.to_string())?
+    .last_insert_rowid();
+
+    visit_by_id(&mut conn, visit_id).await
+}
+
+#[tauri::command]
+pub async fn check_in_guest(
+    pool: State<'_, SqlitePool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+    guest: NewGuestVisit,
+) -> Result<Visit, String> {
+    let session = sessions.authorize(&token, Role::Staff)?;
+
+    if guest.name.trim().is_empty() {
+        return Err("Nama tamu wajib diisi".to_string());
+    }
+    if guest.purpose.trim().is_empty() {
+        return Err("Keperluan kunjungan wajib diisi".to_string());
+    }
+
+    let mut conn = pool.acquire().await.map_err(|e| e.to_string())?;
+    let visit_id = sqlx::query(
+        "INSERT INTO visits (visitor_name, purpose, jenis_kelamin, checked_in_at, recorded_by) VALUES (?, ?, ?, ?, ?)",
+    )
+    .bind(guest.name.trim())
+    .bind(guest.purpose.trim())
+    .bind(guest.jenis_kelamin.filter(|j| !j.trim().is_empty()))
+    .bind(Utc::now())
+    .bind(session.user_id)
+    .execute(&mut *conn)
+    .await
+    .map_err(|e| e.to_string())?
+    .last_insert_rowid();
+
+    visit_by_id(&mut conn, visit_id).await
+}
+
+#[tauri::command]
+pub async fn check_out_visit(
+    pool: State<'_, SqlitePool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+    visit_id: i64,
+) -> Result<Visit, String> {
+    sessions.authorize(&token, Role::Staff)?;
+    let mut conn = pool.acquire().await.map_err(|e| e.to_string())?;
+
+    let res =
+        sqlx::query("UPDATE visits SET checked_out_at = ? WHERE id = ? AND checked_out_at IS NULL")
+            .bind(Utc::now())
+            .bind(visit_id)
+            .execute(&mut *conn)
+            .await
+            .map_err(|e| e.to_string())?;
+
+    if res.rows_affected() == 0 {
+        visit_by_id(&mut conn, visit_id).await?;
+        return Err("Pengunjung sudah tercatat keluar".to_string());
+    }
+
+    visit_by_id(&mut conn, visit_id).await
+}
+
+// The guestbook of one day, today when no date is given
+#[tauri::command]
+pub async fn get_visits(
+    pool: State<'_, SqlitePool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+    date: Option<NaiveDate>,
+) -> Result<Vec<Visit>, String> {
+    sessions.authorize(&token, Role::Viewer)?;
+    let date = date.unwrap_or_else(|| chrono::Local::now().date_naive());
+
+    sqlx::query_as::<_, Visit>(&format!(
+        "{} WHERE date(v.checked_in_at, 'localtime') = ? ORDER BY v.checked_in_at DESC, v.id DESC",
+        VISIT_COLUMNS
+    ))
+    .bind(date.to_string())
+    .fetch_all(&*pool)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+// First and last day of the "daily", "weekly" or "monthly" period holding date
+fn period_range(period: &str, date: NaiveDate) -> Result<(NaiveDate, NaiveDate), String> {
+    match period {
+        "daily" => Ok((date, date)),
+        "weekly" => {
+            let monday = date - Duration::days(date.weekday().num_days_from_monday() as i64);
+            Ok((monday, monday + Duration::days(6)))
+        }
+        "monthly" => {
+            let first = date.with_day(1).unwrap_or(date);
+            let next_month = if date.month() == 12 {
+                NaiveDate::from_ymd_opt(date.year() + 1, 1, 1)
+            } else {
+                NaiveDate::from_ymd_opt(date.year(), date.month() + 1, 1)
+            }
+            .ok_or("Tanggal tidak valid")?;
+            Ok((first, next_month - Duration::days(1)))
+        }
+        other => Err(format!("Periode '{}' tidak dikenal", other)),
+    }
+}
+
+async fn visit_counts(
+    pool: &SqlitePool,
+    label: &str,
+    from: NaiveDate,
+    to: NaiveDate,
+) -> Result<Vec<VisitCount>, String> {
+    sqlx::query_as::<_, VisitCount>(&format!(
+        r#"
+        SELECT {} AS label, COUNT(*) AS count
+        FROM visits
+        WHERE date(checked_in_at, 'localtime') BETWEEN ? AND ?
+        GROUP BY label
+        ORDER BY label ASC
+        "#,
+        label
+    ))
+    .bind(from.to_string())
+    .bind(to.to_string())
+    .fetch_all(pool)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+// Visitor numbers for the report to the school ("laporan buku tamu"). Guests
+// are counted under the kelas "Tamu".
+#[tauri::command]
+pub async fn get_visit_stats(
+    pool: State<'_, SqlitePool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+    period: String,
+    date: Option<NaiveDate>,
+) -> Result<VisitStats, String> {
+    sessions.authorize(&token, Role::Viewer)?;
+    let date = date.unwrap_or_else(|| chrono::Local::now().date_naive());
+    let (date_from, date_to) = period_range(&period, date)?;
+
+    let (total, members): (i64, i64) = sqlx::query_as(
+        r#"
+        SELECT COUNT(*), COUNT(member_id)
+        FROM visits
+        WHERE date(checked_in_at, 'localtime') BETWEEN ? AND ?
+        "#,
+    )
+    .bind(date_from.to_string())
+    .bind(date_to.to_string())
+    .fetch_one(&*pool)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    let by_day = visit_counts(
+        &pool,
+        "date(checked_in_at, 'localtime')",
+        date_from,
+        date_to,
+    )
+    .await?;
+    let by_kelas = visit_counts(
+        &pool,
+        "CASE WHEN member_id IS NULL THEN 'Tamu' ELSE COALESCE(NULLIF(kelas, ''), 'Tanpa Kelas') END",
+        date_from,
+        date_to,
+    )
+    .await?;
+    let by_jenis_kelamin = visit_counts(
+        &pool,
+        "COALESCE(NULLIF(jenis_kelamin, ''), 'Tidak Diketahui')",
+        date_from,
+        date_to,
+    )
+    .await?;
+
+    Ok(VisitStats {
+        period,
+        date_from,
+        date_to,
+        total,
+        members,
+        guests: total - members,
+        by_day,
+        by_kelas,
+        by_jenis_kelamin,
+    })
+}
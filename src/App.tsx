@@ -75,15 +75,21 @@ interface FineWithDetails {
   due_date: string;
   return_date: string | null;
   amount: number;
+  paid_amount: number;
+  waived_amount: number;
+  remaining_amount: number;
   fine_type: string;
   status: string;
   paid_at: string | null;
 }
 
+// Session token issued by the backend on login, sent with every command
+let sessionToken: string | null = null;
+
 // Helper to handle invoke safely in browser for preview
 const safeInvoke = async (cmd: string, args: any = {}): Promise<any> => {
   if (window.hasOwnProperty("__TAURI_INTERNALS__") || window.hasOwnProperty("__TAURI__")) {
-    return await invoke(cmd, args);
+    return await invoke(cmd, { token: sessionToken, ...args });
   }
 
   // Fallback for non-Tauri environment (development only)
@@ -91,40 +97,62 @@ const safeInvoke = async (cmd: string, args: any = {}): Promise<any> => {
 
   if (cmd === "login") {
     if (args.username === "admin" && args.password === "admin123") {
-      return { id: 1, username: "admin", name: "Administrator", role: "admin" };
+      return { token: "mock", user: { id: 1, username: "admin", name: "Administrator", role: "admin" } };
     } else {
       throw "Username atau password salah";
     }
   }
 
   const mocks: any = {
-    "get_stats": { total_books: 0, total_members: 0, active_loans: 0, overdue_loans: 0, monthly_new_members: 0, total_loans_count: 0 },
-    "get_books": [],
-    "get_members": [],
-    "get_active_loans": [],
+    "get_stats": { total_books: 0, total_members: 0, active_loans: 0, overdue_loans: 0, monthly_new_members: 0, total_loans_count: 0, visitors_today: 0 },
+    "get_books": { items: [], total: 0, next_cursor: null },
+    "get_members": { items: [], total: 0, next_cursor: null },
+    "get_active_loans": { items: [], total: 0, next_cursor: null },
     "get_recent_activity": [],
     "get_weekly_circulation": [],
     "get_member_loans": [],
     "get_member_borrowing_history": [],
     "find_active_loan": [],
-    "get_overdue_loans": [],
-    "get_settings": { theme: 'light', language: 'id', fine_late_per_day: '1000' }
+    "get_overdue_loans": { items: [], total: 0, next_cursor: null },
+    "get_fines": { items: [], total: 0, next_cursor: null },
+    "get_settings": { theme: 'light', language: 'id', fine_late_per_day: '1000' },
+    "get_display_settings": { theme: 'light', language: 'id' }
   };
 
   return mocks[cmd] || (cmd.startsWith("add_") ? 1 : null);
 };
 
+// List commands return one page ({ items, total, next_cursor }); this follows
+// the cursor until every row is loaded
+const invokeAllPages = async (cmd: string, query: any = {}): Promise<any[]> => {
+  const items: any[] = [];
+  let cursor: string | null = null;
+  do {
+    const page: any = await safeInvoke(cmd, { query: { ...query, page_size: 500, cursor } });
+    if (!page || !Array.isArray(page.items)) break;
+    items.push(...page.items);
+    cursor = page.next_cursor;
+  } while (cursor);
+  return items;
+};
+
 function App() {
   const { showConfirm } = useAlert();
   const [user, setUser] = useState<User | null>(null);
   const [view, setView] = useState("dashboard");
   const [books, setBooks] = useState<Book[]>([]);
   const [members, setMembers] = useState<Member[]>([]);
+  const [station, setStation] = useState("desk");
 
   useEffect(() => {
     const initApp = async () => {
       try {
-        const s = await safeInvoke('get_settings');
+        const mode = await safeInvoke('get_station_mode');
+        if (mode && mode !== 'desk') {
+          setStation(mode);
+          return;
+        }
+        const s = await safeInvoke('get_display_settings');
         if (s && s.theme) {
           document.documentElement.setAttribute('data-theme', s.theme);
         }
@@ -144,10 +172,10 @@ function App() {
   const loadData = async () => {
     try {
       if (view === "books") {
-        const data = await safeInvoke("get_books");
+        const data = await invokeAllPages("get_books");
         setBooks(data);
       } else if (view === "members") {
-        const data = await safeInvoke("get_members");
+        const data = await invokeAllPages("get_members");
         setMembers(data);
       }
     } catch (err) {
@@ -155,6 +183,14 @@ function App() {
     }
   };
 
+  if (station === "kiosk") {
+    return <KioskView />;
+  }
+
+  if (station === "self_service") {
+    return <SelfServiceView />;
+  }
+
   if (!user) {
     return <LoginView onLogin={setUser} />;
   }
@@ -215,7 +251,11 @@ function App() {
             className="nav-item"
             onClick={async () => {
               const confirmed = await showConfirm("Apakah Anda yakin ingin keluar dari aplikasi?");
-              if (confirmed) setUser(null);
+              if (confirmed) {
+                await safeInvoke("logout");
+                sessionToken = null;
+                setUser(null);
+              }
             }}
             style={{ color: '#ef4444' }}
           >
@@ -322,7 +362,7 @@ function ReturnsView() {
 
   const loadOverdue = async () => {
     try {
-      const results = await safeInvoke("get_overdue_loans", {});
+      const results = await invokeAllPages("get_overdue_loans");
       setOverdueList(results);
     } catch (err) {
       console.error(err);
@@ -824,7 +864,8 @@ function FinesView({ setView }: { setView: (v: string) => void }) {
     fine_late_per_day: "1000",
     fine_damage_light: "15000",
     fine_damage_medium: "50000",
-    fine_damage_heavy: "100000"
+    fine_damage_heavy: "100000",
+    fine_accrual_last_error: ""
   });
   const [searchTerm, setSearchTerm] = useState("");
   const [statusFilter, setStatusFilter] = useState("");
@@ -843,7 +884,7 @@ function FinesView({ setView }: { setView: (v: string) => void }) {
   const loadData = async () => {
     try {
       const [f, s] = await Promise.all([
-        safeInvoke("get_fines"),
+        invokeAllPages("get_fines"),
         safeInvoke("get_settings")
       ]);
       if (Array.isArray(f)) setFines(f);
@@ -858,23 +899,23 @@ function FinesView({ setView }: { setView: (v: string) => void }) {
       const allMemberFines = await safeInvoke("get_fines_by_member", { memberId: fine.member_id });
       setMemberFines(allMemberFines || []);
       setSelectedFine(fine);
-      const totalUnpaid = allMemberFines.filter((f: any) => f.status === 'Unpaid').reduce((sum: number, f: any) => sum + f.amount, 0);
+      const totalUnpaid = allMemberFines.filter((f: any) => f.status === 'Unpaid').reduce((sum: number, f: any) => sum + f.remaining_amount, 0);
       setAmountPaid(totalUnpaid);
       setShowDetailModal(true);
     } catch (err) {
       console.error("Failed to load member fines:", err);
       setSelectedFine(fine);
       setMemberFines([fine]);
-      setAmountPaid(fine.amount);
+      setAmountPaid(fine.remaining_amount);
       setShowDetailModal(true);
     }
   };
 
   const handleSaveSettings = async () => {
     try {
-      await Promise.all(Object.entries(fineSettings).map(([key, value]) =>
-        safeInvoke("update_setting", { key, value: value.toString() })
-      ));
+      await Promise.all(Object.entries(fineSettings)
+        .filter(([key]) => key !== "fine_accrual_last_error")
+        .map(([key, value]) => safeInvoke("update_setting", { key, value: value.toString() })));
       await showAlert("Pengaturan denda berhasil disimpan!", "success");
     } catch (err) {
       await showAlert("Gagal menyimpan pengaturan: " + err, "error");
@@ -882,7 +923,7 @@ function FinesView({ setView }: { setView: (v: string) => void }) {
   };
 
   const handlePay = async (fine: FineWithDetails) => {
-    const confirmed = await showConfirm(`Konfirmasi pembayaran denda Rp ${fine.amount.toLocaleString('id-ID')} untuk ${fine.member_name}?`);
+    const confirmed = await showConfirm(`Konfirmasi pembayaran denda Rp ${fine.remaining_amount.toLocaleString('id-ID')} untuk ${fine.member_name}?`);
     if (confirmed) {
       try {
         await safeInvoke("pay_fine", { fineId: fine.id });
@@ -898,7 +939,7 @@ function FinesView({ setView }: { setView: (v: string) => void }) {
     if (!selectedFine) return;
     const unpaidFines = memberFines.filter(f => f.status === 'Unpaid');
 
-    const confirmed = await showConfirm(`Proses pelunasan ${unpaidFines.length} tagihan senilai Rp ${unpaidFines.reduce((sum, f) => sum + f.amount, 0).toLocaleString('id-ID')}?`);
+    const confirmed = await showConfirm(`Proses pelunasan ${unpaidFines.length} tagihan senilai Rp ${unpaidFines.reduce((sum, f) => sum + f.remaining_amount, 0).toLocaleString('id-ID')}?`);
 
     if (confirmed) {
       try {
@@ -954,6 +995,12 @@ function FinesView({ setView }: { setView: (v: string) => void }) {
         </div>
       </header>
 
+      {fineSettings.fine_accrual_last_error && (
+        <div style={{ margin: '20px 40px 0', padding: '12px 16px', borderRadius: '12px', backgroundColor: '#fef2f2', border: '1px solid #fecaca', color: '#b91c1c', fontSize: '0.875rem' }}>
+          Perhitungan denda otomatis gagal ({fineSettings.fine_accrual_last_error}). Denda keterlambatan mungkin belum diperbarui.
+        </div>
+      )}
+
       {/* Breadcrumbs & Filter Bar */}
       <section style={{ padding: '16px 40px', backgroundColor: '#f8fafc', borderBottom: '1px solid var(--border-main)', display: 'flex', justifyContent: 'space-between', alignItems: 'center' }}>
         <div style={{ display: 'flex', alignItems: 'center', gap: '8px', fontSize: '0.813rem', color: '#64748b' }}>
@@ -1033,11 +1080,11 @@ function FinesView({ setView }: { setView: (v: string) => void }) {
                           borderRadius: '9999px',
                           fontSize: '0.75rem',
                           fontWeight: 700,
-                          backgroundColor: fine.status === 'Paid' ? '#dcfce7' : '#fee2e2',
-                          color: fine.status === 'Paid' ? '#16a34a' : '#ef4444'
+                          backgroundColor: fine.status === 'Unpaid' ? '#fee2e2' : '#dcfce7',
+                          color: fine.status === 'Unpaid' ? '#ef4444' : '#16a34a'
                         }}>
-                          <span style={{ width: '6px', height: '6px', borderRadius: '50%', backgroundColor: fine.status === 'Paid' ? '#16a34a' : '#ef4444' }}></span>
-                          {fine.status === 'Paid' ? 'Lunas' : 'Belum Lunas'}
+                          <span style={{ width: '6px', height: '6px', borderRadius: '50%', backgroundColor: fine.status === 'Unpaid' ? '#ef4444' : '#16a34a' }}></span>
+                          {fine.status === 'Cancelled' ? 'Dibatalkan' : fine.status === 'Paid' ? 'Lunas' : 'Belum Lunas'}
                         </span>
                       </td>
                       <td style={{ paddingRight: '24px', textAlign: 'right' }}>
@@ -1306,7 +1353,7 @@ function FinesView({ setView }: { setView: (v: string) => void }) {
                   <div style={{ display: 'flex', alignItems: 'baseline', gap: '8px' }}>
                     <span style={{ fontSize: '1.25rem', fontWeight: 500, opacity: 0.9 }}>Rp</span>
                     <h2 style={{ fontSize: '3rem', fontWeight: 900, margin: 0, letterSpacing: '-0.02em' }}>
-                      {memberFines.filter(f => f.status === 'Unpaid').reduce((sum, f) => sum + f.amount, 0).toLocaleString('id-ID')}
+                      {memberFines.filter(f => f.status === 'Unpaid').reduce((sum, f) => sum + f.remaining_amount, 0).toLocaleString('id-ID')}
                     </h2>
                   </div>
                   <div style={{ marginTop: '20px', paddingTop: '20px', borderTop: '1px solid rgba(255,255,255,0.2)' }}>
@@ -1395,8 +1442,9 @@ function LoginView({ onLogin }: { onLogin: (u: User) => void }) {
     setError("");
     setLoading(true);
     try {
-      const u = await safeInvoke("login", { username, password });
-      onLogin(u);
+      const session = await safeInvoke("login", { username, password });
+      sessionToken = session.token;
+      onLogin(session.user);
     } catch (err: any) {
       setError(err.toString());
     } finally {
@@ -1511,8 +1559,332 @@ function LoginView({ onLogin }: { onLogin: (u: User) => void }) {
   );
 }
 
+// Public catalogue for the reading-room PC. Only the kiosk commands exist in
+// this mode, so nothing here can reach member data.
+function KioskView() {
+  const [query, setQuery] = useState("");
+  const [results, setResults] = useState<any[]>([]);
+  const [availability, setAvailability] = useState<Record<number, any>>({});
+  const [searched, setSearched] = useState(false);
+  const [showExit, setShowExit] = useState(false);
+  const [username, setUsername] = useState("");
+  const [password, setPassword] = useState("");
+  const [error, setError] = useState("");
+
+  const handleSearch = async () => {
+    if (!query.trim()) return;
+    try {
+      const hits = await safeInvoke("kiosk_search_books", { query: { query } });
+      setResults(hits || []);
+      setSearched(true);
+      const entries = await Promise.all((hits || []).map(async (hit: any) =>
+        [hit.book.id, await safeInvoke("kiosk_book_availability", { bookId: hit.book.id })]
+      ));
+      setAvailability(Object.fromEntries(entries));
+    } catch (err) {
+      console.error(err);
+    }
+  };
+
+  const handleExit = async (e: React.FormEvent) => {
+    e.preventDefault();
+    setError("");
+    try {
+      await safeInvoke("exit_kiosk", { username, password });
+    } catch (err: any) {
+      setError(err.toString());
+      setPassword("");
+    }
+  };
+
+  return (
+    <div style={{ minHeight: '100vh', padding: '48px', backgroundColor: '#f8fafc' }}>
+      <header style={{ display: 'flex', justifyContent: 'space-between', alignItems: 'center', marginBottom: '32px' }}>
+        <div style={{ display: 'flex', alignItems: 'center', gap: '12px' }}>
+          <span className="material-symbols-outlined" style={{ fontSize: '2.5rem', color: '#137fec' }}>menu_book</span>
+          <div>
+            <h1 style={{ fontSize: '1.75rem', fontWeight: 800, color: '#1e293b' }}>Katalog Perpustakaan</h1>
+            <p style={{ color: '#64748b' }}>Cari judul, pengarang, penerbit atau subjek buku</p>
+          </div>
+        </div>
+        <button onClick={() => setShowExit(true)} style={{ background: 'none', border: 'none', color: '#cbd5e1', cursor: 'pointer' }}>
+          <span className="material-symbols-outlined">lock</span>
+        </button>
+      </header>
+
+      <div style={{ display: 'flex', gap: '12px', marginBottom: '32px' }}>
+        <input
+          className="login-input"
+          style={{ flex: 1, fontSize: '1.25rem', padding: '16px 20px' }}
+          placeholder="Contoh: harry pot"
+          value={query}
+          onChange={(e) => setQuery(e.target.value)}
+          onKeyPress={(e) => e.key === 'Enter' && handleSearch()}
+          autoFocus
+        />
+        <button onClick={handleSearch} className="btn-primary" style={{ padding: '0 32px', borderRadius: '12px', fontWeight: 700 }}>
+          Cari
+        </button>
+      </div>
+
+      {searched && results.length === 0 && (
+        <p style={{ color: '#64748b', textAlign: 'center' }}>Buku tidak ditemukan</p>
+      )}
+
+      <div style={{ display: 'flex', flexDirection: 'column', gap: '12px' }}>
+        {results.map(hit => {
+          const info = availability[hit.book.id];
+          return (
+            <div key={hit.book.id} style={{ backgroundColor: 'white', borderRadius: '16px', padding: '20px 24px', border: '1px solid #e2e8f0', display: 'flex', justifyContent: 'space-between', gap: '24px' }}>
+              <div>
+                <h3 style={{ fontSize: '1.125rem', fontWeight: 700, color: '#1e293b' }}>{hit.book.title}</h3>
+                <p style={{ color: '#64748b' }}>{hit.book.author}{hit.book.published_year ? ` · ${hit.book.published_year}` : ''}</p>
+              </div>
+              <div style={{ textAlign: 'right', minWidth: '220px' }}>
+                <p style={{ fontWeight: 700, color: '#1e293b' }}>Rak {info?.rack_location || '-'}{info?.call_number ? ` · ${info.call_number}` : ''}</p>
+                {info && (info.available_copy > 0 ? (
+                  <p style={{ color: '#16a34a', fontWeight: 600 }}>Tersedia {info.available_copy} dari {info.total_copy}</p>
+                ) : (
+                  <p style={{ color: '#dc2626', fontWeight: 600 }}>
+                    Sedang dipinjam{info.next_due_date ? `, kembali ${new Date(info.next_due_date).toLocaleDateString('id-ID')}` : ''}
+                  </p>
+                ))}
+              </div>
+            </div>
+          );
+        })}
+      </div>
+
+      {showExit && (
+        <div style={{ position: 'fixed', inset: 0, backgroundColor: 'rgba(15, 23, 42, 0.5)', display: 'flex', alignItems: 'center', justifyContent: 'center' }}>
+          <form onSubmit={handleExit} style={{ backgroundColor: 'white', borderRadius: '16px', padding: '32px', width: '360px', display: 'flex', flexDirection: 'column', gap: '12px' }}>
+            <h2 style={{ fontSize: '1.25rem', fontWeight: 700 }}>Keluar Mode Kios</h2>
+            <input className="login-input" placeholder="Username admin" value={username} onChange={e => setUsername(e.target.value)} required />
+            <input className="login-input" type="password" placeholder="Kata sandi" value={password} onChange={e => setPassword(e.target.value)} required />
+            {error && <p style={{ color: '#ef4444', fontSize: '0.875rem' }}>{error}</p>}
+            <div style={{ display: 'flex', gap: '8px', justifyContent: 'flex-end' }}>
+              <button type="button" className="btn-white" onClick={() => { setShowExit(false); setError(""); setPassword(""); }}>Batal</button>
+              <button type="submit" className="btn-primary">Keluar</button>
+            </div>
+          </form>
+        </div>
+      )}
+    </div>
+  );
+}
+
+// Self-checkout and return station. Members identify with their card; returned
+// books wait for staff to inspect them before going back on the shelf.
+function SelfServiceView() {
+  const [mode, setMode] = useState<"checkout" | "return">("checkout");
+  const [memberCode, setMemberCode] = useState("");
+  const [member, setMember] = useState<any>(null);
+  const [scan, setScan] = useState("");
+  const [barcodes, setBarcodes] = useState<string[]>([]);
+  const [lent, setLent] = useState<any[] | null>(null);
+  const [returned, setReturned] = useState<any[]>([]);
+  const [message, setMessage] = useState("");
+  const [showExit, setShowExit] = useState(false);
+  const [username, setUsername] = useState("");
+  const [password, setPassword] = useState("");
+  const [error, setError] = useState("");
+
+  const reset = () => {
+    setMemberCode("");
+    setMember(null);
+    setScan("");
+    setBarcodes([]);
+    setLent(null);
+    setReturned([]);
+    setMessage("");
+  };
+
+  const handleIdentify = async () => {
+    if (!memberCode.trim()) return;
+    try {
+      setMember(await safeInvoke("self_checkout_identify", { memberCode }));
+      setMessage("");
+    } catch (err: any) {
+      setMessage(err.toString());
+      setMemberCode("");
+    }
+  };
+
+  const handleScan = async () => {
+    const code = scan.trim();
+    setScan("");
+    if (!code) return;
+    if (mode === "return") {
+      try {
+        const res = await safeInvoke("self_return", { barcode: code });
+        setReturned(prev => [res, ...prev]);
+        setMessage("");
+      } catch (err: any) {
+        setMessage(err.toString());
+      }
+    } else if (!barcodes.includes(code)) {
+      if (barcodes.length >= member.max_books) {
+        setMessage(`Maksimal ${member.max_books} buku sekali pinjam`);
+        return;
+      }
+      setBarcodes([...barcodes, code]);
+    }
+  };
+
+  const handleCheckout = async () => {
+    try {
+      setLent(await safeInvoke("self_checkout", { memberCode: member.member_code, barcodes }));
+      setMessage("");
+    } catch (err: any) {
+      setMessage(err.toString());
+    }
+  };
+
+  const handleReceipt = async () => {
+    const loanIds = (lent || []).filter(i => i.loan_id).map(i => i.loan_id);
+    try {
+      await safeInvoke("self_checkout_receipt", { memberCode: member.member_code, loanIds });
+      reset();
+    } catch (err: any) {
+      setMessage(err.toString());
+    }
+  };
+
+  const handleExit = async (e: React.FormEvent) => {
+    e.preventDefault();
+    setError("");
+    try {
+      await safeInvoke("exit_kiosk", { username, password });
+    } catch (err: any) {
+      setError(err.toString());
+      setPassword("");
+    }
+  };
+
+  const formatDate = (d: string) => new Date(d).toLocaleDateString('id-ID');
+
+  return (
+    <div style={{ minHeight: '100vh', padding: '48px', backgroundColor: '#f8fafc' }}>
+      <header style={{ display: 'flex', justifyContent: 'space-between', alignItems: 'center', marginBottom: '32px' }}>
+        <div style={{ display: 'flex', alignItems: 'center', gap: '12px' }}>
+          <span className="material-symbols-outlined" style={{ fontSize: '2.5rem', color: '#137fec' }}>qr_code_scanner</span>
+          <div>
+            <h1 style={{ fontSize: '1.75rem', fontWeight: 800, color: '#1e293b' }}>Pinjam & Kembali Mandiri</h1>
+            <p style={{ color: '#64748b' }}>{mode === "checkout" ? "Pindai kartu anggota, lalu pindai buku" : "Pindai kode pada buku yang dikembalikan"}</p>
+          </div>
+        </div>
+        <div style={{ display: 'flex', gap: '8px', alignItems: 'center' }}>
+          <button className={mode === "checkout" ? "btn-primary" : "btn-white"} onClick={() => { reset(); setMode("checkout"); }}>Pinjam</button>
+          <button className={mode === "return" ? "btn-primary" : "btn-white"} onClick={() => { reset(); setMode("return"); }}>Kembali</button>
+          <button onClick={() => setShowExit(true)} style={{ background: 'none', border: 'none', color: '#cbd5e1', cursor: 'pointer' }}>
+            <span className="material-symbols-outlined">lock</span>
+          </button>
+        </div>
+      </header>
+
+      {message && <p style={{ color: '#dc2626', fontWeight: 600, marginBottom: '16px' }}>{message}</p>}
+
+      {mode === "checkout" && !member && (
+        <input
+          className="login-input"
+          style={{ width: '100%', fontSize: '1.25rem', padding: '16px 20px' }}
+          placeholder="Pindai kartu anggota"
+          value={memberCode}
+          onChange={(e) => setMemberCode(e.target.value)}
+          onKeyPress={(e) => e.key === 'Enter' && handleIdentify()}
+          autoFocus
+        />
+      )}
+
+      {mode === "checkout" && member && !member.eligible && (
+        <div style={{ backgroundColor: 'white', borderRadius: '16px', padding: '24px', border: '1px solid #fecaca' }}>
+          <h3 style={{ fontSize: '1.125rem', fontWeight: 700 }}>{member.name}, peminjaman belum dapat dilakukan</h3>
+          <ul style={{ margin: '12px 0', paddingLeft: '20px', color: '#dc2626' }}>
+            {member.reasons.map((r: any, i: number) => <li key={i}>{r.message}</li>)}
+          </ul>
+          <p style={{ color: '#64748b', marginBottom: '16px' }}>Silakan menghubungi petugas di meja layanan.</p>
+          <button className="btn-white" onClick={reset}>Selesai</button>
+        </div>
+      )}
+
+      {mode === "checkout" && member && member.eligible && !lent && (
+        <div style={{ display: 'flex', flexDirection: 'column', gap: '12px' }}>
+          <p style={{ fontWeight: 700, color: '#1e293b' }}>{member.name}{member.kelas ? ` · ${member.kelas}` : ''} — {barcodes.length} dari {member.max_books} buku</p>
+          <input
+            className="login-input"
+            style={{ fontSize: '1.25rem', padding: '16px 20px' }}
+            placeholder="Pindai buku"
+            value={scan}
+            onChange={(e) => setScan(e.target.value)}
+            onKeyPress={(e) => e.key === 'Enter' && handleScan()}
+            autoFocus
+          />
+          {barcodes.map(b => <div key={b} style={{ backgroundColor: 'white', borderRadius: '12px', padding: '12px 20px', border: '1px solid #e2e8f0' }}>{b}</div>)}
+          <div style={{ display: 'flex', gap: '8px' }}>
+            <button className="btn-white" onClick={reset}>Batal</button>
+            <button className="btn-primary" disabled={barcodes.length === 0} onClick={handleCheckout}>Pinjam {barcodes.length} Buku</button>
+          </div>
+        </div>
+      )}
+
+      {mode === "checkout" && lent && (
+        <div style={{ display: 'flex', flexDirection: 'column', gap: '12px' }}>
+          {lent.map(item => (
+            <div key={item.barcode} style={{ backgroundColor: 'white', borderRadius: '12px', padding: '12px 20px', border: '1px solid #e2e8f0' }}>
+              <p style={{ fontWeight: 700 }}>{item.book_title || item.barcode}</p>
+              {item.loan_id
+                ? <p style={{ color: '#16a34a' }}>Dipinjam, kembali {formatDate(item.due_date)}</p>
+                : <p style={{ color: '#dc2626' }}>{item.error}</p>}
+            </div>
+          ))}
+          <div style={{ display: 'flex', gap: '8px' }}>
+            <button className="btn-white" onClick={reset}>Selesai</button>
+            {lent.some(i => i.loan_id) && <button className="btn-primary" onClick={handleReceipt}>Cetak Struk</button>}
+          </div>
+        </div>
+      )}
+
+      {mode === "return" && (
+        <div style={{ display: 'flex', flexDirection: 'column', gap: '12px' }}>
+          <input
+            className="login-input"
+            style={{ fontSize: '1.25rem', padding: '16px 20px' }}
+            placeholder="Pindai buku"
+            value={scan}
+            onChange={(e) => setScan(e.target.value)}
+            onKeyPress={(e) => e.key === 'Enter' && handleScan()}
+            autoFocus
+          />
+          {returned.map(r => (
+            <div key={r.self_return_id} style={{ backgroundColor: 'white', borderRadius: '12px', padding: '12px 20px', border: '1px solid #e2e8f0' }}>
+              <p style={{ fontWeight: 700 }}>{r.book_title}</p>
+              <p style={{ color: '#16a34a' }}>Diterima, akan diperiksa petugas</p>
+              {r.late_fine > 0 && <p style={{ color: '#dc2626' }}>Denda keterlambatan Rp {r.late_fine.toLocaleString('id-ID')}</p>}
+            </div>
+          ))}
+        </div>
+      )}
+
+      {showExit && (
+        <div style={{ position: 'fixed', inset: 0, backgroundColor: 'rgba(15, 23, 42, 0.5)', display: 'flex', alignItems: 'center', justifyContent: 'center' }}>
+          <form onSubmit={handleExit} style={{ backgroundColor: 'white', borderRadius: '16px', padding: '32px', width: '360px', display: 'flex', flexDirection: 'column', gap: '12px' }}>
+            <h2 style={{ fontSize: '1.25rem', fontWeight: 700 }}>Keluar Mode Mandiri</h2>
+            <input className="login-input" placeholder="Username admin" value={username} onChange={e => setUsername(e.target.value)} required />
+            <input className="login-input" type="password" placeholder="Kata sandi" value={password} onChange={e => setPassword(e.target.value)} required />
+            {error && <p style={{ color: '#ef4444', fontSize: '0.875rem' }}>{error}</p>}
+            <div style={{ display: 'flex', gap: '8px', justifyContent: 'flex-end' }}>
+              <button type="button" className="btn-white" onClick={() => { setShowExit(false); setError(""); setPassword(""); }}>Batal</button>
+              <button type="submit" className="btn-primary">Keluar</button>
+            </div>
+          </form>
+        </div>
+      )}
+    </div>
+  );
+}
+
 function DashboardView({ setView }: { setView: (v: string) => void }) {
-  const [stats, setStats] = useState({ total_books: 0, total_members: 0, active_loans: 0, overdue_loans: 0, monthly_new_members: 0, total_loans_count: 0 });
+  const [stats, setStats] = useState({ total_books: 0, total_members: 0, active_loans: 0, overdue_loans: 0, monthly_new_members: 0, total_loans_count: 0, visitors_today: 0 });
   const [categories, setCategories] = useState<any[]>([]);
   const [topBooks, setTopBooks] = useState<any[]>([]);
   const [memberStats, setMemberStats] = useState<any[]>([]);
@@ -1530,7 +1902,7 @@ function DashboardView({ setView }: { setView: (v: string) => void }) {
         safeInvoke("get_most_borrowed_books"),
         safeInvoke("get_member_activity_stats")
       ]);
-      setStats(s || { total_books: 0, total_members: 0, active_loans: 0, overdue_loans: 0, monthly_new_members: 0, total_loans_count: 0 });
+      setStats(s || { total_books: 0, total_members: 0, active_loans: 0, overdue_loans: 0, monthly_new_members: 0, total_loans_count: 0, visitors_today: 0 });
       setCategories(cat || []);
       setTopBooks(books || []);
       setMemberStats(members || []);
@@ -1582,6 +1954,7 @@ function DashboardView({ setView }: { setView: (v: string) => void }) {
         ["Statistik Utama"],
         ["Total Koleksi Buku", stats.total_books],
         ["Pendaftar Bulan Ini", stats.monthly_new_members],
+        ["Pengunjung Hari Ini", stats.visitors_today],
         ["Peminjaman Aktif", stats.active_loans],
         ["Buku Terlambat", stats.overdue_loans],
         [],
@@ -1690,6 +2063,18 @@ function DashboardView({ setView }: { setView: (v: string) => void }) {
           </div>
         </div>
 
+        <div style={{ background: 'white', padding: '24px', borderRadius: '12px', border: '1px solid #e5e7eb', boxShadow: '0 1px 2px rgba(0,0,0,0.05)' }}>
+          <div style={{ display: 'flex', justifyContent: 'space-between', alignItems: 'center', marginBottom: '8px' }}>
+            <p style={{ fontSize: '14px', fontWeight: 500, color: '#617589', margin: 0 }}>Pengunjung Hari Ini</p>
+            <span className="material-symbols-outlined" style={{ backgroundColor: 'rgba(245, 158, 11, 0.1)', color: '#f59e0b', padding: '6px', borderRadius: '8px', fontSize: '18px' }}>how_to_reg</span>
+          </div>
+          <p style={{ fontSize: '24px', fontWeight: 700, margin: '8px 0', color: '#111418' }}>{(stats?.visitors_today || 0).toLocaleString()}</p>
+          <div style={{ display: 'flex', alignItems: 'center', gap: '4px', fontSize: '12px', fontWeight: 700, color: '#078838', margin: 0 }}>
+            <span className="material-symbols-outlined" style={{ fontSize: '14px' }}>menu_book</span>
+            Tercatat di buku tamu
+          </div>
+        </div>
+
         <div
           onClick={() => setView("loans")}
           style={{ background: 'white', padding: '24px', borderRadius: '12px', border: '1px solid #e5e7eb', boxShadow: '0 1px 2px rgba(0,0,0,0.05)', cursor: 'pointer', transition: 'all 0.2s' }}
@@ -3797,6 +4182,11 @@ function MembersView({ members, onRefresh }: { members: Member[], onRefresh: ()
                           statusBg = '#d1fae5';
                           statusColor = '#047857';
                           dotColor = '#10b981';
+                        } else if (item.status === 'lost') {
+                          statusLabel = 'Hilang';
+                          statusBg = '#f1f5f9';
+                          statusColor = '#475569';
+                          dotColor = '#64748b';
                         } else if (isOverdue) {
                           statusLabel = 'Terlambat';
                           statusBg = '#ffe4e6';
@@ -3999,8 +4389,19 @@ function LoansView({ onRefresh }: { onRefresh: () => void }) {
     try {
       const book = await safeInvoke("find_book_by_isbn", { isbn: bookCode }) as Book;
 
+      // Loan period follows the member's loan policy for this book
+      let days = loanDays;
+      if (selectedMember) {
+        try {
+          const policy = await safeInvoke("get_loan_policy", { memberId: selectedMember.id, bookId: book.id });
+          if (policy?.loan_days) days = policy.loan_days;
+        } catch (e) {
+          console.error("Failed to get loan policy", e);
+        }
+      }
+
       const dueDate = new Date();
-      dueDate.setDate(dueDate.getDate() + loanDays);
+      dueDate.setDate(dueDate.getDate() + days);
 
       setCart([...cart, { book, dueDate: dueDate.toISOString() }]);
       setBookCode("");
@@ -4332,7 +4733,7 @@ function SettingsView({ user, onProfileUpdate }: { user: User, onProfileUpdate:
 
   const handleSaveProfile = async () => {
     try {
-      const updatedUser = await safeInvoke('update_profile', { userId: user.id, name: profileName, avatar: profileAvatar });
+      const updatedUser = await safeInvoke('update_profile', { name: profileName, avatar: profileAvatar });
       onProfileUpdate(updatedUser);
       await showAlert('Profil berhasil diperbarui!', 'success');
     } catch (err) {
@@ -4365,7 +4766,7 @@ function SettingsView({ user, onProfileUpdate }: { user: User, onProfileUpdate:
       return;
     }
     try {
-      await safeInvoke('change_password', { userId: user.id, oldPassword, newPassword });
+      await safeInvoke('change_password', { oldPassword, newPassword });
       await showAlert('Kata sandi berhasil diubah!', 'success');
       setShowPasswordModal(false);
       setOldPassword('');